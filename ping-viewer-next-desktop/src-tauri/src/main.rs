@@ -1,8 +1,9 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use ping_viewer_next::{cli, device, logger, server};
+use ping_viewer_next::{cli, device, logger, server, settings};
 use tauri::Manager;
+use tracing::error;
 
 #[tokio::main]
 async fn main() {
@@ -10,7 +11,13 @@ async fn main() {
 
     logger::manager::init();
 
-    let (manager, handler) = device::manager::DeviceManager::new(10);
+    settings::manager::init();
+
+    let (mut manager, handler) = device::manager::DeviceManager::new(10);
+
+    if let Err(err) = manager.restore_devices().await {
+        error!("DeviceManager unable to restore devices from settings, details {err:?}");
+    }
 
     let (recordings_manager, recordings_manager_handler) =
         device::recording::RecordingManager::new(10, "recordings", handler.clone());
@@ -0,0 +1,59 @@
+use clap::Parser;
+use std::net::Ipv4Addr;
+use tracing::error;
+
+use ping_viewer_next::device::simulator::{
+    udp_emulator::{run_udp_emulator, UdpEmulatorConfig, DATA_PORT, DISCOVERY_PORT},
+    SimulationSettings,
+};
+
+/// Emulates a Ping360 Ethernet unit on the local network, for tests without hardware.
+#[derive(Parser, Debug)]
+#[command(version = env!("CARGO_PKG_VERSION"), author = env!("CARGO_PKG_AUTHORS"))]
+struct Args {
+    /// IP address reported on discovery replies.
+    #[arg(long, default_value = "127.0.0.1")]
+    ip: Ipv4Addr,
+
+    /// Port used to answer network discovery requests.
+    #[arg(long, default_value_t = DISCOVERY_PORT)]
+    discovery_port: u16,
+
+    /// Port used by the Ping protocol.
+    #[arg(long, default_value_t = DATA_PORT)]
+    data_port: u16,
+
+    /// Distance to the simulated walls, in millimeters.
+    #[arg(long, default_value = "8000")]
+    depth_mm: u32,
+
+    /// Maximum amplitude of the random noise added to every sample.
+    #[arg(long, default_value = "20")]
+    noise: u8,
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let args = Args::parse();
+
+    let config = UdpEmulatorConfig {
+        ip: args.ip,
+        discovery_port: args.discovery_port,
+        data_port: args.data_port,
+        settings: SimulationSettings {
+            depth_mm: args.depth_mm,
+            noise: args.noise,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    if let Err(err) = run_udp_emulator(config).await {
+        error!("Ping360 emulator stopped: {err}");
+        std::process::exit(1);
+    }
+}
@@ -1,7 +1,13 @@
 use clap;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use lazy_static::lazy_static;
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
+
+use crate::device::export::{ExportFormat, ExportSettings, SonarMounting};
+use crate::server::{auth::ApiToken, tls::TlsSettings};
+use crate::vehicle::{
+    DepthSettings, VehicleBridgeSettings, VehicleSource, ZenohMode, ZenohSettings,
+};
 
 #[derive(Parser, Debug)]
 #[command(version = env!("CARGO_PKG_VERSION"), author = env!("CARGO_PKG_AUTHORS"), about = env!("CARGO_PKG_DESCRIPTION"))]
@@ -18,6 +24,79 @@ struct Args {
     #[arg(long, value_name = "IP>:<PORT", default_value = "0.0.0.0:4936")]
     rest_server: String,
 
+    /// Serves HTTPS and secure websockets, with a self-signed certificate generated on first run unless --tls-cert and --tls-key are used.
+    #[arg(long)]
+    tls: bool,
+
+    /// PEM certificate chain used for HTTPS, turns on TLS.
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the --tls-cert certificate.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// API token required by the REST and websocket server, with read or admin scope, can be used multiple times.
+    #[arg(long, value_name = "TOKEN>:<SCOPE")]
+    api_token: Vec<ApiToken>,
+
+    /// Vehicle telemetry source: zenoh, none or a MAVLink address like udpin:0.0.0.0:14550, tcpout:127.0.0.1:5760 or serial:/dev/ttyACM0:115200.
+    #[arg(long, value_name = "SOURCE", default_value = "zenoh")]
+    vehicle_source: VehicleSource,
+
+    /// MAVLink system id of the vehicle, telemetry from any vehicle is accepted when not set.
+    #[arg(long, value_name = "ID")]
+    vehicle_system_id: Option<u8>,
+
+    /// Absolute pressure at the water surface used to compute the vehicle depth, in hectopascal.
+    #[arg(long, value_name = "HPA", default_value = "1013.25")]
+    vehicle_surface_pressure: f32,
+
+    /// Water density used to compute the vehicle depth, in kilograms per cubic meter.
+    #[arg(long, value_name = "KG_M3", default_value = "1025")]
+    vehicle_water_density: f32,
+
+    /// Vehicle poses further than this from a recorded sample are flagged as stale, in milliseconds.
+    #[arg(long, value_name = "MILLISECONDS", default_value = "1000")]
+    vehicle_pose_max_age_ms: u64,
+
+    /// Zenoh session mode: client, peer or router.
+    #[arg(long, value_name = "MODE", default_value = "client")]
+    zenoh_mode: ZenohMode,
+
+    /// Zenoh endpoints to connect to, comma separated.
+    #[arg(
+        long,
+        value_name = "ENDPOINTS",
+        value_delimiter = ',',
+        default_value = "tcp/127.0.0.1:7447"
+    )]
+    zenoh_endpoints: Vec<String>,
+
+    /// Zenoh key expression for ATTITUDE, {system_id} is replaced by the vehicle system id or **.
+    #[arg(
+        long,
+        value_name = "KEY_EXPR",
+        default_value = "mavlink/{system_id}/1/ATTITUDE"
+    )]
+    zenoh_attitude_topic: String,
+
+    /// Zenoh key expression for GLOBAL_POSITION_INT, {system_id} is replaced by the vehicle system id or **.
+    #[arg(
+        long,
+        value_name = "KEY_EXPR",
+        default_value = "mavlink/{system_id}/1/GLOBAL_POSITION_INT"
+    )]
+    zenoh_position_topic: String,
+
+    /// Zenoh key expression for the other vehicle messages, {system_id} is replaced by the vehicle system id or ** and {message} by the message name.
+    #[arg(
+        long,
+        value_name = "KEY_EXPR",
+        default_value = "mavlink/{system_id}/1/{message}"
+    )]
+    zenoh_topic: String,
+
     /// Turns all log categories up to Debug, for more information check RUST_LOG env variable.
     #[arg(short, long)]
     verbose: bool,
@@ -41,6 +120,73 @@ struct Args {
     /// Turns on the debug mode.
     #[arg(long, default_value = "false")]
     debug: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export a Ping360 recording as georeferenced points or as a mosaic image and exit.
+    Export(ExportArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    /// MCAP recording with Ping360 and vehicle data.
+    recording: PathBuf,
+
+    /// Output file, defaults to the recording path with the format extension.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Output format: csv, xyz or las for points, png (with a .pgw world file) or geotiff for a mosaic.
+    #[arg(long, default_value = "csv")]
+    format: ExportFormat,
+
+    /// Sonar distance towards the vehicle bow, in meters.
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    mount_forward: f64,
+
+    /// Sonar distance towards the vehicle starboard side, in meters.
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    mount_right: f64,
+
+    /// Sonar distance below the vehicle reference point, in meters.
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    mount_down: f64,
+
+    /// Rotation of the sonar head angle 0 from the vehicle bow, clockwise in degrees.
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    mount_yaw_offset: f64,
+
+    /// The sonar is mounted upside down.
+    #[arg(long)]
+    mount_inverted: bool,
+
+    /// Speed of sound in water, in meters per second.
+    #[arg(long, default_value = "1500")]
+    speed_of_sound: f64,
+
+    /// Samples with lower intensity are not exported.
+    #[arg(long, default_value = "0")]
+    min_intensity: u8,
+
+    /// Samples further than this from a vehicle pose are not exported, in milliseconds.
+    #[arg(long, default_value = "1000")]
+    max_pose_age_ms: u64,
+
+    /// Mosaic cell size, in meters.
+    #[arg(long, default_value = "0.1")]
+    cell_size: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportCommand {
+    pub recording: PathBuf,
+    pub output: PathBuf,
+    pub format: ExportFormat,
+    pub settings: ExportSettings,
 }
 
 #[derive(Debug)]
@@ -90,6 +236,11 @@ pub fn is_enable_auto_create() -> bool {
     MANAGER.clap_matches.enable_auto_create
 }
 
+// Check if the settings file should be deleted before starting
+pub fn is_reset() -> bool {
+    MANAGER.clap_matches.reset
+}
+
 pub fn log_path() -> String {
     let log_path =
         MANAGER.clap_matches.log_path.clone().expect(
@@ -106,11 +257,90 @@ pub fn server_address() -> String {
     MANAGER.clap_matches.rest_server.clone()
 }
 
+// Return the certificate used to serve HTTPS, plain HTTP is used when not set
+pub fn tls_settings() -> Option<TlsSettings> {
+    let args = &MANAGER.clap_matches;
+    match (&args.tls_cert, &args.tls_key) {
+        (Some(cert_path), Some(key_path)) => Some(TlsSettings {
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+            self_signed: false,
+        }),
+        _ if args.tls => Some(TlsSettings::self_signed(
+            &crate::logger::manager::get_app_home_dir().join("tls"),
+        )),
+        _ => None,
+    }
+}
+
+// Return the API tokens from the command line, authentication is disabled without tokens
+pub fn api_tokens() -> Vec<ApiToken> {
+    MANAGER.clap_matches.api_token.clone()
+}
+
 // Return the command line used to start this application
 pub fn command_line_string() -> String {
     std::env::args().collect::<Vec<String>>().join(" ")
 }
 
+// Return the Zenoh session settings, shared by the vehicle bridge and zenoh outputs
+pub fn zenoh_settings() -> ZenohSettings {
+    ZenohSettings {
+        mode: MANAGER.clap_matches.zenoh_mode,
+        endpoints: MANAGER.clap_matches.zenoh_endpoints.clone(),
+    }
+}
+
+// Return the maximum age of a vehicle pose before being flagged as stale
+pub fn vehicle_pose_max_age() -> std::time::Duration {
+    std::time::Duration::from_millis(MANAGER.clap_matches.vehicle_pose_max_age_ms)
+}
+
+// Return the source and filters used to read vehicle telemetry
+pub fn vehicle_bridge_settings() -> VehicleBridgeSettings {
+    VehicleBridgeSettings {
+        source: MANAGER.clap_matches.vehicle_source.clone(),
+        zenoh: zenoh_settings(),
+        system_id: MANAGER.clap_matches.vehicle_system_id,
+        attitude_topic: MANAGER.clap_matches.zenoh_attitude_topic.clone(),
+        position_topic: MANAGER.clap_matches.zenoh_position_topic.clone(),
+        topic: MANAGER.clap_matches.zenoh_topic.clone(),
+        depth: DepthSettings {
+            surface_pressure_hpa: MANAGER.clap_matches.vehicle_surface_pressure,
+            water_density_kg_m3: MANAGER.clap_matches.vehicle_water_density,
+        },
+    }
+}
+
+// Return the export subcommand arguments, when used the application exits after exporting
+pub fn export_command() -> Option<ExportCommand> {
+    let Some(Command::Export(args)) = &MANAGER.clap_matches.command else {
+        return None;
+    };
+
+    Some(ExportCommand {
+        recording: args.recording.clone(),
+        output: args
+            .output
+            .clone()
+            .unwrap_or_else(|| args.recording.with_extension(args.format.extension())),
+        format: args.format,
+        settings: ExportSettings {
+            mounting: SonarMounting {
+                forward_m: args.mount_forward,
+                right_m: args.mount_right,
+                down_m: args.mount_down,
+                yaw_offset_deg: args.mount_yaw_offset,
+                inverted: args.mount_inverted,
+            },
+            speed_of_sound: args.speed_of_sound,
+            min_intensity: args.min_intensity,
+            max_pose_age_ms: args.max_pose_age_ms,
+            cell_size_m: args.cell_size,
+        },
+    })
+}
+
 // Return a clone of current Args struct
 pub fn command_line() -> String {
     format!("{:#?}", MANAGER.clap_matches)
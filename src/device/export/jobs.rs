@@ -0,0 +1,210 @@
+use lazy_static::lazy_static;
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{Mutex, Once},
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::{
+    export_recording, mosaic::world_file_path, ExportFormat, ExportSettings, ExportSummary,
+};
+use crate::device::{manager::ManagerError, playback::recording_path};
+
+static EXPORTS_PATH: &str = "exports";
+// Finished and failed jobs over this are removed with their files, oldest first
+static MAX_EXPORT_JOBS: usize = 20;
+static REMOVE_PREVIOUS_EXPORTS: Once = Once::new();
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema)]
+pub struct ExportRequest {
+    /// Recording file name, inside the recordings folder.
+    pub file_name: String,
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub settings: ExportSettings,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub enum ExportJobState {
+    Running,
+    Finished(ExportSummary),
+    Failed(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct ExportJob {
+    pub id: Uuid,
+    pub request: ExportRequest,
+    /// Exported file name, inside the exports folder.
+    pub output_file: String,
+    pub state: ExportJobState,
+}
+
+lazy_static! {
+    // Ordered by start time
+    static ref JOBS: Mutex<VecDeque<ExportJob>> = Mutex::new(VecDeque::new());
+}
+
+fn set_state(id: Uuid, state: ExportJobState) {
+    if let Some(job) = JOBS.lock().unwrap().iter_mut().find(|job| job.id == id) {
+        job.state = state;
+    }
+}
+
+fn job_files(job: &ExportJob) -> Vec<PathBuf> {
+    let output = Path::new(EXPORTS_PATH).join(&job.output_file);
+    match job.request.format {
+        ExportFormat::Png => vec![world_file_path(&output), output],
+        _ => vec![output],
+    }
+}
+
+fn remove_job_files(job: &ExportJob) {
+    for file in job_files(job).iter().filter(|file| file.exists()) {
+        if let Err(err) = std::fs::remove_file(file) {
+            warn!("Failed to remove export {file:?}: {err}");
+        }
+    }
+}
+
+fn remove_old_jobs(jobs: &mut VecDeque<ExportJob>) {
+    while jobs.len() > MAX_EXPORT_JOBS {
+        let Some(index) = jobs
+            .iter()
+            .position(|job| job.state != ExportJobState::Running)
+        else {
+            break;
+        };
+        if let Some(job) = jobs.remove(index) {
+            remove_job_files(&job);
+        }
+    }
+}
+
+// Jobs are not kept across restarts, the exports they left can't be downloaded anymore
+fn remove_previous_exports() {
+    let Ok(entries) = std::fs::read_dir(EXPORTS_PATH) else {
+        return;
+    };
+
+    let removed = entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| match std::fs::remove_file(entry.path()) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Failed to remove previous export {:?}: {err}", entry.path());
+                false
+            }
+        })
+        .count();
+    if removed > 0 {
+        info!("Removed {removed} exports of a previous run");
+    }
+}
+
+// Start exporting a recording in the background, the job can be followed by its id
+pub fn start_export(request: ExportRequest) -> Result<ExportJob, ManagerError> {
+    let input = recording_path(&request.file_name)?;
+    if !input.is_file() {
+        return Err(ManagerError::Other(format!(
+            "export: Recording not found: {}",
+            request.file_name
+        )));
+    }
+    request.settings.validate()?;
+    REMOVE_PREVIOUS_EXPORTS.call_once(remove_previous_exports);
+
+    let id = Uuid::new_v4();
+    let stem = Path::new(&request.file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("recording");
+    let job = ExportJob {
+        id,
+        output_file: format!("{stem}_{id}.{}", request.format.extension()),
+        request,
+        state: ExportJobState::Running,
+    };
+    {
+        let mut jobs = JOBS.lock().unwrap();
+        jobs.push_back(job.clone());
+        remove_old_jobs(&mut jobs);
+    }
+
+    let output = Path::new(EXPORTS_PATH).join(&job.output_file);
+    let (format, settings) = (job.request.format, job.request.settings.clone());
+    // Large recordings take a while, keep the runtime workers free
+    tokio::task::spawn_blocking(move || {
+        let state = match export_recording(&input, &output, format, &settings) {
+            Ok(summary) => ExportJobState::Finished(summary),
+            Err(err) => {
+                error!("Export job {id} failed: {err:?}");
+                ExportJobState::Failed(format!("{err:?}"))
+            }
+        };
+        set_state(id, state);
+    });
+
+    Ok(job)
+}
+
+pub fn export_job(id: Uuid) -> Option<ExportJob> {
+    JOBS.lock()
+        .unwrap()
+        .iter()
+        .find(|job| job.id == id)
+        .cloned()
+}
+
+pub fn export_jobs() -> Vec<ExportJob> {
+    JOBS.lock().unwrap().iter().cloned().collect()
+}
+
+// Remove a job with its files, running jobs have to finish first
+pub fn delete_export(id: Uuid) -> Result<ExportJob, ManagerError> {
+    let mut jobs = JOBS.lock().unwrap();
+    let index = jobs
+        .iter()
+        .position(|job| job.id == id)
+        .ok_or_else(|| ManagerError::Other(format!("export: Job not found: {id}")))?;
+    if jobs[index].state == ExportJobState::Running {
+        return Err(ManagerError::Other(format!(
+            "export: Job {id} is still running"
+        )));
+    }
+
+    let job = jobs.remove(index).unwrap();
+    remove_job_files(&job);
+    Ok(job)
+}
+
+// Path of the exported file, only available once the job has finished
+pub fn export_output(id: Uuid) -> Result<PathBuf, ManagerError> {
+    let job = export_job(id)
+        .ok_or_else(|| ManagerError::Other(format!("export: Job not found: {id}")))?;
+
+    match job.state {
+        ExportJobState::Finished(_) => Ok(Path::new(EXPORTS_PATH).join(job.output_file)),
+        state => Err(ManagerError::Other(format!(
+            "export: Job {id} has no output, state: {state:?}"
+        ))),
+    }
+}
+
+// World file of a finished PNG mosaic job
+pub fn export_world_file(id: Uuid) -> Result<PathBuf, ManagerError> {
+    let output = export_output(id)?;
+    if export_job(id).is_some_and(|job| job.request.format != ExportFormat::Png) {
+        return Err(ManagerError::Other(format!(
+            "export: Job {id} is not a PNG mosaic, it has no world file"
+        )));
+    }
+
+    Ok(world_file_path(&output))
+}
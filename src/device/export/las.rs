@@ -0,0 +1,204 @@
+use chrono::Datelike;
+use std::io::{Error, ErrorKind, Result, Write};
+
+use super::GeoPoint;
+
+static HEADER_SIZE: u16 = 227;
+static VLR_HEADER_SIZE: u32 = 54;
+static POINT_RECORD_LENGTH: u16 = 20;
+// Coordinates are stored as integers, 1e-7 degrees is around one centimeter
+static DEGREES_SCALE: f64 = 1e-7;
+static ELEVATION_SCALE: f64 = 1e-3;
+// GeoKeyDirectoryTag: geographic model, WGS 84 (EPSG:4326), angular units in degrees
+static GEO_KEY_DIRECTORY: [u16; 16] = [
+    1, 1, 0, 3, // Header, version 1.1.0 with 3 keys
+    1024, 0, 1, 2, // GTModelTypeGeoKey: ModelTypeGeographic
+    2048, 0, 1, 4326, // GeographicTypeGeoKey: GCS_WGS_84
+    2054, 0, 1, 9102, // GeogAngularUnitsGeoKey: Angular_Degree
+];
+
+// Fixed size, null padded string fields
+fn write_text(output: &mut impl Write, text: &str, size: usize) -> Result<()> {
+    let mut field = vec![0u8; size];
+    let length = text.len().min(size);
+    field[..length].copy_from_slice(&text.as_bytes()[..length]);
+    output.write_all(&field)
+}
+
+/// Bounds and number of the points, the LAS header comes before them.
+#[derive(Clone, Debug)]
+pub struct LasBounds {
+    min: [f64; 3],
+    max: [f64; 3],
+    count: u32,
+}
+
+impl Default for LasBounds {
+    fn default() -> Self {
+        Self {
+            min: [f64::MAX; 3],
+            max: [f64::MIN; 3],
+            count: 0,
+        }
+    }
+}
+
+impl LasBounds {
+    pub fn add(&mut self, point: &GeoPoint) {
+        for (axis, value) in [point.longitude, point.latitude, point.elevation]
+            .into_iter()
+            .enumerate()
+        {
+            self.min[axis] = self.min[axis].min(value);
+            self.max[axis] = self.max[axis].max(value);
+        }
+        self.count += 1;
+    }
+}
+
+/// Writes a LAS 1.2 file with point data format 0, the intensity is scaled to 16 bits.
+pub struct LasWriter<W: Write> {
+    output: W,
+    scale: [f64; 3],
+    offset: [f64; 3],
+    remaining: u32,
+}
+
+impl<W: Write> LasWriter<W> {
+    // Write the header and projection, the points written after it must fit in the bounds
+    pub fn new(bounds: &LasBounds, mut output: W) -> Result<Self> {
+        let (min, max) = if bounds.count == 0 {
+            ([0.0; 3], [0.0; 3])
+        } else {
+            (bounds.min, bounds.max)
+        };
+
+        let scale = [DEGREES_SCALE, DEGREES_SCALE, ELEVATION_SCALE];
+        // Whole number offsets keep the scaled coordinates inside i32
+        let offset = min.map(f64::floor);
+        let vlr_size = GEO_KEY_DIRECTORY.len() as u32 * 2;
+        let point_offset = HEADER_SIZE as u32 + VLR_HEADER_SIZE + vlr_size;
+        let today = chrono::Utc::now().date_naive();
+
+        output.write_all(b"LASF")?;
+        output.write_all(&0u16.to_le_bytes())?; // File source id
+        output.write_all(&0u16.to_le_bytes())?; // Global encoding
+        output.write_all(&[0u8; 16])?; // Project GUID
+        output.write_all(&[1, 2])?; // Version 1.2
+        write_text(&mut output, "OTHER", 32)?;
+        write_text(
+            &mut output,
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+            32,
+        )?;
+        output.write_all(&(today.ordinal() as u16).to_le_bytes())?;
+        output.write_all(&(today.year() as u16).to_le_bytes())?;
+        output.write_all(&HEADER_SIZE.to_le_bytes())?;
+        output.write_all(&point_offset.to_le_bytes())?;
+        output.write_all(&1u32.to_le_bytes())?; // Number of variable length records
+        output.write_all(&[0])?; // Point data format
+        output.write_all(&POINT_RECORD_LENGTH.to_le_bytes())?;
+        output.write_all(&bounds.count.to_le_bytes())?;
+        // Every sample is a single return
+        output.write_all(&bounds.count.to_le_bytes())?;
+        output.write_all(&[0u8; 16])?;
+        for value in scale.iter().chain(offset.iter()) {
+            output.write_all(&value.to_le_bytes())?;
+        }
+        for axis in 0..3 {
+            output.write_all(&max[axis].to_le_bytes())?;
+            output.write_all(&min[axis].to_le_bytes())?;
+        }
+
+        output.write_all(&0u16.to_le_bytes())?; // Reserved
+        write_text(&mut output, "LASF_Projection", 16)?;
+        output.write_all(&34735u16.to_le_bytes())?; // GeoKeyDirectoryTag record
+        output.write_all(&(vlr_size as u16).to_le_bytes())?;
+        write_text(&mut output, "GeoKeyDirectoryTag", 32)?;
+        for key in GEO_KEY_DIRECTORY {
+            output.write_all(&key.to_le_bytes())?;
+        }
+
+        Ok(Self {
+            output,
+            scale,
+            offset,
+            remaining: bounds.count,
+        })
+    }
+
+    pub fn write_point(&mut self, point: &GeoPoint) -> Result<()> {
+        // The header already has the number of points
+        if self.remaining == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "More points than counted in the LAS header",
+            ));
+        }
+        self.remaining -= 1;
+
+        for (axis, value) in [point.longitude, point.latitude, point.elevation]
+            .into_iter()
+            .enumerate()
+        {
+            let scaled = ((value - self.offset[axis]) / self.scale[axis]).round() as i32;
+            self.output.write_all(&scaled.to_le_bytes())?;
+        }
+        self.output
+            .write_all(&(point.intensity as u16 * 257).to_le_bytes())?;
+        // Return number 1 of 1
+        self.output.write_all(&[0b0000_1001])?;
+        self.output.write_all(&[0])?; // Classification: created, never classified
+        self.output.write_all(&[0])?; // Scan angle rank
+        self.output.write_all(&[0])?; // User data
+        self.output.write_all(&0u16.to_le_bytes())?; // Point source id
+        Ok(())
+    }
+}
+
+// Write the points as a LAS 1.2 file, when they are already in memory
+pub fn write_las(points: &[GeoPoint], output: &mut impl Write) -> Result<()> {
+    let mut bounds = LasBounds::default();
+    points.iter().for_each(|point| bounds.add(point));
+
+    let mut writer = LasWriter::new(&bounds, output)?;
+    points
+        .iter()
+        .try_for_each(|point| writer.write_point(point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn las_header_and_points() {
+        let point = |longitude: f64, intensity| GeoPoint {
+            time_ms: 0,
+            latitude: -27.5,
+            longitude,
+            elevation: -3.25,
+            intensity,
+            range_m: 1.0,
+            bearing_deg: 0.0,
+        };
+        let mut file = Vec::new();
+        write_las(&[point(-48.5, 255), point(-48.4, 1)], &mut file).unwrap();
+
+        let point_offset = u32::from_le_bytes(file[96..100].try_into().unwrap()) as usize;
+        assert_eq!(&file[0..4], b"LASF");
+        assert_eq!(u16::from_le_bytes([file[94], file[95]]), HEADER_SIZE);
+        assert_eq!(u32::from_le_bytes(file[107..111].try_into().unwrap()), 2);
+        assert_eq!(file.len(), point_offset + 2 * POINT_RECORD_LENGTH as usize);
+
+        let read_f64 = |at: usize| f64::from_le_bytes(file[at..at + 8].try_into().unwrap());
+        // Max and min X
+        assert_eq!(read_f64(179), -48.4);
+        assert_eq!(read_f64(187), -48.5);
+
+        let record = &file[point_offset..point_offset + POINT_RECORD_LENGTH as usize];
+        let x = i32::from_le_bytes(record[0..4].try_into().unwrap());
+        assert!((x as f64 * DEGREES_SCALE + read_f64(155) + 48.5).abs() < 1e-7);
+        assert_eq!(u16::from_le_bytes([record[12], record[13]]), u16::MAX);
+    }
+}
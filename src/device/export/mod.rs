@@ -0,0 +1,600 @@
+use bluerobotics_ping::ping360::AutoDeviceDataStruct;
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tracing::{debug, info};
+
+use memmap2::Mmap;
+
+use crate::{
+    device::{manager::ManagerError, recording::map_recording},
+    vehicle::{PoseHistory, VehicleData, VehicleDataTimestamps},
+};
+
+/// The `jobs` module runs exports in background tasks and keeps their status for the REST API.
+pub mod jobs;
+
+/// The `las` module writes points as LAS 1.2 files, using point data format 0.
+pub mod las;
+
+/// The `mosaic` module rasterizes points into a grid, averaging the intensity of each cell,
+/// and writes it as a PNG with a world file or as a GeoTIFF.
+pub mod mosaic;
+
+/// The `png` module encodes 8 bits gray and alpha images, shared by mosaics and sonar images.
+pub mod png;
+
+// Mean Earth radius, the local flat Earth approximation is good enough for sonar ranges
+static EARTH_RADIUS_M: f64 = 6_371_000.0;
+// Ping360 sample period unit, in seconds
+static SAMPLE_PERIOD_TICK_S: f64 = 25e-9;
+static GRADIANS_TO_DEGREES: f64 = 360.0 / 400.0;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub enum ExportFormat {
+    /// Comma separated values with a header, one line per point.
+    #[default]
+    Csv,
+    /// Space separated longitude, latitude, elevation and intensity.
+    Xyz,
+    /// LAS 1.2 point cloud, coordinates in WGS 84 degrees.
+    Las,
+    /// Mosaic image with transparent empty cells, georeferenced by a world file next to it.
+    Png,
+    /// Mosaic image with GeoTIFF tags, in WGS 84 degrees.
+    GeoTiff,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xyz => "xyz",
+            Self::Las => "las",
+            Self::Png => "png",
+            Self::GeoTiff => "tif",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "xyz" => Ok(Self::Xyz),
+            "las" => Ok(Self::Las),
+            "png" => Ok(Self::Png),
+            "geotiff" | "tif" | "tiff" => Ok(Self::GeoTiff),
+            _ => Err(format!(
+                "Unknown export format: {format}, expected csv, xyz, las, png or geotiff"
+            )),
+        }
+    }
+}
+
+/// Position and orientation of the sonar head relative to the vehicle reference point.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+#[serde(default)]
+pub struct SonarMounting {
+    /// Distance towards the vehicle bow, in meters.
+    pub forward_m: f64,
+    /// Distance towards the vehicle starboard side, in meters.
+    pub right_m: f64,
+    /// Distance below the vehicle reference point, in meters.
+    pub down_m: f64,
+    /// Rotation of the head angle 0 from the vehicle bow, clockwise in degrees.
+    pub yaw_offset_deg: f64,
+    /// The sonar is mounted upside down, head angles turn counterclockwise.
+    pub inverted: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+#[serde(default)]
+pub struct ExportSettings {
+    pub mounting: SonarMounting,
+    /// Speed of sound in water, in meters per second.
+    pub speed_of_sound: f64,
+    /// Samples with lower intensity are not exported.
+    pub min_intensity: u8,
+    /// Samples further than this from a vehicle pose are not exported, in milliseconds.
+    pub max_pose_age_ms: u64,
+    /// Mosaic cell size, in meters.
+    pub cell_size_m: f64,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            mounting: SonarMounting::default(),
+            speed_of_sound: 1500.0,
+            min_intensity: 0,
+            max_pose_age_ms: 1000,
+            cell_size_m: 0.1,
+        }
+    }
+}
+
+impl ExportSettings {
+    pub fn validate(&self) -> Result<(), ManagerError> {
+        if !(self.speed_of_sound.is_finite() && self.speed_of_sound > 0.0) {
+            return Err(ManagerError::Other(format!(
+                "export: Invalid speed of sound: {}",
+                self.speed_of_sound
+            )));
+        }
+
+        if !(self.cell_size_m.is_finite() && self.cell_size_m > 0.0) {
+            return Err(ManagerError::Other(format!(
+                "export: Invalid mosaic cell size: {}",
+                self.cell_size_m
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeoPoint {
+    /// Unix time in milliseconds of the ping.
+    pub time_ms: u64,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Meters, positive above sea level.
+    pub elevation: f64,
+    pub intensity: u8,
+    pub range_m: f64,
+    /// Beam direction relative to true north, clockwise in degrees.
+    pub bearing_deg: f64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub struct ExportSummary {
+    pub pings: usize,
+    /// Pings without a usable vehicle position or heading nearby.
+    pub pings_without_pose: usize,
+    pub points: usize,
+}
+
+// Distance to the center of a sample, sample_period is in 25 ns ticks and covers the round trip
+pub fn sample_range(index: usize, sample_period: u16, speed_of_sound: f64) -> f64 {
+    (index as f64 + 0.5) * sample_period as f64 * SAMPLE_PERIOD_TICK_S * speed_of_sound / 2.0
+}
+
+// Vehicle heading in degrees, the attitude yaw is preferred over the VFR_HUD heading
+fn vehicle_heading(pose: &VehicleData) -> Option<f64> {
+    pose.yaw
+        .map(|yaw| (yaw as f64).to_degrees())
+        .or(pose.heading.map(|heading| heading as f64))
+}
+
+// Project a sample of a ping into geographic coordinates, returns None without position or heading
+pub fn project_sample(
+    pose: &VehicleData,
+    mounting: &SonarMounting,
+    angle_gradians: u16,
+    range_m: f64,
+) -> Option<(f64, f64, f64, f64)> {
+    let (latitude, longitude) = (pose.lat?, pose.lon?);
+    let heading = vehicle_heading(pose)?;
+
+    let head_angle = angle_gradians as f64 * GRADIANS_TO_DEGREES;
+    let head_angle = if mounting.inverted {
+        -head_angle
+    } else {
+        head_angle
+    };
+    let relative_bearing = (head_angle + mounting.yaw_offset_deg).to_radians();
+
+    // Vehicle frame, x towards the bow and y towards starboard
+    let forward = mounting.forward_m + range_m * relative_bearing.cos();
+    let right = mounting.right_m + range_m * relative_bearing.sin();
+
+    let (sin_heading, cos_heading) = heading.to_radians().sin_cos();
+    let north = forward * cos_heading - right * sin_heading;
+    let east = forward * sin_heading + right * cos_heading;
+
+    let latitude_point = latitude + (north / EARTH_RADIUS_M).to_degrees();
+    let longitude_point =
+        longitude + (east / (EARTH_RADIUS_M * latitude.to_radians().cos())).to_degrees();
+
+    // Depth from pressure is more reliable underwater than the GPS altitude
+    let elevation = match (pose.depth, pose.alt) {
+        (Some(depth), _) => -(depth as f64) - mounting.down_m,
+        (None, Some(alt)) => alt - mounting.down_m,
+        (None, None) => -mounting.down_m,
+    };
+
+    let bearing = (heading + relative_bearing.to_degrees()).rem_euclid(360.0);
+
+    Some((latitude_point, longitude_point, elevation, bearing))
+}
+
+// Georeferenced points of a single ping, samples below min_intensity are skipped
+pub fn ping_points(
+    time_ms: u64,
+    ping: &AutoDeviceDataStruct,
+    pose: &VehicleData,
+    settings: &ExportSettings,
+) -> Option<Vec<GeoPoint>> {
+    // Any sample can be used to check if the pose is usable
+    project_sample(pose, &settings.mounting, ping.angle, 0.0)?;
+
+    Some(
+        ping.data
+            .iter()
+            .enumerate()
+            .filter(|(_, intensity)| **intensity >= settings.min_intensity)
+            .filter_map(|(index, intensity)| {
+                let range_m = sample_range(index, ping.sample_period, settings.speed_of_sound);
+                let (latitude, longitude, elevation, bearing_deg) =
+                    project_sample(pose, &settings.mounting, ping.angle, range_m)?;
+                Some(GeoPoint {
+                    time_ms,
+                    latitude,
+                    longitude,
+                    elevation,
+                    intensity: *intensity,
+                    range_m,
+                    bearing_deg,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Ping360 pings of a recording with the vehicle poses recorded alongside them.
+pub struct RecordingPings {
+    input: PathBuf,
+    // Only the poses are kept in memory, pings are read from the mapped file when visited
+    data: Mmap,
+    pings: usize,
+    history: PoseHistory,
+}
+
+impl RecordingPings {
+    // Read the recorded vehicle poses of a MCAP file created by RecordingManager
+    pub fn load(input: &Path, settings: &ExportSettings) -> Result<Self, ManagerError> {
+        settings.validate()?;
+
+        let data = map_recording(input)?;
+
+        let mut pings = 0;
+        let mut poses = Vec::new();
+        for_each_message(input, &data, |topic, time_ms, data| {
+            if topic.ends_with("/Ping360") {
+                pings += 1;
+            } else if topic.ends_with("/VehicleData") {
+                // Older recordings may have poses with missing fields, those are ignored
+                match serde_json::from_slice::<VehicleData>(data) {
+                    Ok(pose) => poses.push((time_ms, pose)),
+                    Err(err) => debug!("export: Ignoring vehicle data on {topic}: {err}"),
+                }
+            }
+            Ok(())
+        })?;
+
+        if pings == 0 {
+            return Err(ManagerError::Other(format!(
+                "export: Recording {input:?} has no Ping360 messages"
+            )));
+        }
+
+        // Poses are indexed by the recording time, the same clock used by the pings
+        poses.sort_by_key(|(time_ms, _)| *time_ms);
+        let mut history = PoseHistory::new(
+            Duration::from_millis(u64::MAX),
+            Duration::from_millis(settings.max_pose_age_ms),
+        );
+        for (time_ms, pose) in poses {
+            history.push(recorded_pose(time_ms, pose));
+        }
+
+        Ok(Self {
+            input: input.to_path_buf(),
+            data,
+            pings,
+            history,
+        })
+    }
+
+    // Visit the georeferenced points of every ping in the recording order, without keeping them
+    // in memory, stops at the first error of the visitor
+    pub fn for_each_point(
+        &self,
+        settings: &ExportSettings,
+        mut visit: impl FnMut(GeoPoint) -> Result<(), ManagerError>,
+    ) -> Result<ExportSummary, ManagerError> {
+        let mut summary = ExportSummary {
+            pings: self.pings,
+            ..Default::default()
+        };
+
+        for_each_message(&self.input, &self.data, |topic, time_ms, data| {
+            if !topic.ends_with("/Ping360") {
+                return Ok(());
+            }
+
+            let ping: AutoDeviceDataStruct = serde_json::from_slice(data).map_err(|err| {
+                ManagerError::Other(format!("export: Invalid Ping360 message on {topic}: {err}"))
+            })?;
+            let ping_points = self
+                .history
+                .pose_at(time_ms)
+                .filter(|pose| !pose.stale)
+                .and_then(|pose| ping_points(time_ms, &ping, &pose, settings));
+
+            match ping_points {
+                Some(ping_points) => {
+                    summary.points += ping_points.len();
+                    ping_points.into_iter().try_for_each(&mut visit)
+                }
+                None => {
+                    summary.pings_without_pose += 1;
+                    Ok(())
+                }
+            }
+        })?;
+
+        Ok(summary)
+    }
+}
+
+// Visit the topic, log time in milliseconds and data of every message of a recording
+fn for_each_message(
+    input: &Path,
+    data: &[u8],
+    mut visit: impl FnMut(&str, u64, &[u8]) -> Result<(), ManagerError>,
+) -> Result<(), ManagerError> {
+    let parse_error = |err: mcap::McapError| {
+        ManagerError::Other(format!(
+            "export: Failed to parse recording {input:?}: {err}"
+        ))
+    };
+
+    for message in mcap::MessageStream::new(data).map_err(parse_error)? {
+        let message = message.map_err(parse_error)?;
+        visit(
+            &message.channel.topic,
+            message.log_time / 1_000_000,
+            &message.data,
+        )?;
+    }
+
+    Ok(())
+}
+
+// Each group of a recorded pose has the time of its value, stale groups are left out so the
+// history flags them again, older recordings only have the log time of the whole pose
+fn recorded_pose(time_ms: u64, mut pose: VehicleData) -> VehicleData {
+    if pose.timestamps == VehicleDataTimestamps::default() {
+        pose = pose.measured_at(time_ms);
+    }
+    for group in std::mem::take(&mut pose.stale_groups) {
+        pose.timestamps.clear(group);
+    }
+    pose.timestamp_ms = Some(time_ms);
+    pose
+}
+
+fn write_text_header(format: ExportFormat, output: &mut impl Write) -> std::io::Result<()> {
+    if format == ExportFormat::Csv {
+        writeln!(
+            output,
+            "time_ms,latitude,longitude,elevation,intensity,range_m,bearing_deg"
+        )?;
+    }
+    Ok(())
+}
+
+fn write_text_point(
+    point: &GeoPoint,
+    format: ExportFormat,
+    output: &mut impl Write,
+) -> std::io::Result<()> {
+    match format {
+        ExportFormat::Csv => writeln!(
+            output,
+            "{},{:.8},{:.8},{:.3},{},{:.3},{:.2}",
+            point.time_ms,
+            point.latitude,
+            point.longitude,
+            point.elevation,
+            point.intensity,
+            point.range_m,
+            point.bearing_deg
+        ),
+        _ => writeln!(
+            output,
+            "{:.8} {:.8} {:.3} {}",
+            point.longitude, point.latitude, point.elevation, point.intensity
+        ),
+    }
+}
+
+pub(crate) fn create_output(output: &Path) -> Result<BufWriter<File>, ManagerError> {
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| {
+            ManagerError::Other(format!("export: Failed to create {parent:?}: {err}"))
+        })?;
+    }
+    let file = File::create(output).map_err(|err| {
+        ManagerError::Other(format!("export: Failed to create {output:?}: {err}"))
+    })?;
+    Ok(BufWriter::new(file))
+}
+
+// Export a Ping360 recording as georeferenced points or as a mosaic, in the selected format
+pub fn export_recording(
+    input: &Path,
+    output: &Path,
+    format: ExportFormat,
+    settings: &ExportSettings,
+) -> Result<ExportSummary, ManagerError> {
+    if matches!(format, ExportFormat::Png | ExportFormat::GeoTiff) {
+        return mosaic::export_mosaic(input, output, format, settings);
+    }
+
+    let recording = RecordingPings::load(input, settings)?;
+    let write_error =
+        |err| ManagerError::Other(format!("export: Failed to write {output:?}: {err}"));
+    let mut writer = create_output(output)?;
+
+    let summary = match format {
+        // The LAS header has the bounds and number of points, those are visited twice
+        ExportFormat::Las => {
+            let mut bounds = las::LasBounds::default();
+            recording.for_each_point(settings, |point| {
+                bounds.add(&point);
+                Ok(())
+            })?;
+
+            let mut las = las::LasWriter::new(&bounds, &mut writer).map_err(write_error)?;
+            recording.for_each_point(settings, |point| {
+                las.write_point(&point).map_err(write_error)
+            })?
+        }
+        _ => {
+            write_text_header(format, &mut writer).map_err(write_error)?;
+            recording.for_each_point(settings, |point| {
+                write_text_point(&point, format, &mut writer).map_err(write_error)
+            })?
+        }
+    };
+    writer.flush().map_err(write_error)?;
+
+    info!("Exported {input:?} to {output:?}: {summary:?}");
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pose(time_ms: u64, lat: f64, lon: f64, yaw_deg: f32) -> VehicleData {
+        VehicleData {
+            lat: Some(lat),
+            lon: Some(lon),
+            yaw: Some(yaw_deg.to_radians()),
+            depth: Some(2.0),
+            timestamp_ms: Some(time_ms),
+            ..Default::default()
+        }
+    }
+
+    fn ping(angle: u16) -> AutoDeviceDataStruct {
+        AutoDeviceDataStruct {
+            mode: 1,
+            gain_setting: 0,
+            angle,
+            transmit_duration: 10,
+            sample_period: 40000,
+            transmit_frequency: 750,
+            start_angle: 0,
+            stop_angle: 399,
+            num_steps: 1,
+            delay: 0,
+            number_of_samples: 4,
+            data_length: 4,
+            data: vec![0, 50, 100, 200],
+        }
+    }
+
+    #[test]
+    fn projects_samples_with_heading_and_mounting() {
+        let mounting = SonarMounting {
+            forward_m: 1.0,
+            down_m: 0.5,
+            ..Default::default()
+        };
+
+        // Vehicle heading east, head angle 0 looks east too
+        let (latitude, longitude, elevation, bearing) =
+            project_sample(&pose(0, 0.0, 0.0, 90.0), &mounting, 0, 9.0).unwrap();
+        assert!(latitude.abs() < 1e-9);
+        assert!((longitude - (10.0 / EARTH_RADIUS_M).to_degrees()).abs() < 1e-9);
+        assert!((elevation + 2.5).abs() < 1e-9);
+        assert!((bearing - 90.0).abs() < 1e-4);
+
+        // Head angle 100 gradians is starboard, south when heading east
+        let (latitude, longitude, _, bearing) = project_sample(
+            &pose(0, 0.0, 0.0, 90.0),
+            &SonarMounting::default(),
+            100,
+            10.0,
+        )
+        .unwrap();
+        assert!((latitude + (10.0 / EARTH_RADIUS_M).to_degrees()).abs() < 1e-9);
+        assert!(longitude.abs() < 1e-9);
+        assert!((bearing - 180.0).abs() < 1e-4);
+
+        let without_position = VehicleData {
+            lat: None,
+            ..pose(0, 0.0, 0.0, 0.0)
+        };
+        assert!(project_sample(&without_position, &mounting, 0, 1.0).is_none());
+    }
+
+    #[test]
+    fn export_recording_to_csv_and_las() {
+        let directory =
+            std::env::temp_dir().join(format!("ping-viewer-next-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("device.mcap");
+        let output = directory.join("device.csv");
+
+        let ctx = foxglove::Context::new();
+        let writer = ctx.mcap_writer().create_new_buffered_file(&input).unwrap();
+        let sonar = ctx
+            .channel_builder("device_0/Ping360")
+            .build::<AutoDeviceDataStruct>();
+        let vehicle = ctx
+            .channel_builder("device_0/VehicleData")
+            .build::<VehicleData>();
+        vehicle.log_with_time(&pose(0, -27.0, -48.0, 0.0), 1_000_000_000u64);
+        vehicle.log_with_time(&pose(0, -27.0, -48.0, 0.0), 2_000_000_000u64);
+        sonar.log_with_time(&ping(0), 1_500_000_000u64);
+        // Far from any pose
+        sonar.log_with_time(&ping(1), 9_000_000_000u64);
+        writer.close().unwrap();
+
+        let settings = ExportSettings {
+            min_intensity: 100,
+            ..Default::default()
+        };
+        let summary = export_recording(&input, &output, ExportFormat::Csv, &settings).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary {
+                pings: 2,
+                pings_without_pose: 1,
+                points: 2,
+            }
+        );
+
+        let csv = std::fs::read_to_string(&output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("time_ms,latitude"));
+        assert!(lines[1].starts_with("1500,-26.99998"));
+        assert!(lines[2].ends_with(",200,2.625,0.00"));
+
+        // The LAS header counts the points before they are streamed
+        let las_output = directory.join("device.las");
+        export_recording(&input, &las_output, ExportFormat::Las, &settings).unwrap();
+        let las = std::fs::read(&las_output).unwrap();
+        let point_offset = u32::from_le_bytes(las[96..100].try_into().unwrap()) as usize;
+        assert_eq!(u32::from_le_bytes(las[107..111].try_into().unwrap()), 2);
+        assert_eq!(las.len(), point_offset + 2 * 20);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+use super::{
+    create_output, png, ExportFormat, ExportSettings, ExportSummary, RecordingPings, EARTH_RADIUS_M,
+};
+use crate::device::manager::ManagerError;
+
+// Larger mosaics need a bigger cell size, keeps memory use and file size reasonable
+static MAX_MOSAIC_SIDE: u64 = 8192;
+// GeoKeyDirectoryTag: geographic model, pixels are areas, WGS 84 (EPSG:4326), angular units in degrees
+static GEO_KEY_DIRECTORY: [u16; 20] = [
+    1, 1, 0, 4, // Header, version 1.1.0 with 4 keys
+    1024, 0, 1, 2, // GTModelTypeGeoKey: ModelTypeGeographic
+    1025, 0, 1, 1, // GTRasterTypeGeoKey: RasterPixelIsArea
+    2048, 0, 1, 4326, // GeographicTypeGeoKey: GCS_WGS_84
+    2054, 0, 1, 9102, // GeogAngularUnitsGeoKey: Angular_Degree
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeoBounds {
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lon: f64,
+    pub max_lon: f64,
+}
+
+impl GeoBounds {
+    pub fn extend(bounds: Option<Self>, latitude: f64, longitude: f64) -> Option<Self> {
+        let bounds = bounds.unwrap_or(Self {
+            min_lat: latitude,
+            max_lat: latitude,
+            min_lon: longitude,
+            max_lon: longitude,
+        });
+
+        Some(Self {
+            min_lat: bounds.min_lat.min(latitude),
+            max_lat: bounds.max_lat.max(latitude),
+            min_lon: bounds.min_lon.min(longitude),
+            max_lon: bounds.max_lon.max(longitude),
+        })
+    }
+}
+
+/// Intensity average of the points inside each cell, rows go from north to south.
+pub struct MosaicGrid {
+    /// Longitude of the west edge, in degrees.
+    pub west: f64,
+    /// Latitude of the north edge, in degrees.
+    pub north: f64,
+    pub lon_step: f64,
+    pub lat_step: f64,
+    pub width: u32,
+    pub height: u32,
+    sum: Vec<u32>,
+    count: Vec<u32>,
+}
+
+impl MosaicGrid {
+    // Cells are square in meters at the center latitude of the bounds
+    pub fn new(bounds: &GeoBounds, cell_size_m: f64) -> Result<Self, ManagerError> {
+        let center_lat = (bounds.min_lat + bounds.max_lat) / 2.0;
+        let lat_step = (cell_size_m / EARTH_RADIUS_M).to_degrees();
+        let lon_step = lat_step / center_lat.to_radians().cos();
+
+        let width = ((bounds.max_lon - bounds.min_lon) / lon_step).floor() as u64 + 1;
+        let height = ((bounds.max_lat - bounds.min_lat) / lat_step).floor() as u64 + 1;
+        if width > MAX_MOSAIC_SIDE || height > MAX_MOSAIC_SIDE {
+            return Err(ManagerError::Other(format!(
+                "export: Mosaic would have {width}x{height} cells, more than {MAX_MOSAIC_SIDE} per side, increase the cell size"
+            )));
+        }
+
+        let cells = (width * height) as usize;
+        Ok(Self {
+            west: bounds.min_lon,
+            north: bounds.max_lat,
+            lon_step,
+            lat_step,
+            width: width as u32,
+            height: height as u32,
+            sum: vec![0; cells],
+            count: vec![0; cells],
+        })
+    }
+
+    pub fn add(&mut self, latitude: f64, longitude: f64, intensity: u8) {
+        let column = ((longitude - self.west) / self.lon_step).floor();
+        let row = ((self.north - latitude) / self.lat_step).floor();
+        if column < 0.0 || row < 0.0 || column >= self.width as f64 || row >= self.height as f64 {
+            return;
+        }
+
+        let index = row as usize * self.width as usize + column as usize;
+        self.sum[index] += intensity as u32;
+        self.count[index] += 1;
+    }
+
+    // Gray and alpha values of a row, cells without points are transparent
+    pub fn row(&self, row: u32) -> Vec<u8> {
+        let start = row as usize * self.width as usize;
+        (start..start + self.width as usize)
+            .flat_map(|index| match self.count[index] {
+                0 => [0, 0],
+                count => [(self.sum[index] / count) as u8, u8::MAX],
+            })
+            .collect()
+    }
+}
+
+pub fn write_png(grid: &MosaicGrid, output: &mut impl Write) -> io::Result<()> {
+    png::write_gray_alpha(
+        output,
+        grid.width,
+        grid.height,
+        (0..grid.height).map(|row| grid.row(row)),
+    )
+}
+
+// World file lines: cell size, rotations and the center of the upper left cell
+pub fn world_file(grid: &MosaicGrid) -> String {
+    format!(
+        "{:.12}\n0.0\n0.0\n{:.12}\n{:.12}\n{:.12}\n",
+        grid.lon_step,
+        -grid.lat_step,
+        grid.west + grid.lon_step / 2.0,
+        grid.north - grid.lat_step / 2.0
+    )
+}
+
+pub fn world_file_path(image: &Path) -> PathBuf {
+    image.with_extension("pgw")
+}
+
+fn ifd_entry(
+    output: &mut impl Write,
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: u32,
+) -> io::Result<()> {
+    output.write_all(&tag.to_le_bytes())?;
+    output.write_all(&kind.to_le_bytes())?;
+    output.write_all(&count.to_le_bytes())?;
+    output.write_all(&value.to_le_bytes())
+}
+
+// Single strip, uncompressed, 8 bits gray with alpha and the GeoTIFF tags after the image data
+pub fn write_geotiff(grid: &MosaicGrid, output: &mut impl Write) -> io::Result<()> {
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    const DOUBLE: u16 = 12;
+
+    let data_size = grid.width * grid.height * 2;
+    let pixel_scale_offset = 8 + data_size;
+    let tiepoint_offset = pixel_scale_offset + 3 * 8;
+    let geo_keys_offset = tiepoint_offset + 6 * 8;
+    let ifd_offset = geo_keys_offset + GEO_KEY_DIRECTORY.len() as u32 * 2;
+
+    output.write_all(b"II")?;
+    output.write_all(&42u16.to_le_bytes())?;
+    output.write_all(&ifd_offset.to_le_bytes())?;
+
+    for row in 0..grid.height {
+        output.write_all(&grid.row(row))?;
+    }
+
+    for value in [grid.lon_step, grid.lat_step, 0.0] {
+        output.write_all(&value.to_le_bytes())?;
+    }
+    // Raster point (0, 0) is the north west corner
+    for value in [0.0, 0.0, 0.0, grid.west, grid.north, 0.0] {
+        output.write_all(&value.to_le_bytes())?;
+    }
+    for key in GEO_KEY_DIRECTORY {
+        output.write_all(&key.to_le_bytes())?;
+    }
+
+    // Entries are sorted by tag, short values are stored in the lower bytes
+    output.write_all(&14u16.to_le_bytes())?;
+    ifd_entry(output, 256, LONG, 1, grid.width)?; // ImageWidth
+    ifd_entry(output, 257, LONG, 1, grid.height)?; // ImageLength
+    ifd_entry(output, 258, SHORT, 2, 8 | 8 << 16)?; // BitsPerSample
+    ifd_entry(output, 259, SHORT, 1, 1)?; // Compression: none
+    ifd_entry(output, 262, SHORT, 1, 1)?; // PhotometricInterpretation: black is zero
+    ifd_entry(output, 273, LONG, 1, 8)?; // StripOffsets
+    ifd_entry(output, 277, SHORT, 1, 2)?; // SamplesPerPixel
+    ifd_entry(output, 278, LONG, 1, grid.height)?; // RowsPerStrip
+    ifd_entry(output, 279, LONG, 1, data_size)?; // StripByteCounts
+    ifd_entry(output, 284, SHORT, 1, 1)?; // PlanarConfiguration: chunky
+    ifd_entry(output, 338, SHORT, 1, 2)?; // ExtraSamples: unassociated alpha
+    ifd_entry(output, 33550, DOUBLE, 3, pixel_scale_offset)?; // ModelPixelScaleTag
+    ifd_entry(output, 33922, DOUBLE, 6, tiepoint_offset)?; // ModelTiepointTag
+    ifd_entry(
+        output,
+        34735,
+        SHORT,
+        GEO_KEY_DIRECTORY.len() as u32,
+        geo_keys_offset,
+    )?; // GeoKeyDirectoryTag
+    output.write_all(&0u32.to_le_bytes())
+}
+
+// Rasterize a Ping360 recording, points are visited twice to avoid keeping them in memory
+pub fn export_mosaic(
+    input: &Path,
+    output: &Path,
+    format: ExportFormat,
+    settings: &ExportSettings,
+) -> Result<ExportSummary, ManagerError> {
+    let recording = RecordingPings::load(input, settings)?;
+
+    let mut bounds = None;
+    recording.for_each_point(settings, |point| {
+        bounds = GeoBounds::extend(bounds.take(), point.latitude, point.longitude);
+        Ok(())
+    })?;
+    let Some(bounds) = bounds else {
+        return Err(ManagerError::Other(format!(
+            "export: Recording {input:?} has no georeferenced samples"
+        )));
+    };
+
+    let mut grid = MosaicGrid::new(&bounds, settings.cell_size_m)?;
+    let summary = recording.for_each_point(settings, |point| {
+        grid.add(point.latitude, point.longitude, point.intensity);
+        Ok(())
+    })?;
+
+    let write_error =
+        |err| ManagerError::Other(format!("export: Failed to write {output:?}: {err}"));
+    let mut writer = create_output(output)?;
+    match format {
+        ExportFormat::GeoTiff => write_geotiff(&grid, &mut writer),
+        _ => write_png(&grid, &mut writer),
+    }
+    .and_then(|_| writer.flush())
+    .map_err(write_error)?;
+
+    if format == ExportFormat::Png {
+        std::fs::write(world_file_path(output), world_file(&grid)).map_err(write_error)?;
+    }
+
+    info!(
+        "Exported {input:?} mosaic with {}x{} cells to {output:?}: {summary:?}",
+        grid.width, grid.height
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn grid() -> MosaicGrid {
+        let bounds = GeoBounds {
+            min_lat: 0.0,
+            max_lat: (1.5 / EARTH_RADIUS_M).to_degrees(),
+            min_lon: 0.0,
+            max_lon: (2.5 / EARTH_RADIUS_M).to_degrees(),
+        };
+        let mut grid = MosaicGrid::new(&bounds, 1.0).unwrap();
+        // North west cell
+        grid.add(bounds.max_lat, 0.0, 100);
+        grid.add(bounds.max_lat, 0.0, 200);
+        // South east cell
+        grid.add(0.0, bounds.max_lon, 10);
+        grid
+    }
+
+    #[test]
+    fn png_mosaic_averages_cells() {
+        let grid = grid();
+        assert_eq!((grid.width, grid.height), (3, 2));
+
+        let mut file = Vec::new();
+        write_png(&grid, &mut file).unwrap();
+        assert_eq!(file[..8], png::PNG_SIGNATURE);
+        assert_eq!(&file[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(file[16..20].try_into().unwrap()), 3);
+        assert_eq!(u32::from_be_bytes(file[20..24].try_into().unwrap()), 2);
+
+        // Signature, IHDR with length, type and CRC, then IDAT length and type
+        let idat_length = u32::from_be_bytes(file[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&file[37..41], b"IDAT");
+        let mut pixels = Vec::new();
+        ZlibDecoder::new(&file[41..41 + idat_length])
+            .read_to_end(&mut pixels)
+            .unwrap();
+        assert_eq!(
+            pixels,
+            vec![0, 150, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10, 255]
+        );
+
+        let world = world_file(&grid);
+        let lines: Vec<f64> = world.lines().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(lines.len(), 6);
+        assert!((lines[0] - grid.lon_step).abs() < 1e-12);
+        assert!((lines[3] + grid.lat_step).abs() < 1e-12);
+        assert!((lines[5] - (grid.north - grid.lat_step / 2.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn geotiff_mosaic_layout() {
+        let grid = grid();
+        let mut file = Vec::new();
+        write_geotiff(&grid, &mut file).unwrap();
+
+        assert_eq!(&file[..4], b"II*\0");
+        let ifd_offset = u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize;
+        assert_eq!(file.len(), ifd_offset + 2 + 14 * 12 + 4);
+        assert_eq!(&file[8..10], &[150, 255]);
+
+        let entry = |index: usize| {
+            let at = ifd_offset + 2 + index * 12;
+            let tag = u16::from_le_bytes([file[at], file[at + 1]]);
+            let value = u32::from_le_bytes(file[at + 8..at + 12].try_into().unwrap());
+            (tag, value)
+        };
+        assert_eq!(entry(0), (256, 3));
+        assert_eq!(entry(1), (257, 2));
+
+        let (tag, tiepoint_offset) = entry(12);
+        assert_eq!(tag, 33922);
+        let west_at = tiepoint_offset as usize + 3 * 8;
+        let west = f64::from_le_bytes(file[west_at..west_at + 8].try_into().unwrap());
+        assert_eq!(west, grid.west);
+    }
+}
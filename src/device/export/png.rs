@@ -0,0 +1,41 @@
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::{self, Write};
+
+pub static PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn png_chunk(output: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+
+    output.write_all(&(data.len() as u32).to_be_bytes())?;
+    output.write_all(kind)?;
+    output.write_all(data)?;
+    output.write_all(&crc.finalize().to_be_bytes())
+}
+
+// 8 bits gray with alpha, not interlaced, each row has two bytes per pixel
+pub fn write_gray_alpha(
+    output: &mut impl Write,
+    width: u32,
+    height: u32,
+    rows: impl IntoIterator<Item = impl AsRef<[u8]>>,
+) -> io::Result<()> {
+    output.write_all(&PNG_SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 4, 0, 0, 0]);
+    png_chunk(output, b"IHDR", &header)?;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    for row in rows {
+        // Filter type none
+        encoder.write_all(&[0])?;
+        encoder.write_all(row.as_ref())?;
+    }
+    png_chunk(output, b"IDAT", &encoder.finish()?)?;
+
+    png_chunk(output, b"IEND", &[])
+}
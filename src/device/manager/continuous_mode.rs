@@ -2,12 +2,17 @@ use serde_json::json;
 use tracing::{debug, error, trace};
 use uuid::Uuid;
 
+use bluerobotics_ping::ping1d;
+
 use crate::device::{
-    devices::DeviceActorHandler,
+    devices::{DeviceActorHandler, Ping1DRequest, PingRequest},
     manager::{Answer, DeviceAnswer, DeviceManager, DeviceSelection, ManagerError},
 };
 
-use super::{DeviceProperties, ManagerActorHandler, Ping360Properties, SourceSelection};
+use super::{
+    ping360_scan::ScanScheduler, DeviceProperties, ManagerActorHandler, Ping1DContinuousMessage,
+    Ping360Properties, SourceSelection,
+};
 
 impl DeviceManager {
     // Call the helpers specifically for each device type
@@ -68,7 +73,10 @@ impl DeviceManager {
                 if supports_auto_transmit {
                     match self.get_device_source(device_id) {
                         Ok(source) => match source {
-                            super::SourceSelection::UdpStream(_) => {
+                            // Virtual devices replay or simulate the auto transmit messages
+                            super::SourceSelection::UdpStream(_)
+                            | super::SourceSelection::Playback(_)
+                            | super::SourceSelection::Simulated(_) => {
                                 Some(Self::start_ping360_firmware_mode(
                                     self.get_device_manager_handler(),
                                     handler,
@@ -109,22 +117,73 @@ impl DeviceManager {
         device_type: DeviceSelection,
     ) -> Result<(), ManagerError> {
         if device_type == DeviceSelection::Ping1D {
+            // Playback devices stream the recorded messages and refuse any command
+            if matches!(
+                self.get_device_source(device_id)?,
+                SourceSelection::Playback(_)
+            ) {
+                return Ok(());
+            }
+
             let handler_request = self.get_device_handler(device_id).await?;
             let handler = self.extract_handler(handler_request)?;
 
-            let id = <bluerobotics_ping::ping1d::ProfileStruct as bluerobotics_ping::message::MessageInfo>::id();
-            let _ = handler
-                .send(crate::device::devices::PingRequest::Ping1D(
-                    crate::device::devices::Ping1DRequest::ContinuousStart(
-                        bluerobotics_ping::ping1d::ContinuousStartStruct { id },
-                    ),
-                ))
+            let Some(DeviceProperties::Ping1D(properties)) =
+                self.get_device_properties(device_id).await?
+            else {
+                return Err(ManagerError::Other(format!(
+                    "No properties available for Ping1D device, device: {device_id}"
+                )));
+            };
+            let config = *properties
+                .config
+                .read()
+                .map_err(|err| ManagerError::Other(err.to_string()))?;
+            let continuous_config = properties
+                .continuous_mode_settings
+                .read()
+                .map_err(|err| ManagerError::Other(err.to_string()))?
+                .clone();
+
+            // The device may have been restarted since the configuration was applied
+            Self::apply_ping1d_config(&handler, &config).await?;
+            Self::start_ping1d_continuous_messages(&handler, &continuous_config.messages).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn start_ping1d_continuous_messages(
+        handler: &DeviceActorHandler,
+        messages: &[Ping1DContinuousMessage],
+    ) -> Result<(), ManagerError> {
+        for message in messages {
+            handler
+                .send(PingRequest::Ping1D(Ping1DRequest::ContinuousStart(
+                    ping1d::ContinuousStartStruct { id: message.id() },
+                )))
                 .await
                 .map_err(|err| {trace!("Something went wrong while executing continuous_mode_startup, details: {err:?}"); ManagerError::DeviceError(err)})?;
         }
+
         Ok(())
     }
 
+    pub async fn stop_ping1d_continuous_messages(
+        handler: &DeviceActorHandler,
+        messages: &[Ping1DContinuousMessage],
+    ) {
+        for message in messages {
+            if let Err(err) = handler
+                .send(PingRequest::Ping1D(Ping1DRequest::ContinuousStop(
+                    ping1d::ContinuousStopStruct { id: message.id() },
+                )))
+                .await
+            {
+                error!("Something went wrong while stopping Ping1D {message:?} stream, details: {err:?}");
+            }
+        }
+    }
+
     // Execute some especial commands required for device stop auto_send mode
     pub async fn continuous_mode_shutdown_routine(
         &mut self,
@@ -136,17 +195,9 @@ impl DeviceManager {
 
         match device_type {
             DeviceSelection::Ping1D => {
-                let id = <bluerobotics_ping::ping1d::ProfileStruct as bluerobotics_ping::message::MessageInfo>::id();
-                if let Err(err) = handler
-                    .send(crate::device::devices::PingRequest::Ping1D(
-                        crate::device::devices::Ping1DRequest::ContinuousStop(
-                            bluerobotics_ping::ping1d::ContinuousStopStruct { id },
-                        ),
-                    ))
-                    .await
-                {
-                    error!("Something went wrong while executing continuous_mode_shutdown_routine, details: {err:?}, device: {device_id}");
-                }
+                // Stop every message, the configuration may have changed since the start
+                Self::stop_ping1d_continuous_messages(&handler, &Ping1DContinuousMessage::ALL)
+                    .await;
             }
             DeviceSelection::Ping360 => {
                 if matches!(
@@ -171,50 +222,60 @@ impl DeviceManager {
         Ok(())
     }
 
-    // An inner helper focused on Ping1D, which uses Profile message to plot graphs
+    // An inner helper focused on Ping1D, which forwards the messages selected by Ping1DContinuousConfig
     pub fn ping1d_continuous_mode_helper(
         msg: bluerobotics_ping::message::ProtocolMessage,
         device_id: Uuid,
     ) {
-        if msg.message_id == <bluerobotics_ping::ping1d::ProfileStruct as bluerobotics_ping::message::MessageInfo>::id() {
-            if let Ok(bluerobotics_ping::Messages::Ping1D(bluerobotics_ping::ping1d::Messages::Profile(_answer))) = bluerobotics_ping::Messages::try_from(&msg) {
-                let answer = Answer::DeviceMessage(DeviceAnswer {
-                    answer: crate::device::devices::PingAnswer::PingMessage(
-                        match bluerobotics_ping::Messages::try_from(&msg){
-                            Ok(msg) => msg,
-                            Err(err) => {
-                                error!("Unexpected message during scan: {err:?}");
-                                return},
-                        }
-                    ),
-                    device_id,
-                });
-                crate::server::protocols::v1::websocket::send_to_websockets(json!(answer), Some(device_id));
-            }
+        if !Ping1DContinuousMessage::ALL
+            .iter()
+            .any(|message| message.id() == msg.message_id)
+        {
+            return;
         }
+
+        let msg = match bluerobotics_ping::Messages::try_from(&msg) {
+            Ok(msg @ bluerobotics_ping::Messages::Ping1D(_)) => msg,
+            Ok(msg) => {
+                error!("Unexpected message during continuous mode: {msg:?}");
+                return;
+            }
+            Err(err) => {
+                error!("Unexpected message during continuous mode: {err:?}");
+                return;
+            }
+        };
+
+        let answer = Answer::DeviceMessage(DeviceAnswer {
+            answer: crate::device::devices::PingAnswer::PingMessage(msg),
+            device_id,
+        });
+        crate::server::protocols::v1::websocket::stream_to_websockets(&answer, device_id);
     }
 
-    // An inner helper focused on Ping360 on AutoTransmit mode, which uses AutoDeviceData.
+    // An inner helper focused on Ping360 on AutoTransmit mode, which uses AutoDeviceData, returns the forwarded head angle.
     pub fn ping360_continuous_mode_helper_auto(
         msg: bluerobotics_ping::message::ProtocolMessage,
         device_id: Uuid,
-    ) {
+    ) -> Option<u16> {
         if msg.message_id == <bluerobotics_ping::ping360::AutoDeviceDataStruct as bluerobotics_ping::message::MessageInfo>::id() {
-                if let Ok(bluerobotics_ping::Messages::Ping360(bluerobotics_ping::ping360::Messages::AutoDeviceData(_answer))) = bluerobotics_ping::Messages::try_from(&msg) {
+                if let Ok(bluerobotics_ping::Messages::Ping360(bluerobotics_ping::ping360::Messages::AutoDeviceData(data))) = bluerobotics_ping::Messages::try_from(&msg) {
                     let answer = Answer::DeviceMessage(DeviceAnswer {
                         answer: crate::device::devices::PingAnswer::PingMessage(
                             match bluerobotics_ping::Messages::try_from(&msg){
                                 Ok(msg) => msg,
                                 Err(err) => {
                                     error!("Unexpected message during scan: {err:?}");
-                                    return},
+                                    return None},
                             }
                         ),
                         device_id,
                     });
-                    crate::server::protocols::v1::websocket::send_to_websockets(json!(answer), Some(device_id));
+                    crate::server::protocols::v1::websocket::stream_to_websockets(&answer, device_id);
+                    return Some(data.angle);
                 }
             }
+        None
     }
 
     // An inner helper focused on Ping360, which uses DeviceData message to plot graphs
@@ -223,7 +284,7 @@ impl DeviceManager {
             answer: crate::device::devices::PingAnswer::PingMessage(msg),
             device_id,
         });
-        crate::server::protocols::v1::websocket::send_to_websockets(json!(answer), Some(device_id));
+        crate::server::protocols::v1::websocket::stream_to_websockets(&answer, device_id);
     }
 
     // An inner helper that returns error to requester
@@ -234,7 +295,11 @@ impl DeviceManager {
         let error = ManagerError::DeviceError(crate::device::devices::DeviceError::PingError(
             bluerobotics_ping::error::PingError::TokioBroadcastError(error.to_string()),
         ));
-        crate::server::protocols::v1::websocket::send_to_websockets(json!(error), Some(device_id));
+        crate::server::protocols::v1::websocket::send_to_websockets(
+            json!(error),
+            crate::server::protocols::v1::subscription::Topic::Error,
+            Some(device_id),
+        );
     }
 
     fn start_ping360_firmware_mode(
@@ -247,6 +312,7 @@ impl DeviceManager {
         >,
     ) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
+            let mut scheduler = ScanScheduler::new(Default::default());
             'main: loop {
                 let config = properties.continuous_mode_settings.clone();
                 let initial_settings = match config.read() {
@@ -256,22 +322,31 @@ impl DeviceManager {
                         break;
                     }
                 };
+                scheduler.sync(&properties.scan_schedule);
+                let scan_settings = scheduler.apply(&initial_settings);
+                if let Some(profile) = scheduler.profile() {
+                    debug!(
+                        "Scanning Ping360 profile {}, device: {device_id}",
+                        profile.name
+                    );
+                }
+                let mut next_profile = false;
 
                 // Start auto-transmit mode
                 if let Err(err) = handler
                     .send(crate::device::devices::PingRequest::Ping360(
                         crate::device::devices::Ping360Request::AutoTransmit(
                             bluerobotics_ping::ping360::AutoTransmitStruct {
-                                mode: initial_settings.mode,
-                                gain_setting: initial_settings.gain_setting,
-                                transmit_duration: initial_settings.transmit_duration,
-                                sample_period: initial_settings.sample_period,
-                                transmit_frequency: initial_settings.transmit_frequency,
-                                number_of_samples: initial_settings.number_of_samples,
-                                start_angle: initial_settings.start_angle,
-                                stop_angle: initial_settings.stop_angle,
-                                num_steps: initial_settings.num_steps,
-                                delay: initial_settings.delay,
+                                mode: scan_settings.mode,
+                                gain_setting: scan_settings.gain_setting,
+                                transmit_duration: scan_settings.transmit_duration,
+                                sample_period: scan_settings.sample_period,
+                                transmit_frequency: scan_settings.transmit_frequency,
+                                number_of_samples: scan_settings.number_of_samples,
+                                start_angle: scan_settings.start_angle,
+                                stop_angle: scan_settings.stop_angle,
+                                num_steps: scan_settings.num_steps,
+                                delay: scan_settings.delay,
                             },
                         ),
                     ))
@@ -289,8 +364,11 @@ impl DeviceManager {
                             break 'main;
                         }
                     };
-                    if initial_settings != current_settings {
-                        debug!("Restarting firmware scanning routine for Ping360Config, device: {device_id}");
+                    if initial_settings != current_settings
+                        || scheduler.sync(&properties.scan_schedule)
+                        || next_profile
+                    {
+                        debug!("Restarting firmware scanning routine for Ping360Config or scan profile, device: {device_id}");
 
                         if properties.common.device_information.firmware_version_major >= 3
                             && properties.common.device_information.firmware_version_minor >= 3
@@ -320,7 +398,13 @@ impl DeviceManager {
                     }
 
                     match subscriber.recv().await {
-                        Ok(msg) => Self::ping360_continuous_mode_helper_auto(msg, device_id),
+                        Ok(msg) => {
+                            if let Some(angle) =
+                                Self::ping360_continuous_mode_helper_auto(msg, device_id)
+                            {
+                                next_profile = scheduler.ping(&scan_settings, angle);
+                            }
+                        }
                         Err(err @ tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
                             error!("Device subscriber channel issue {err:?}, device: {device_id}");
                             Self::handle_error_continuous_mode(err, device_id);
@@ -342,6 +426,7 @@ impl DeviceManager {
         properties: Ping360Properties,
     ) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
+            let mut scheduler = ScanScheduler::new(Default::default());
             loop {
                 let config = properties.continuous_mode_settings.clone();
                 let initial_settings = match config.read() {
@@ -351,11 +436,19 @@ impl DeviceManager {
                         break;
                     }
                 };
+                scheduler.sync(&properties.scan_schedule);
+                let scan_settings = scheduler.apply(&initial_settings);
+                if let Some(profile) = scheduler.profile() {
+                    debug!(
+                        "Scanning Ping360 profile {}, device: {device_id}",
+                        profile.name
+                    );
+                }
 
-                let mut angle = initial_settings.start_angle;
-                let step_size = initial_settings.num_steps as u16;
+                let mut angle = scan_settings.start_angle;
+                let step_size = scan_settings.num_steps as u16;
                 let is_full_circle =
-                    (initial_settings.stop_angle + 1) % 400 == initial_settings.start_angle % 400;
+                    (scan_settings.stop_angle + 1) % 400 == scan_settings.start_angle % 400;
                 let mut direction = 1i16;
 
                 loop {
@@ -366,7 +459,9 @@ impl DeviceManager {
                             break;
                         }
                     };
-                    if initial_settings != current_settings {
+                    if initial_settings != current_settings
+                        || scheduler.sync(&properties.scan_schedule)
+                    {
                         break;
                     }
 
@@ -374,12 +469,12 @@ impl DeviceManager {
                         .send(crate::device::devices::PingRequest::Ping360(
                             crate::device::devices::Ping360Request::Transducer(
                                 bluerobotics_ping::ping360::TransducerStruct {
-                                    mode: initial_settings.mode,
-                                    gain_setting: initial_settings.gain_setting,
-                                    transmit_duration: initial_settings.transmit_duration,
-                                    sample_period: initial_settings.sample_period,
-                                    transmit_frequency: initial_settings.transmit_frequency,
-                                    number_of_samples: initial_settings.number_of_samples,
+                                    mode: scan_settings.mode,
+                                    gain_setting: scan_settings.gain_setting,
+                                    transmit_duration: scan_settings.transmit_duration,
+                                    sample_period: scan_settings.sample_period,
+                                    transmit_frequency: scan_settings.transmit_frequency,
+                                    number_of_samples: scan_settings.number_of_samples,
                                     angle,
                                     transmit: 1,
                                     reserved: 0,
@@ -403,13 +498,17 @@ impl DeviceManager {
                         }
                     }
 
+                    if scheduler.ping(&scan_settings, angle) {
+                        break;
+                    }
+
                     angle = Self::calculate_next_angle(
                         angle,
                         step_size,
                         is_full_circle,
                         &mut direction,
-                        initial_settings.start_angle,
-                        initial_settings.stop_angle,
+                        scan_settings.start_angle,
+                        scan_settings.stop_angle,
                     );
                 }
             }
@@ -458,6 +557,14 @@ impl DeviceManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::device::{
+        manager::{
+            ModifyDevice, ModifyDeviceCommand, ModifyDeviceResult, Ping1DConfig,
+            Ping1DContinuousConfig, SourceSimulatedStruct,
+        },
+        simulator::{SimulatedDeviceType, SimulationSettings},
+    };
+    use bluerobotics_ping::message::MessageInfo;
 
     fn run_scan(start: u16, stop: u16, step: u16) -> Vec<u16> {
         let is_full_circle = (stop + 1) % 400 == start % 400;
@@ -513,4 +620,87 @@ mod tests {
         let set: std::collections::HashSet<_> = visited.into_iter().collect();
         assert_eq!(set.len(), 400);
     }
+
+    #[tokio::test]
+    async fn ping1d_continuous_config() {
+        let (mut manager, _handler) = DeviceManager::new(10);
+        let source = SourceSelection::Simulated(SourceSimulatedStruct {
+            name: "ping1d_continuous_config".to_string(),
+            device_type: SimulatedDeviceType::Ping1D,
+            settings: SimulationSettings::default(),
+        });
+
+        let Ok(Answer::DeviceInfo(info)) = manager.create(source, DeviceSelection::Ping1D).await
+        else {
+            panic!("Failed to create simulated Ping1D");
+        };
+        let device_id = info[0].id;
+
+        // Ping interval, range and gain of the continuous mode come from the Ping1DConfig
+        let device_config = Ping1DConfig {
+            ping_interval: 20,
+            mode_auto: 0,
+            scan_start: 0,
+            scan_length: 20000,
+            gain_setting: 2,
+            ..Default::default()
+        };
+        manager
+            .modify_device(ModifyDevice {
+                uuid: device_id,
+                modify: ModifyDeviceCommand::SetPing1DConfig(device_config),
+            })
+            .await
+            .unwrap();
+
+        let config = Ping1DContinuousConfig {
+            messages: vec![Ping1DContinuousMessage::DistanceSimple],
+        };
+        manager
+            .modify_device(ModifyDevice {
+                uuid: device_id,
+                modify: ModifyDeviceCommand::SetPing1DContinuousConfig(config.clone()),
+            })
+            .await
+            .unwrap();
+
+        let Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping1DContinuousConfig(stored))) =
+            manager.get_ping1d_continuous_config(device_id).await
+        else {
+            panic!("Failed to get Ping1DContinuousConfig");
+        };
+        assert_eq!(stored, config);
+
+        // Only the selected message should be streamed after the profile stream is stopped
+        let mut subscriber = manager.get_subscriber(device_id).await.unwrap();
+        let mut distance_simple = 0;
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while distance_simple < 5 {
+                let message = subscriber.recv().await.unwrap();
+                if message.message_id == ping1d::DistanceSimpleStruct::id() {
+                    distance_simple += 1;
+                } else if distance_simple > 0 {
+                    assert_ne!(message.message_id, ping1d::ProfileStruct::id());
+                }
+            }
+        })
+        .await
+        .expect("No DistanceSimple messages received");
+
+        // Configuration survives continuous mode restarts
+        manager.continuous_mode_off(device_id).await.unwrap();
+        manager.continuous_mode(device_id).await.unwrap();
+        let Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping1DContinuousConfig(stored))) =
+            manager.get_ping1d_continuous_config(device_id).await
+        else {
+            panic!("Failed to get Ping1DContinuousConfig");
+        };
+        assert_eq!(stored, config);
+        let Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping1DConfig(stored))) =
+            manager.get_ping1d_config(device_id).await
+        else {
+            panic!("Failed to get Ping1DConfig");
+        };
+        assert_eq!(stored, device_config);
+    }
 }
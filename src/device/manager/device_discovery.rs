@@ -1,4 +1,7 @@
-use std::{net::Ipv4Addr, time::Duration};
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    time::Duration,
+};
 
 use tokio::{io::AsyncWriteExt, task::JoinSet, time::timeout};
 use tokio_serial::{available_ports, SerialPort, SerialPortBuilderExt, SerialStream};
@@ -13,6 +16,9 @@ use std::collections::HashMap;
 #[cfg(feature = "blueos-extension")]
 use serde::{Deserialize, Serialize};
 
+// Ping360 Ethernet boards answer discovery requests and SetSS1IP commands on this port
+pub static DISCOVERY_PORT: u16 = 30303;
+
 #[derive(Debug, PartialEq)]
 pub struct DiscoveryResponse {
     pub device_name: String,
@@ -103,7 +109,8 @@ impl DiscoveryResponse {
     }
 }
 
-pub fn network_discovery() -> Option<Vec<SourceSelection>> {
+// Discovery requests are broadcast to the Ping360 discovery port unless another address is configured
+pub fn network_discovery(address: SocketAddrV4) -> Option<Vec<SourceSelection>> {
     let socket = match std::net::UdpSocket::bind("0.0.0.0:0") {
         Ok(s) => s,
         Err(err) => {
@@ -117,10 +124,9 @@ pub fn network_discovery() -> Option<Vec<SourceSelection>> {
         return None;
     }
 
-    let broadcast_addr = "255.255.255.255:30303";
     let discovery_message = "Discovery";
 
-    if let Err(err) = socket.send_to(discovery_message.as_bytes(), broadcast_addr) {
+    if let Err(err) = socket.send_to(discovery_message.as_bytes(), address) {
         warn!("auto_create: network: Failed to send discovery message: {err}");
         return None;
     }
@@ -534,6 +540,18 @@ mod tests {
         assert_eq!(parsed, Some(expected));
     }
 
+    #[test]
+    fn test_emulator_discovery_response_parsing() {
+        let response = crate::device::simulator::udp_emulator::discovery_response(
+            Ipv4Addr::new(127, 0, 0, 1),
+            "54-10-EC-00-00-01",
+        );
+
+        let parsed = DiscoveryResponse::from_response(&response).unwrap();
+        assert_eq!(parsed.ip_address, Ipv4Addr::LOCALHOST);
+        assert_eq!(parsed.device_name, "SONAR PING360");
+    }
+
     #[test]
     fn test_invalid_response_parsing() {
         let invalid_response = "INVALID RESPONSE FORMAT";
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use tracing::trace;
+use uuid::Uuid;
+
+use super::{
+    DeviceInfo, DeviceManager, DeviceProperties, DeviceStatus, ManagerActorHandler, ManagerError,
+    Request,
+};
+
+// Events kept for slow subscribers before they start to lag
+pub static DEVICE_EVENTS_SIZE: usize = 100;
+
+/// Changes of the devices, published as soon as the manager applies them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeviceEvent {
+    /// Found by the discovery, available to be created.
+    DeviceDiscovered(DeviceInfo),
+    DeviceCreated(DeviceInfo),
+    DeviceDeleted {
+        device_id: Uuid,
+    },
+    StatusChanged {
+        device_id: Uuid,
+        old: DeviceStatus,
+        new: DeviceStatus,
+    },
+    ContinuousModeStarted {
+        device_id: Uuid,
+    },
+    ContinuousModeStopped {
+        device_id: Uuid,
+    },
+    PropertiesUpdated {
+        device_id: Uuid,
+        properties: Option<DeviceProperties>,
+    },
+    /// A request to change a device failed.
+    Error {
+        device_id: Option<Uuid>,
+        error: ManagerError,
+    },
+}
+
+impl DeviceEvent {
+    // Event name used by Server-Sent Events
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DeviceDiscovered(_) => "DeviceDiscovered",
+            Self::DeviceCreated(_) => "DeviceCreated",
+            Self::DeviceDeleted { .. } => "DeviceDeleted",
+            Self::StatusChanged { .. } => "StatusChanged",
+            Self::ContinuousModeStarted { .. } => "ContinuousModeStarted",
+            Self::ContinuousModeStopped { .. } => "ContinuousModeStopped",
+            Self::PropertiesUpdated { .. } => "PropertiesUpdated",
+            Self::Error { .. } => "Error",
+        }
+    }
+}
+
+impl Request {
+    // Requests creating or changing devices, their failures are published as events
+    pub fn changes_devices(&self) -> bool {
+        matches!(self, Request::AutoCreate | Request::Create(_)) || self.write_target().is_some()
+    }
+}
+
+impl ManagerActorHandler {
+    pub fn subscribe_events(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn publish(&self, event: DeviceEvent) {
+        trace!("DeviceManager: Publishing event: {}", event.name());
+        // Nobody may be listening, the event is just dropped
+        let _ = self.events.send(event);
+    }
+}
+
+// Status and serialized properties of each device, compared to find what changed
+#[derive(Debug, Default)]
+pub struct DevicesSnapshot(HashMap<Uuid, (DeviceStatus, serde_json::Value)>);
+
+impl DeviceManager {
+    pub fn snapshot(&self) -> DevicesSnapshot {
+        DevicesSnapshot(
+            self.device
+                .iter()
+                .map(|(id, device)| {
+                    let properties = serde_json::to_value(&device.properties).unwrap_or_default();
+                    (*id, (device.status.clone(), properties))
+                })
+                .collect(),
+        )
+    }
+
+    pub fn events_since(&self, before: &DevicesSnapshot) -> Vec<DeviceEvent> {
+        let after = self.snapshot();
+        let mut events = Vec::new();
+
+        for (device_id, (status, properties)) in &after.0 {
+            let Some(device) = self.device.get(device_id) else {
+                continue;
+            };
+            let Some((old_status, old_properties)) = before.0.get(device_id) else {
+                events.push(match status {
+                    DeviceStatus::Available => DeviceEvent::DeviceDiscovered(device.info()),
+                    _ => DeviceEvent::DeviceCreated(device.info()),
+                });
+                if *status == DeviceStatus::ContinuousMode {
+                    events.push(DeviceEvent::ContinuousModeStarted {
+                        device_id: *device_id,
+                    });
+                }
+                continue;
+            };
+
+            if old_status != status {
+                // Discovered devices are created once they are started
+                if *old_status == DeviceStatus::Available {
+                    events.push(DeviceEvent::DeviceCreated(device.info()));
+                }
+                events.push(DeviceEvent::StatusChanged {
+                    device_id: *device_id,
+                    old: old_status.clone(),
+                    new: status.clone(),
+                });
+                if *status == DeviceStatus::ContinuousMode {
+                    events.push(DeviceEvent::ContinuousModeStarted {
+                        device_id: *device_id,
+                    });
+                } else if *old_status == DeviceStatus::ContinuousMode {
+                    events.push(DeviceEvent::ContinuousModeStopped {
+                        device_id: *device_id,
+                    });
+                }
+            }
+
+            if old_properties != properties {
+                events.push(DeviceEvent::PropertiesUpdated {
+                    device_id: *device_id,
+                    properties: device.properties.clone(),
+                });
+            }
+        }
+
+        events.extend(
+            before
+                .0
+                .keys()
+                .filter(|device_id| !after.0.contains_key(device_id))
+                .map(|device_id| DeviceEvent::DeviceDeleted {
+                    device_id: *device_id,
+                }),
+        );
+        events
+    }
+
+    pub fn publish_changes(&self, before: &DevicesSnapshot) {
+        for event in self.events_since(before) {
+            self.manager_handler.publish(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{
+        manager::{Answer, DeviceSelection, SourceSelection, SourceSimulatedStruct},
+        simulator::{SimulatedDeviceType, SimulationSettings},
+    };
+
+    #[tokio::test]
+    async fn changes_are_published_as_events() {
+        let (mut manager, handler) = DeviceManager::new(10);
+        let mut events = handler.subscribe_events();
+        let source = SourceSelection::Simulated(SourceSimulatedStruct {
+            name: "changes_are_published_as_events".to_string(),
+            device_type: SimulatedDeviceType::Ping360,
+            settings: SimulationSettings::default(),
+        });
+
+        let before = manager.snapshot();
+        let Ok(Answer::DeviceInfo(info)) = manager.create(source, DeviceSelection::Ping360).await
+        else {
+            panic!("Failed to create simulated Ping360");
+        };
+        let device_id = info[0].id;
+        manager.publish_changes(&before);
+        assert!(
+            matches!(events.try_recv(), Ok(DeviceEvent::DeviceCreated(info)) if info.id == device_id)
+        );
+
+        manager.device.get_mut(&device_id).unwrap().status = DeviceStatus::Running;
+        let before = manager.snapshot();
+        manager.device.get_mut(&device_id).unwrap().status = DeviceStatus::ContinuousMode;
+        let names: Vec<_> = manager
+            .events_since(&before)
+            .iter()
+            .map(DeviceEvent::name)
+            .collect();
+        assert_eq!(names, ["StatusChanged", "ContinuousModeStarted"]);
+
+        let before = manager.snapshot();
+        manager.device.remove(&device_id);
+        let events = manager.events_since(&before);
+        assert!(
+            matches!(events.as_slice(), [DeviceEvent::DeviceDeleted { device_id: id }] if *id == device_id)
+        );
+        assert!(manager.events_since(&manager.snapshot()).is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+use uuid::Uuid;
+
+use super::{Answer, DeviceManager, ManagerError, ModifyDeviceCommand, Request};
+
+static DEFAULT_LEASE_S: u64 = 30;
+static MAX_LEASE_S: u64 = 3600;
+
+/// Control of a device by a single client, other clients can only read until it expires or is released.
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct DeviceLease {
+    pub device_id: Uuid,
+    /// Milliseconds since the Unix epoch, the lease should be acquired again before it.
+    pub expires_at_ms: u64,
+}
+
+/// Lease returned only to the client that acquired it.
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct LeaseGrant {
+    pub lease: DeviceLease,
+    /// Secret required to change the device while the lease is held, sent as the X-Lease-Token header
+    /// or the lease_token websocket query.
+    pub token: String,
+}
+
+impl LeaseGrant {
+    fn held_by(&self, client: &LeaseClient) -> bool {
+        client.token.as_deref().is_some_and(|token| {
+            crate::server::auth::constant_time_eq(token.as_bytes(), self.token.as_bytes())
+        })
+    }
+}
+
+// Client that sent a request, with the token of the lease it holds on the target device, if any
+#[derive(Debug, Clone, Default)]
+pub struct LeaseClient {
+    pub token: Option<String>,
+}
+
+impl Answer {
+    // Answer broadcast to the other clients, lease tokens are only returned to their holder
+    pub fn shared(&self) -> Cow<'_, Answer> {
+        match self {
+            Answer::LeaseGranted(grant) => Cow::Owned(Answer::Lease(Some(grant.lease.clone()))),
+            answer => Cow::Borrowed(answer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct LeaseRequestStruct {
+    pub uuid: Uuid,
+    /// Lease duration in seconds, defaults to 30 and is limited to one hour.
+    #[serde(default)]
+    #[openapi(minimum = 1, maximum = 3600)]
+    pub duration_s: Option<u64>,
+    /// Take over the lease of another client.
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Request {
+    // Device changed by the request, raw device requests are included since they may change the device state
+    pub fn write_target(&self) -> Option<Uuid> {
+        match self {
+            Request::ModifyDevice(modify) => match modify.modify {
+                ModifyDeviceCommand::GetPing360Config
+                | ModifyDeviceCommand::GetPing360ScanSchedule
+                | ModifyDeviceCommand::GetPing1DContinuousConfig
+                | ModifyDeviceCommand::GetPing1DConfig => None,
+                _ => Some(modify.uuid),
+            },
+            Request::Ping(request) => Some(request.uuid),
+            Request::GetDeviceHandler(uuid)
+            | Request::Delete(uuid)
+            | Request::EnableContinuousMode(uuid)
+            | Request::DisableContinuousMode(uuid) => Some(**uuid),
+            Request::Playback(request) => Some(request.uuid),
+            Request::SetNmeaOutput(request) => Some(request.uuid),
+            Request::SetMavlinkOutput(request) => Some(request.uuid),
+            Request::SetSonarImage(request) => Some(request.uuid),
+            _ => None,
+        }
+    }
+}
+
+impl DeviceManager {
+    // Lease held by a client, expired leases are dropped
+    fn active_lease(&mut self, device_id: Uuid) -> Option<&LeaseGrant> {
+        if self
+            .leases
+            .get(&device_id)
+            .is_some_and(|grant| grant.lease.expires_at_ms <= now_ms())
+        {
+            info!("Lease expired, device: {device_id}");
+            self.leases.remove(&device_id);
+        }
+        self.leases.get(&device_id)
+    }
+
+    // Requests from clients can only change devices leased by nobody or with the lease token,
+    // internal requests have no client and are always allowed
+    pub fn check_lease(
+        &mut self,
+        request: &Request,
+        client: Option<&LeaseClient>,
+    ) -> Result<(), ManagerError> {
+        let (Some(client), Some(device_id)) = (client, request.write_target()) else {
+            return Ok(());
+        };
+
+        match self.active_lease(device_id) {
+            Some(grant) if !grant.held_by(client) => {
+                Err(ManagerError::DeviceLeased(grant.lease.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn acquire_lease(
+        &mut self,
+        request: LeaseRequestStruct,
+        client: Option<&LeaseClient>,
+    ) -> Result<Answer, ManagerError> {
+        self.check_device_uuid(request.uuid)?;
+        let Some(client) = client else {
+            return Err(ManagerError::Other(
+                "acquire_lease: Requests without a client can't hold leases".to_string(),
+            ));
+        };
+
+        let duration_s = request.duration_s.unwrap_or(DEFAULT_LEASE_S);
+        if !(1..=MAX_LEASE_S).contains(&duration_s) {
+            return Err(ManagerError::Other(format!(
+                "acquire_lease: Duration should be between 1 and {MAX_LEASE_S} seconds, got {duration_s}"
+            )));
+        }
+
+        // Renewals keep the token, new and taken over leases get a new one
+        let token = match self.active_lease(request.uuid) {
+            Some(grant) if grant.held_by(client) => grant.token.clone(),
+            Some(grant) if !request.force => {
+                return Err(ManagerError::DeviceLeased(grant.lease.clone()));
+            }
+            Some(_) => {
+                info!("Lease taken over, device: {}", request.uuid);
+                Uuid::new_v4().simple().to_string()
+            }
+            None => Uuid::new_v4().simple().to_string(),
+        };
+
+        let grant = LeaseGrant {
+            lease: DeviceLease {
+                device_id: request.uuid,
+                expires_at_ms: now_ms() + duration_s * 1000,
+            },
+            token,
+        };
+        self.leases.insert(request.uuid, grant.clone());
+        Ok(Answer::LeaseGranted(grant))
+    }
+
+    pub fn release_lease(
+        &mut self,
+        device_id: Uuid,
+        client: Option<&LeaseClient>,
+    ) -> Result<Answer, ManagerError> {
+        self.check_device_uuid(device_id)?;
+
+        if let Some(grant) = self.active_lease(device_id) {
+            if client.is_some_and(|client| !grant.held_by(client)) {
+                return Err(ManagerError::DeviceLeased(grant.lease.clone()));
+            }
+            info!("Lease released, device: {device_id}");
+            self.leases.remove(&device_id);
+        }
+        Ok(Answer::Lease(None))
+    }
+
+    pub fn get_lease(&mut self, device_id: Uuid) -> Result<Answer, ManagerError> {
+        self.check_device_uuid(device_id)?;
+        Ok(Answer::Lease(
+            self.active_lease(device_id)
+                .map(|grant| grant.lease.clone()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{
+        manager::{
+            DeviceSelection, ModifyDevice, SourceSelection, SourceSimulatedStruct, UuidWrapper,
+        },
+        simulator::{SimulatedDeviceType, SimulationSettings},
+    };
+
+    #[tokio::test]
+    async fn lease_blocks_writes_from_other_clients() {
+        let (mut manager, _handler) = DeviceManager::new(10);
+        let source = SourceSelection::Simulated(SourceSimulatedStruct {
+            name: "lease_blocks_writes_from_other_clients".to_string(),
+            device_type: SimulatedDeviceType::Ping360,
+            settings: SimulationSettings::default(),
+        });
+        let Ok(Answer::DeviceInfo(info)) = manager.create(source, DeviceSelection::Ping360).await
+        else {
+            panic!("Failed to create simulated Ping360");
+        };
+        let device_id = info[0].id;
+
+        let lease = |duration_s, force| LeaseRequestStruct {
+            uuid: device_id,
+            duration_s: Some(duration_s),
+            force,
+        };
+        let write = Request::DisableContinuousMode(UuidWrapper { uuid: device_id });
+        let read = Request::ModifyDevice(ModifyDevice {
+            uuid: device_id,
+            modify: ModifyDeviceCommand::GetPing360Config,
+        });
+
+        let client = |token: Option<&str>| LeaseClient {
+            token: token.map(str::to_string),
+        };
+        let acquire = |manager: &mut DeviceManager, force, token: Option<&str>| match manager
+            .acquire_lease(lease(30, force), Some(&client(token)))
+        {
+            Ok(Answer::LeaseGranted(grant)) => Ok(grant.token),
+            Ok(answer) => panic!("Unexpected answer: {answer:?}"),
+            Err(err) => Err(err),
+        };
+
+        let first = acquire(&mut manager, false, None).unwrap();
+        assert_eq!(acquire(&mut manager, false, Some(&first)).unwrap(), first);
+        assert!(manager
+            .check_lease(&write, Some(&client(Some(&first))))
+            .is_ok());
+        assert!(manager.check_lease(&write, None).is_ok());
+        assert!(manager.check_lease(&read, Some(&client(None))).is_ok());
+        // Neither the errors nor the shared answers carry the token
+        assert!(matches!(
+            manager.check_lease(&write, Some(&client(Some("guess")))),
+            Err(ManagerError::DeviceLeased(DeviceLease { device_id: id, .. })) if id == device_id
+        ));
+        assert!(manager.check_lease(&write, Some(&client(None))).is_err());
+        assert!(acquire(&mut manager, false, None).is_err());
+        assert!(manager
+            .release_lease(device_id, Some(&client(None)))
+            .is_err());
+        let granted = manager
+            .acquire_lease(lease(30, false), Some(&client(Some(&first))))
+            .unwrap();
+        assert!(matches!(
+            granted.shared().as_ref(),
+            Answer::Lease(Some(DeviceLease { device_id: id, .. })) if *id == device_id
+        ));
+
+        // Forced takeover, then expiry
+        let second = acquire(&mut manager, true, None).unwrap();
+        assert_ne!(first, second);
+        assert!(manager
+            .check_lease(&write, Some(&client(Some(&first))))
+            .is_err());
+        manager
+            .leases
+            .get_mut(&device_id)
+            .unwrap()
+            .lease
+            .expires_at_ms = now_ms();
+        assert!(manager.check_lease(&write, Some(&client(None))).is_ok());
+        assert!(matches!(
+            manager.get_lease(device_id),
+            Ok(Answer::Lease(None))
+        ));
+
+        let third = acquire(&mut manager, false, None).unwrap();
+        manager
+            .release_lease(device_id, Some(&client(Some(&third))))
+            .unwrap();
+        assert!(manager.check_lease(&write, Some(&client(None))).is_ok());
+    }
+}
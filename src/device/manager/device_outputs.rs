@@ -0,0 +1,389 @@
+use tokio::task::JoinHandle;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::device::{
+    manager::{Answer, DeviceManager, DeviceSelection, DeviceStatus, ManagerError},
+    output::{
+        mavlink::{start_mavlink_output, MavlinkOutputConfig},
+        nmea::{start_nmea_output, NmeaOutputConfig},
+    },
+    sonar_image::{start_sonar_image, SonarImageConfig, SonarImageHandle},
+};
+
+// Running output of a device, stopped when dropped
+pub struct RunningOutput<T> {
+    pub config: T,
+    handle: JoinHandle<()>,
+}
+
+impl<T> RunningOutput<T> {
+    // Outputs stop by themselves when the device stream closes
+    pub fn is_running(&self) -> bool {
+        !self.handle.is_finished()
+    }
+}
+
+impl<T: Clone> RunningOutput<T> {
+    // Configuration of the output while it is running, stopped outputs are reported as disabled
+    pub fn running_config(&self) -> Option<T> {
+        self.is_running().then(|| self.config.clone())
+    }
+}
+
+impl<T> Drop for RunningOutput<T> {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl DeviceManager {
+    // Start, replace or stop (with None) the NMEA depth output of a Ping1D device
+    pub async fn set_nmea_output(
+        &mut self,
+        device_id: Uuid,
+        config: Option<NmeaOutputConfig>,
+    ) -> Result<Answer, ManagerError> {
+        self.check_ping1d(device_id, "set_nmea_output")?;
+
+        let Some(config) = config else {
+            if self.nmea_outputs.remove(&device_id).is_some() {
+                info!("NMEA output disabled, device: {device_id}");
+            }
+            return Ok(Answer::NmeaOutput(None));
+        };
+
+        self.check_device_status(
+            device_id,
+            &[DeviceStatus::Running, DeviceStatus::ContinuousMode],
+        )?;
+
+        let subscriber = self.get_subscriber(device_id).await?;
+        let handle = start_nmea_output(device_id, config.clone(), subscriber).await?;
+        info!("NMEA output enabled: {config:?}, device: {device_id}");
+
+        self.nmea_outputs.insert(
+            device_id,
+            RunningOutput {
+                config: config.clone(),
+                handle,
+            },
+        );
+
+        Ok(Answer::NmeaOutput(Some(config)))
+    }
+
+    pub fn get_nmea_output(&self, device_id: Uuid) -> Result<Answer, ManagerError> {
+        self.get_device(device_id)?;
+        Ok(Answer::NmeaOutput(
+            self.nmea_outputs
+                .get(&device_id)
+                .and_then(RunningOutput::running_config),
+        ))
+    }
+
+    // Start, replace or stop (with None) the MAVLink DISTANCE_SENSOR output of a Ping1D device
+    pub async fn set_mavlink_output(
+        &mut self,
+        device_id: Uuid,
+        config: Option<MavlinkOutputConfig>,
+    ) -> Result<Answer, ManagerError> {
+        self.check_ping1d(device_id, "set_mavlink_output")?;
+
+        let Some(config) = config else {
+            if self.mavlink_outputs.remove(&device_id).is_some() {
+                info!("MAVLink output disabled, device: {device_id}");
+            }
+            return Ok(Answer::MavlinkOutput(None));
+        };
+
+        self.check_device_status(
+            device_id,
+            &[DeviceStatus::Running, DeviceStatus::ContinuousMode],
+        )?;
+
+        let subscriber = self.get_subscriber(device_id).await?;
+        let handle = start_mavlink_output(device_id, config.clone(), subscriber).await?;
+        info!("MAVLink output enabled: {config:?}, device: {device_id}");
+
+        self.mavlink_outputs.insert(
+            device_id,
+            RunningOutput {
+                config: config.clone(),
+                handle,
+            },
+        );
+
+        Ok(Answer::MavlinkOutput(Some(config)))
+    }
+
+    pub fn get_mavlink_output(&self, device_id: Uuid) -> Result<Answer, ManagerError> {
+        self.get_device(device_id)?;
+        Ok(Answer::MavlinkOutput(
+            self.mavlink_outputs
+                .get(&device_id)
+                .and_then(RunningOutput::running_config),
+        ))
+    }
+
+    // Start or restart the sonar image of a Ping360 device with a new configuration
+    pub async fn set_sonar_image(
+        &mut self,
+        device_id: Uuid,
+        config: SonarImageConfig,
+    ) -> Result<Answer, ManagerError> {
+        let image = self.start_sonar_image(device_id, config).await?;
+        Ok(Answer::SonarImage(image.info()))
+    }
+
+    // The sonar image is started on first use, and again after the device stream was restarted
+    pub async fn get_sonar_image_handle(
+        &mut self,
+        device_id: Uuid,
+    ) -> Result<Answer, ManagerError> {
+        let config = match self.sonar_images.get(&device_id) {
+            Some(image) if image.is_running() => {
+                return Ok(Answer::SonarImageHandle(image.config.clone()))
+            }
+            Some(image) => image.config.config.clone(),
+            None => SonarImageConfig::default(),
+        };
+
+        Ok(Answer::SonarImageHandle(
+            self.start_sonar_image(device_id, config).await?,
+        ))
+    }
+
+    async fn start_sonar_image(
+        &mut self,
+        device_id: Uuid,
+        config: SonarImageConfig,
+    ) -> Result<SonarImageHandle, ManagerError> {
+        if self.get_device(device_id)?.device_type != DeviceSelection::Ping360 {
+            return Err(ManagerError::Other(format!(
+                "sonar_image: Sonar images are only available for Ping360 devices, device: {device_id}"
+            )));
+        }
+        self.check_device_status(
+            device_id,
+            &[DeviceStatus::Running, DeviceStatus::ContinuousMode],
+        )?;
+
+        let subscriber = self.get_subscriber(device_id).await?;
+        let (image, handle) = start_sonar_image(device_id, config.clone(), subscriber)?;
+        info!("Sonar image enabled: {config:?}, device: {device_id}");
+
+        self.sonar_images.insert(
+            device_id,
+            RunningOutput {
+                config: image.clone(),
+                handle,
+            },
+        );
+
+        Ok(image)
+    }
+
+    fn check_ping1d(&self, device_id: Uuid, caller: &str) -> Result<(), ManagerError> {
+        if self.get_device(device_id)?.device_type != DeviceSelection::Ping1D {
+            return Err(ManagerError::Other(format!(
+                "{caller}: Outputs are only available for Ping1D devices, device: {device_id}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{
+        manager::{SourceSelection, SourceSimulatedStruct},
+        output::{
+            mavlink::MavlinkDestination, nmea::NmeaSentence, OutputDestination, OutputUdpStruct,
+        },
+        simulator::{SimulatedDeviceType, SimulationSettings},
+    };
+    use ::mavlink::{
+        ardupilotmega::{MavMessage, MavSensorOrientation},
+        peek_reader::PeekReader,
+    };
+    use std::net::Ipv4Addr;
+
+    async fn create_simulated_device(
+        manager: &mut DeviceManager,
+        name: &str,
+        device_type: DeviceSelection,
+    ) -> Uuid {
+        let simulated_type = match device_type {
+            DeviceSelection::Ping360 => SimulatedDeviceType::Ping360,
+            _ => SimulatedDeviceType::Ping1D,
+        };
+        let source = SourceSelection::Simulated(SourceSimulatedStruct {
+            name: name.to_string(),
+            device_type: simulated_type,
+            settings: SimulationSettings::default(),
+        });
+        let Ok(Answer::DeviceInfo(info)) = manager.create(source, device_type.clone()).await else {
+            panic!("Failed to create simulated {device_type:?}");
+        };
+        info[0].id
+    }
+
+    async fn create_simulated_ping1d(manager: &mut DeviceManager, name: &str) -> Uuid {
+        create_simulated_device(manager, name, DeviceSelection::Ping1D).await
+    }
+
+    #[tokio::test]
+    async fn nmea_output_over_udp() {
+        let (mut manager, _handler) = DeviceManager::new(10);
+        let device_id = create_simulated_ping1d(&mut manager, "nmea_output_over_udp").await;
+
+        let receiver = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .unwrap();
+        let config = NmeaOutputConfig {
+            destination: OutputDestination::Udp(OutputUdpStruct {
+                ip: Ipv4Addr::LOCALHOST,
+                port: receiver.local_addr().unwrap().port(),
+            }),
+            sentences: vec![NmeaSentence::DBT],
+            talker_id: "SD".to_string(),
+            offset: 0.0,
+            min_confidence: 0,
+        };
+        manager
+            .set_nmea_output(device_id, Some(config.clone()))
+            .await
+            .unwrap();
+
+        let mut buffer = [0u8; 256];
+        let size = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            receiver.recv(&mut buffer),
+        )
+        .await
+        .expect("No NMEA sentence received")
+        .unwrap();
+        let sentence = String::from_utf8_lossy(&buffer[..size]);
+        assert!(sentence.starts_with("$SDDBT,"), "{sentence}");
+        assert!(sentence.ends_with("\r\n"), "{sentence}");
+
+        assert!(matches!(
+            manager.get_nmea_output(device_id),
+            Ok(Answer::NmeaOutput(Some(stored))) if stored == config
+        ));
+
+        manager.set_nmea_output(device_id, None).await.unwrap();
+        assert!(matches!(
+            manager.get_nmea_output(device_id),
+            Ok(Answer::NmeaOutput(None))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stopped_output_is_disabled() {
+        let output = RunningOutput {
+            config: 1,
+            handle: tokio::spawn(async {}),
+        };
+        while output.is_running() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(output.running_config(), None);
+    }
+
+    #[tokio::test]
+    async fn mavlink_output_over_udp() {
+        let (mut manager, _handler) = DeviceManager::new(10);
+        let device_id = create_simulated_ping1d(&mut manager, "mavlink_output_over_udp").await;
+
+        let receiver = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .unwrap();
+        let config = MavlinkOutputConfig {
+            destination: MavlinkDestination::Direct(OutputDestination::Udp(OutputUdpStruct {
+                ip: Ipv4Addr::LOCALHOST,
+                port: receiver.local_addr().unwrap().port(),
+            })),
+            system_id: 1,
+            component_id: 191,
+            sensor_id: 0,
+            min_confidence: 0,
+        };
+        manager
+            .set_mavlink_output(device_id, Some(config))
+            .await
+            .unwrap();
+
+        let mut buffer = [0u8; 512];
+        let size = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            receiver.recv(&mut buffer),
+        )
+        .await
+        .expect("No MAVLink message received")
+        .unwrap();
+        let (header, message) =
+            ::mavlink::read_v2_msg::<MavMessage, _>(&mut PeekReader::new(&buffer[..size])).unwrap();
+        assert_eq!((header.system_id, header.component_id), (1, 191));
+        let MavMessage::DISTANCE_SENSOR(data) = message else {
+            panic!("Unexpected MAVLink message: {message:?}");
+        };
+        assert_eq!(
+            data.orientation,
+            MavSensorOrientation::MAV_SENSOR_ROTATION_PITCH_270
+        );
+        assert!(data.min_distance < data.current_distance);
+        assert!(data.current_distance < data.max_distance);
+
+        manager.delete(device_id).await.unwrap();
+        assert!(manager.mavlink_outputs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sonar_image_from_simulated_ping360() {
+        let (mut manager, _handler) = DeviceManager::new(10);
+        let device_id = create_simulated_device(
+            &mut manager,
+            "sonar_image_from_simulated_ping360",
+            DeviceSelection::Ping360,
+        )
+        .await;
+
+        let ping1d_id = create_simulated_ping1d(&mut manager, "sonar_image_ping1d").await;
+        assert!(manager.get_sonar_image_handle(ping1d_id).await.is_err());
+
+        let Ok(Answer::SonarImageHandle(image)) = manager.get_sonar_image_handle(device_id).await
+        else {
+            panic!("Sonar image expected");
+        };
+        let mut revision = image.subscribe();
+        tokio::time::timeout(std::time::Duration::from_secs(10), revision.changed())
+            .await
+            .expect("No Ping360 data drawn")
+            .unwrap();
+        assert!(image.info().range_m > 0.0);
+        assert!(image.encode_png().len() > 8);
+
+        // The running image is shared until it is configured again
+        let Ok(Answer::SonarImageHandle(same)) = manager.get_sonar_image_handle(device_id).await
+        else {
+            panic!("Sonar image expected");
+        };
+        assert_eq!(same.config, SonarImageConfig::default());
+
+        let config = SonarImageConfig {
+            size: 128,
+            ..Default::default()
+        };
+        let Ok(Answer::SonarImage(info)) = manager.set_sonar_image(device_id, config.clone()).await
+        else {
+            panic!("Sonar image information expected");
+        };
+        assert_eq!(info.config, config);
+
+        manager.delete(device_id).await.unwrap();
+        assert!(manager.sonar_images.is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+use tracing::{error, info, trace, warn};
+use uuid::Uuid;
+
+use crate::{
+    device::manager::{
+        Answer, Device, DeviceManager, DeviceProperties, DeviceStatus, ManagerError,
+        SourceSelection,
+    },
+    settings::manager::DeviceSettings,
+};
+
+impl Device {
+    pub fn settings(&self) -> DeviceSettings {
+        let (ping360_config, ping360_scan_schedule) = match &self.properties {
+            Some(DeviceProperties::Ping360(properties)) => (
+                properties
+                    .continuous_mode_settings
+                    .read()
+                    .ok()
+                    .map(|config| *config),
+                properties
+                    .scan_schedule
+                    .read()
+                    .ok()
+                    .map(|schedule| schedule.clone()),
+            ),
+            _ => (None, None),
+        };
+
+        let (ping1d_config, ping1d_continuous_config) = match &self.properties {
+            Some(DeviceProperties::Ping1D(properties)) => (
+                properties.config.read().ok().map(|config| *config),
+                properties
+                    .continuous_mode_settings
+                    .read()
+                    .ok()
+                    .map(|config| config.clone()),
+            ),
+            _ => (None, None),
+        };
+
+        DeviceSettings {
+            id: self.id,
+            source: self.source.clone(),
+            device_type: self.device_type.clone(),
+            continuous_mode: self.status == DeviceStatus::ContinuousMode,
+            ping360_config,
+            ping360_scan_schedule,
+            ping1d_continuous_config,
+            ping1d_config,
+            nmea_output: None,
+            mavlink_output: None,
+        }
+    }
+}
+
+impl DeviceManager {
+    // Store all created devices, discovered devices that were never created are not persisted
+    pub fn save_settings(&self) {
+        let previous = crate::settings::manager::devices();
+
+        let mut devices: Vec<DeviceSettings> = self
+            .device
+            .values()
+            .filter(|device| device.status != DeviceStatus::Available)
+            // Playback devices are virtual, recordings may not be available on the next run
+            .filter(|device| !matches!(device.source, SourceSelection::Playback(_)))
+            .map(|device| match device.status {
+                // Keep the last known good settings while the device is unreachable
+                DeviceStatus::Error => previous
+                    .iter()
+                    .find(|settings| settings.source == device.source)
+                    .cloned()
+                    .unwrap_or_else(|| device.settings()),
+                _ => DeviceSettings {
+                    nmea_output: self
+                        .nmea_outputs
+                        .get(&device.id)
+                        .and_then(|output| output.running_config()),
+                    mavlink_output: self
+                        .mavlink_outputs
+                        .get(&device.id)
+                        .and_then(|output| output.running_config()),
+                    ..device.settings()
+                },
+            })
+            .collect();
+
+        devices.extend(self.pending_restore.iter().cloned());
+        devices.sort_by_key(|settings| settings.id);
+
+        crate::settings::manager::set_devices(devices);
+    }
+
+    // Recreate all devices stored in the settings file, devices that fail are retried once discovered
+    pub async fn restore_devices(&mut self) -> Result<Answer, ManagerError> {
+        let mut results = Vec::new();
+
+        for settings in crate::settings::manager::devices() {
+            match self.restore_device(&settings).await {
+                Ok(Answer::DeviceInfo(info)) => {
+                    trace!("Successfully restored device: {info:?}");
+                    results.extend(info);
+                }
+                Ok(unexpected) => {
+                    warn!("Unexpected answer while restoring device: {unexpected:?}");
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to restore device {:?}, waiting for it to be discovered: {err:?}",
+                        settings.source
+                    );
+                    self.pending_restore.push(settings);
+                }
+            }
+        }
+
+        Ok(Answer::DeviceInfo(results))
+    }
+
+    // Restore devices that failed during startup once the discovery service finds them available
+    pub async fn retry_pending_restore(&mut self) {
+        if self.pending_restore.is_empty() {
+            return;
+        }
+
+        let pending = std::mem::take(&mut self.pending_restore);
+        let mut changed = false;
+
+        for settings in pending {
+            let status = self
+                .device
+                .values()
+                .find(|device| device.source == settings.source)
+                .map(|device| device.status.clone());
+
+            match status {
+                None => self.pending_restore.push(settings),
+                Some(DeviceStatus::Available) => match self.restore_device(&settings).await {
+                    Ok(answer) => {
+                        info!("Device restored from settings, details: {answer:?}");
+                        changed = true;
+                    }
+                    Err(err) => {
+                        error!("Failed to restore device {:?}: {err:?}", settings.source);
+                        self.pending_restore.push(settings);
+                    }
+                },
+                Some(status) => {
+                    trace!(
+                        "Device {:?} was already created with status {status:?}, dropping pending restore",
+                        settings.source
+                    );
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            self.save_settings();
+        }
+    }
+
+    async fn restore_device(&mut self, settings: &DeviceSettings) -> Result<Answer, ManagerError> {
+        let available_id = self
+            .device
+            .values()
+            .find(|device| device.source == settings.source)
+            .map(|device| (device.id, device.status.clone()));
+
+        let answer = match available_id {
+            Some((device_id, DeviceStatus::Available)) => self.continuous_mode(device_id).await?,
+            Some((device_id, _)) => return Err(ManagerError::DeviceAlreadyExist(device_id)),
+            None => {
+                self.create(settings.source.clone(), settings.device_type.clone())
+                    .await?
+            }
+        };
+
+        let device_id = Self::answer_device_id(&answer)?;
+
+        if let Some(config) = settings.ping360_config {
+            self.update_ping360_config(device_id, config).await?;
+        }
+
+        if let Some(schedule) = &settings.ping360_scan_schedule {
+            self.set_ping360_scan_schedule(device_id, schedule.clone())
+                .await?;
+        }
+
+        if let Some(config) = settings.ping1d_config {
+            self.update_ping1d_config(device_id, config).await?;
+        }
+
+        if let Some(config) = &settings.ping1d_continuous_config {
+            self.update_ping1d_continuous_config(device_id, config.clone())
+                .await?;
+        }
+
+        if let Some(config) = &settings.nmea_output {
+            self.set_nmea_output(device_id, Some(config.clone()))
+                .await?;
+        }
+
+        if let Some(config) = &settings.mavlink_output {
+            self.set_mavlink_output(device_id, Some(config.clone()))
+                .await?;
+        }
+
+        if !settings.continuous_mode {
+            return self.continuous_mode_off(device_id).await;
+        }
+
+        Ok(Answer::DeviceInfo(vec![self.get_device(device_id)?.info()]))
+    }
+
+    fn answer_device_id(answer: &Answer) -> Result<Uuid, ManagerError> {
+        match answer {
+            Answer::DeviceInfo(info) => info
+                .first()
+                .map(|device_info| device_info.id)
+                .ok_or(ManagerError::NoDevices),
+            unexpected => Err(ManagerError::Other(format!(
+                "Unexpected answer while restoring device: {unexpected:?}"
+            ))),
+        }
+    }
+}
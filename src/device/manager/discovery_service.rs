@@ -2,8 +2,6 @@ use std::collections::HashSet;
 use std::net::SocketAddrV4;
 use std::time::Duration;
 
-use bluerobotics_ping::ping1d::Device as Ping1D;
-use bluerobotics_ping::ping360::Device as Ping360;
 use tokio::sync::broadcast;
 use tokio::time::sleep;
 use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
@@ -11,7 +9,7 @@ use tracing::{debug, error, info, trace, warn};
 use udp_stream::UdpStream;
 use uuid::Uuid;
 
-use crate::device::devices::{DeviceActor, DeviceType, PingAnswer, UpgradeResult};
+use crate::device::devices::{DeviceActor, PingAnswer, UpgradeResult};
 use crate::device::manager::ManagerError;
 
 use super::{
@@ -55,24 +53,14 @@ impl DeviceFactory {
 
                 SourceType::Serial(serial_stream)
             }
+            SourceSelection::Playback(_) | SourceSelection::Simulated(_) => {
+                return Err(ManagerError::DeviceSourceError(
+                    "Virtual devices can't be discovered".to_string(),
+                ))
+            }
         };
 
-        let device = match port {
-            SourceType::Udp(udp_port) => match device_type {
-                DeviceSelection::Common | DeviceSelection::Auto => {
-                    DeviceType::Common(bluerobotics_ping::common::Device::new(udp_port))
-                }
-                DeviceSelection::Ping1D => DeviceType::Ping1D(Ping1D::new(udp_port)),
-                DeviceSelection::Ping360 => DeviceType::Ping360(Ping360::new(udp_port)),
-            },
-            SourceType::Serial(serial_port) => match device_type {
-                DeviceSelection::Common | DeviceSelection::Auto => {
-                    DeviceType::Common(bluerobotics_ping::common::Device::new(serial_port))
-                }
-                DeviceSelection::Ping1D => DeviceType::Ping1D(Ping1D::new(serial_port)),
-                DeviceSelection::Ping360 => DeviceType::Ping360(Ping360::new(serial_port)),
-            },
-        };
+        let device = port.into_device(&device_type);
 
         let (mut device, _handler) = DeviceActor::new(device, 1);
 
@@ -147,6 +135,7 @@ pub struct DeviceDiscoveryManager {
     tx: broadcast::Sender<DeviceInfo>,
     handle: Option<tokio::task::JoinHandle<()>>,
     known_devices_rx: broadcast::Receiver<Vec<DeviceInfo>>,
+    network_address: SocketAddrV4,
 }
 
 impl DeviceDiscoveryManager {
@@ -159,6 +148,10 @@ impl DeviceDiscoveryManager {
                 tx,
                 handle: None,
                 known_devices_rx,
+                network_address: SocketAddrV4::new(
+                    std::net::Ipv4Addr::BROADCAST,
+                    device_discovery::DISCOVERY_PORT,
+                ),
             },
             rx,
         )
@@ -167,6 +160,7 @@ impl DeviceDiscoveryManager {
     pub fn start_discovery(&mut self) {
         let tx = self.tx.clone();
         let mut known_devices_rx = self.known_devices_rx.resubscribe();
+        let network_address = self.network_address;
 
         let handle = tokio::spawn(async move {
             let mut known_devices = Vec::new();
@@ -201,11 +195,12 @@ impl DeviceDiscoveryManager {
                     }
                 }
 
-                if let Some(result) =
-                    tokio::task::spawn_blocking(device_discovery::network_discovery)
-                        .await
-                        .ok()
-                        .flatten()
+                if let Some(result) = tokio::task::spawn_blocking(move || {
+                    device_discovery::network_discovery(network_address)
+                })
+                .await
+                .ok()
+                .flatten()
                 {
                     for source in result {
                         let key = get_device_key(&source);
@@ -273,6 +268,8 @@ fn get_device_key(source: &SourceSelection) -> String {
     match source {
         SourceSelection::SerialStream(serial) => serial.path.clone(),
         SourceSelection::UdpStream(udp) => format!("{}:{}", udp.ip, udp.port),
+        SourceSelection::Playback(playback) => format!("playback:{}", playback.file_name),
+        SourceSelection::Simulated(simulated) => format!("simulated:{}", simulated.name),
     }
 }
 
@@ -316,6 +313,15 @@ impl DiscoveryComponent {
         info!("DeviceDiscovery service is stopped");
     }
 
+    // Address of the network discovery requests, used from the next discovery start
+    pub fn set_network_address(&mut self, address: SocketAddrV4) {
+        self.manager.network_address = address;
+    }
+
+    pub fn network_address(&self) -> SocketAddrV4 {
+        self.manager.network_address
+    }
+
     pub fn broadcast_known_devices(&self, device_ids: &[DeviceInfo]) {
         let _ = self.known_devices_tx.send(device_ids.to_owned());
     }
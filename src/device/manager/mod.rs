@@ -2,10 +2,26 @@
 pub mod continuous_mode;
 /// Specially for auto creation methods, from UDP or serial port
 pub mod device_discovery;
+/// Specially for DeviceManager, publish the changes of the devices to the clients as typed events
+pub mod device_events;
 /// Specially for continuous_mode methods, startup, shutdown, handle and errors routines for each device type
 pub mod device_handle;
+/// Specially for DeviceManager, control leases that keep a single client configuring each device
+pub mod device_lease;
+/// Specially for DeviceManager, forward device measurements to external consumers like NMEA
+pub mod device_outputs;
+/// Specially for DeviceManager, save created devices to the settings file and restore them on startup
+pub mod device_settings;
 /// Specially for DeviceManager, allow discovery service to run on background
 pub mod discovery_service;
+/// Specially for Ping1D devices, apply and verify the device configuration
+pub mod ping1d_config;
+/// Specially for Ping360 devices, firmware limits and validation of the device configuration
+pub mod ping360_config;
+/// Specially for Ping360 devices, compute the firmware timing from a range in meters
+pub mod ping360_range;
+/// Specially for Ping360 devices, named scan sectors and the schedule alternating between them
+pub mod ping360_scan;
 
 use paperclip::actix::Apiv2Schema;
 use serde::{Deserialize, Serialize};
@@ -18,7 +34,10 @@ use std::{
     time::Duration,
 };
 use tokio::{
-    sync::{broadcast::Receiver, mpsc, oneshot},
+    sync::{
+        broadcast::{self, Receiver},
+        mpsc, oneshot,
+    },
     time::sleep,
 };
 
@@ -26,14 +45,20 @@ use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
 use tracing::{debug, error, info, trace, warn};
 use udp_stream::UdpStream;
 use uuid::Uuid;
+use validator::Validate;
 
 use super::devices::{DeviceActor, DeviceActorHandler, DeviceType, PingAnswer};
+use super::output::{mavlink::MavlinkOutputConfig, nmea::NmeaOutputConfig};
+use super::playback::{PlaybackCommand, PlaybackHandler, PlaybackStatus};
+use super::simulator::{SimulatedDeviceType, SimulationSettings};
+use super::sonar_image::{SonarImageConfig, SonarImageHandle, SonarImageInfo};
 use bluerobotics_ping::{
     common::{DeviceInformationStruct, ProtocolVersionStruct},
     device::{Ping1D, Ping360},
-    message::ProtocolMessage,
+    message::{MessageInfo, ProtocolMessage},
 };
 use discovery_service::DiscoveryComponent;
+pub use ping360_config::Ping360Config;
 #[derive(Debug)]
 pub struct Device {
     pub id: Uuid,
@@ -54,17 +79,72 @@ pub enum DeviceProperties {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Apiv2Schema)]
-pub struct Ping360Config {
-    pub mode: u8,
+pub enum Ping1DContinuousMessage {
+    Distance,
+    DistanceSimple,
+    Profile,
+}
+
+impl Ping1DContinuousMessage {
+    pub const ALL: [Ping1DContinuousMessage; 3] = [
+        Ping1DContinuousMessage::Distance,
+        Ping1DContinuousMessage::DistanceSimple,
+        Ping1DContinuousMessage::Profile,
+    ];
+
+    pub fn id(&self) -> u16 {
+        match self {
+            Ping1DContinuousMessage::Distance => {
+                <bluerobotics_ping::ping1d::DistanceStruct as MessageInfo>::id()
+            }
+            Ping1DContinuousMessage::DistanceSimple => {
+                <bluerobotics_ping::ping1d::DistanceSimpleStruct as MessageInfo>::id()
+            }
+            Ping1DContinuousMessage::Profile => {
+                <bluerobotics_ping::ping1d::ProfileStruct as MessageInfo>::id()
+            }
+        }
+    }
+}
+
+/// Ping interval, range and gain of the continuous mode are the ones from the Ping1DConfig.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Apiv2Schema)]
+pub struct Ping1DContinuousConfig {
+    /// Messages streamed by the device while in continuous mode.
+    pub messages: Vec<Ping1DContinuousMessage>,
+}
+
+impl Default for Ping1DContinuousConfig {
+    fn default() -> Self {
+        Self {
+            messages: vec![Ping1DContinuousMessage::Profile],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Apiv2Schema)]
+pub struct Ping1DConfig {
+    /// Speed of sound in mm/s.
+    pub speed_of_sound: u32,
+    /// Automatic range and gain, scan_start, scan_length and gain_setting are only applied when 0.
+    pub mode_auto: u8,
+    pub scan_start: u32,
+    pub scan_length: u32,
     pub gain_setting: u8,
-    pub transmit_duration: u16,
-    pub sample_period: u16,
-    pub transmit_frequency: u16,
-    pub number_of_samples: u16,
-    pub start_angle: u16,
-    pub stop_angle: u16,
-    pub num_steps: u8,
-    pub delay: u8,
+    pub ping_interval: u16,
+}
+
+impl Default for Ping1DConfig {
+    fn default() -> Self {
+        Self {
+            speed_of_sound: 1_500_000,
+            mode_auto: 1,
+            scan_start: 0,
+            scan_length: 5000,
+            gain_setting: 0,
+            ping_interval: 100,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -76,12 +156,15 @@ pub struct CommonProperties {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Ping1DProperties {
     pub common: CommonProperties,
+    pub config: Arc<RwLock<Ping1DConfig>>,
+    pub continuous_mode_settings: Arc<RwLock<Ping1DContinuousConfig>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Ping360Properties {
     pub common: CommonProperties,
     pub continuous_mode_settings: Arc<RwLock<Ping360Config>>,
+    pub scan_schedule: Arc<RwLock<ping360_scan::Ping360ScanSchedule>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -132,11 +215,61 @@ pub enum DeviceSelection {
 pub enum SourceSelection {
     UdpStream(SourceUdpStruct),
     SerialStream(SourceSerialStruct),
+    Playback(SourcePlaybackStruct),
+    Simulated(SourceSimulatedStruct),
 }
 
 enum SourceType {
     Udp(UdpStream),
     Serial(SerialStream),
+    // Devices that only exist in software, like playback and simulators
+    Virtual(tokio::io::DuplexStream),
+}
+
+impl SourceType {
+    // Replay a recording, which must contain data of the selected device type
+    async fn playback(
+        source: &SourcePlaybackStruct,
+        device_selection: &DeviceSelection,
+    ) -> Result<(Self, PlaybackHandler), ManagerError> {
+        let (stream, handler, recorded_type) =
+            super::playback::start_playback(&source.file_name).await?;
+
+        if matches!(
+            device_selection,
+            DeviceSelection::Ping1D | DeviceSelection::Ping360
+        ) && *device_selection != recorded_type
+        {
+            return Err(ManagerError::DeviceSourceError(format!(
+                "Recording {} contains {recorded_type:?} data, not {device_selection:?}",
+                source.file_name
+            )));
+        }
+
+        Ok((SourceType::Virtual(stream), handler))
+    }
+
+    // Device of the selected type over the source, Auto starts as a common device to be upgraded
+    fn into_device(self, device_selection: &DeviceSelection) -> DeviceType {
+        fn device<T>(io: T, device_selection: &DeviceSelection) -> DeviceType
+        where
+            T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        {
+            match device_selection {
+                DeviceSelection::Common | DeviceSelection::Auto => {
+                    DeviceType::Common(bluerobotics_ping::common::Device::new(io))
+                }
+                DeviceSelection::Ping1D => DeviceType::Ping1D(Ping1D::new(io)),
+                DeviceSelection::Ping360 => DeviceType::Ping360(Ping360::new(io)),
+            }
+        }
+
+        match self {
+            SourceType::Udp(udp_stream) => device(udp_stream, device_selection),
+            SourceType::Serial(serial_stream) => device(serial_stream, device_selection),
+            SourceType::Virtual(virtual_stream) => device(virtual_stream, device_selection),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Hash, Apiv2Schema, PartialEq)]
@@ -151,6 +284,19 @@ pub struct SourceSerialStruct {
     pub baudrate: u32,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize, Hash, Apiv2Schema, PartialEq)]
+pub struct SourcePlaybackStruct {
+    pub file_name: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Hash, Apiv2Schema, PartialEq)]
+pub struct SourceSimulatedStruct {
+    pub name: String,
+    pub device_type: SimulatedDeviceType,
+    #[serde(default)]
+    pub settings: SimulationSettings,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DeviceStatus {
     Available,
@@ -164,16 +310,25 @@ pub struct DeviceManager {
     pub device: HashMap<Uuid, Device>,
     discovery_service: DiscoveryComponent,
     pub manager_handler: ManagerActorHandler,
+    pending_restore: Vec<crate::settings::manager::DeviceSettings>,
+    playback: HashMap<Uuid, PlaybackHandler>,
+    nmea_outputs: HashMap<Uuid, device_outputs::RunningOutput<NmeaOutputConfig>>,
+    mavlink_outputs: HashMap<Uuid, device_outputs::RunningOutput<MavlinkOutputConfig>>,
+    sonar_images: HashMap<Uuid, device_outputs::RunningOutput<SonarImageHandle>>,
+    leases: HashMap<Uuid, device_lease::LeaseGrant>,
 }
 
 #[derive(Debug)]
 pub struct ManagerActorRequest {
     pub request: Request,
+    // Client that sent the request, internal requests have none and skip the lease checks
+    pub client: Option<device_lease::LeaseClient>,
     pub respond_to: oneshot::Sender<Result<Answer, ManagerError>>,
 }
 #[derive(Clone)]
 pub struct ManagerActorHandler {
     pub sender: mpsc::Sender<ManagerActorRequest>,
+    events: broadcast::Sender<device_events::DeviceEvent>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Apiv2Schema)]
@@ -183,6 +338,14 @@ pub enum Answer {
     InnerDeviceHandler(DeviceActorHandler),
     DeviceInfo(Vec<DeviceInfo>),
     DeviceConfig(ModifyDeviceResult),
+    PlaybackStatus(PlaybackStatus),
+    NmeaOutput(Option<NmeaOutputConfig>),
+    MavlinkOutput(Option<MavlinkOutputConfig>),
+    SonarImage(SonarImageInfo),
+    #[serde(skip)]
+    SonarImageHandle(SonarImageHandle),
+    Lease(Option<device_lease::DeviceLease>),
+    LeaseGranted(device_lease::LeaseGrant),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -195,6 +358,8 @@ pub enum ManagerError {
     NoDevices,
     TokioMpsc(String),
     NotImplemented(Request),
+    DeviceLeased(device_lease::DeviceLease),
+    InvalidConfig(Vec<ping360_config::InvalidField>),
     Other(String),
 }
 
@@ -219,6 +384,17 @@ pub enum Request {
     ModifyDevice(ModifyDevice),
     EnableContinuousMode(UuidWrapper),
     DisableContinuousMode(UuidWrapper),
+    Playback(PlaybackRequestStruct),
+    SetNmeaOutput(NmeaOutputRequestStruct),
+    GetNmeaOutput(UuidWrapper),
+    SetMavlinkOutput(MavlinkOutputRequestStruct),
+    GetMavlinkOutput(UuidWrapper),
+    SetSonarImage(SonarImageRequestStruct),
+    #[serde(skip)]
+    GetSonarImageHandle(UuidWrapper),
+    AcquireLease(device_lease::LeaseRequestStruct),
+    ReleaseLease(UuidWrapper),
+    GetLease(UuidWrapper),
     #[serde(skip)]
     SpecialTurnOffContinuousMode(UuidWrapper),
 }
@@ -228,12 +404,23 @@ pub enum ModifyDeviceCommand {
     SetIp(Ipv4Addr),
     SetPing360Config(Ping360Config),
     GetPing360Config,
+    SetPing360Range(ping360_range::Ping360RangeConfig),
+    SetPing360ScanSchedule(ping360_scan::Ping360ScanSchedule),
+    GetPing360ScanSchedule,
+    SetPing1DContinuousConfig(Ping1DContinuousConfig),
+    GetPing1DContinuousConfig,
+    SetPing1DConfig(Ping1DConfig),
+    GetPing1DConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum ModifyDeviceResult {
     ConfigAcknowledge(ModifyDevice),
     Ping360Config(Ping360Config),
+    Ping360Range(ping360_range::Ping360RangeResult),
+    Ping360ScanSchedule(ping360_scan::Ping360ScanSchedule),
+    Ping1DContinuousConfig(Ping1DContinuousConfig),
+    Ping1DConfig(Ping1DConfig),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
@@ -260,6 +447,32 @@ pub struct CreateStruct {
     pub device_selection: DeviceSelection,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct PlaybackRequestStruct {
+    pub uuid: Uuid,
+    pub command: PlaybackCommand,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct NmeaOutputRequestStruct {
+    pub uuid: Uuid,
+    /// Output to use, `None` disables it.
+    pub config: Option<NmeaOutputConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct MavlinkOutputRequestStruct {
+    pub uuid: Uuid,
+    /// Output to use, `None` disables it.
+    pub config: Option<MavlinkOutputConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct SonarImageRequestStruct {
+    pub uuid: Uuid,
+    pub config: SonarImageConfig,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceRequestStruct {
     pub uuid: Uuid,
@@ -269,15 +482,25 @@ pub struct DeviceRequestStruct {
 impl DeviceManager {
     async fn handle_message(&mut self, actor_request: ManagerActorRequest) {
         trace!("DeviceManager: Received a request, details: {actor_request:?}");
+        let client = actor_request.client.as_ref();
+        if let Err(err) = self.check_lease(&actor_request.request, client) {
+            if let Err(e) = actor_request.respond_to.send(Err(err)) {
+                error!("DeviceManager: Failed to return lease error: {e:?}");
+            }
+            return;
+        }
+
         match actor_request.request {
             Request::AutoCreate => {
                 let result = self.auto_create().await;
+                self.save_settings();
                 if let Err(e) = actor_request.respond_to.send(result) {
                     error!("DeviceManager: Failed to return AutoCreate response: {e:?}");
                 }
             }
             Request::Create(request) => {
                 let result = self.create(request.source, request.device_selection).await;
+                self.save_settings();
                 if let Err(e) = actor_request.respond_to.send(result) {
                     error!("DeviceManager: Failed to return Create response: {e:?}");
                 }
@@ -290,6 +513,7 @@ impl DeviceManager {
             }
             Request::Delete(uuid) => {
                 let result = self.delete(*uuid).await;
+                self.save_settings();
                 if let Err(e) = actor_request.respond_to.send(result) {
                     error!("DeviceManager: Failed to return Delete response: {e:?}");
                 }
@@ -308,12 +532,14 @@ impl DeviceManager {
             }
             Request::EnableContinuousMode(uuid) => {
                 let result = self.continuous_mode(*uuid).await;
+                self.save_settings();
                 if let Err(e) = actor_request.respond_to.send(result) {
                     error!("DeviceManager: Failed to return EnableContinuousMode response: {e:?}");
                 }
             }
             Request::DisableContinuousMode(uuid) => {
                 let result = self.continuous_mode_off(*uuid).await;
+                self.save_settings();
                 if let Err(e) = actor_request.respond_to.send(result) {
                     error!("DeviceManager: Failed to return DisableContinuousMode response: {e:?}");
                 }
@@ -326,10 +552,73 @@ impl DeviceManager {
             }
             Request::ModifyDevice(request) => {
                 let answer = self.modify_device(request).await;
+                self.save_settings();
                 if let Err(err) = actor_request.respond_to.send(answer) {
                     error!("DeviceManager: Failed to return ModifyDevice response: {err:?}");
                 }
             }
+            Request::Playback(request) => {
+                let answer = self.playback(request).await;
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return Playback response: {err:?}");
+                }
+            }
+            Request::SetNmeaOutput(request) => {
+                let answer = self.set_nmea_output(request.uuid, request.config).await;
+                self.save_settings();
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return SetNmeaOutput response: {err:?}");
+                }
+            }
+            Request::GetNmeaOutput(uuid) => {
+                let answer = self.get_nmea_output(*uuid);
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return GetNmeaOutput response: {err:?}");
+                }
+            }
+            Request::SetMavlinkOutput(request) => {
+                let answer = self.set_mavlink_output(request.uuid, request.config).await;
+                self.save_settings();
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return SetMavlinkOutput response: {err:?}");
+                }
+            }
+            Request::GetMavlinkOutput(uuid) => {
+                let answer = self.get_mavlink_output(*uuid);
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return GetMavlinkOutput response: {err:?}");
+                }
+            }
+            Request::SetSonarImage(request) => {
+                let answer = self.set_sonar_image(request.uuid, request.config).await;
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return SetSonarImage response: {err:?}");
+                }
+            }
+            Request::GetSonarImageHandle(uuid) => {
+                let answer = self.get_sonar_image_handle(*uuid).await;
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return GetSonarImageHandle response: {err:?}");
+                }
+            }
+            Request::AcquireLease(request) => {
+                let answer = self.acquire_lease(request, client);
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return AcquireLease response: {err:?}");
+                }
+            }
+            Request::ReleaseLease(uuid) => {
+                let answer = self.release_lease(*uuid, client);
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return ReleaseLease response: {err:?}");
+                }
+            }
+            Request::GetLease(uuid) => {
+                let answer = self.get_lease(*uuid);
+                if let Err(err) = actor_request.respond_to.send(answer) {
+                    error!("DeviceManager: Failed to return GetLease response: {err:?}");
+                }
+            }
             _ => {
                 if let Err(e) = actor_request
                     .respond_to
@@ -344,18 +633,31 @@ impl DeviceManager {
     pub fn new(size: usize) -> (Self, ManagerActorHandler) {
         let (sender, receiver) = mpsc::channel(size);
 
-        let actor_handler = ManagerActorHandler { sender };
+        let (events, _) = broadcast::channel(device_events::DEVICE_EVENTS_SIZE);
+        let actor_handler = ManagerActorHandler { sender, events };
         let actor = DeviceManager {
             receiver,
             device: HashMap::new(),
             discovery_service: DiscoveryComponent::new(),
             manager_handler: actor_handler.clone(),
+            pending_restore: Vec::new(),
+            playback: HashMap::new(),
+            nmea_outputs: HashMap::new(),
+            mavlink_outputs: HashMap::new(),
+            sonar_images: HashMap::new(),
+            leases: HashMap::new(),
         };
 
         trace!("DeviceManager and handler successfully created: Success");
         (actor, actor_handler)
     }
 
+    // Send network discovery requests and SetSS1IP commands to this address instead of
+    // broadcasting them to the Ping360 discovery port, must be set before running the manager
+    pub fn set_network_discovery_address(&mut self, address: SocketAddrV4) {
+        self.discovery_service.set_network_address(address);
+    }
+
     pub fn get_device_manager_handler(&self) -> ManagerActorHandler {
         self.manager_handler.clone()
     }
@@ -373,12 +675,19 @@ impl DeviceManager {
 
         let mut status_check_interval = tokio::time::interval(std::time::Duration::from_secs(10));
 
+        // Requests, discoveries and status checks are published as events once applied,
+        // read-only requests are answered without comparing the devices
         loop {
             tokio::select! {
                 Some(msg) = self.receiver.recv() => {
+                    let devices = msg.request.changes_devices().then(|| self.snapshot());
                     self.handle_message(msg).await;
+                    if let Some(devices) = devices {
+                        self.publish_changes(&devices);
+                    }
                 }
                 Ok(device_info) = discovery_rx.recv() => {
+                    let devices = self.snapshot();
                     match self.register_device(device_info.clone()).await {
                         Ok(_) => {
                             info!("New device available, registered with id {:?} : device_type: {:?}", device_info.id, device_info.device_type);
@@ -387,10 +696,14 @@ impl DeviceManager {
                             error!("Failed to register discovered device: {err:?}");
                         }
                     }
+                    self.publish_changes(&devices);
                 }
                 _ = status_check_interval.tick() => {
                     debug!("Running scheduled device status check");
+                    let devices = self.snapshot();
                     self.update_devices_status().await;
+                    self.retry_pending_restore().await;
+                    self.publish_changes(&devices);
                 }
                 else => break,
             }
@@ -440,6 +753,12 @@ impl DeviceManager {
             }
 
             match &device_entry.status {
+                // Playback devices may be paused, so they are checked like a running device
+                DeviceStatus::ContinuousMode
+                    if matches!(device_entry.source, SourceSelection::Playback(_)) =>
+                {
+                    DeviceManager::check_running_device(device_entry, device.id).await;
+                }
                 DeviceStatus::ContinuousMode => {
                     DeviceManager::check_continuous_mode_device(device_entry, receiver, device.id)
                         .await;
@@ -552,6 +871,8 @@ impl DeviceManager {
             return Err(ManagerError::DeviceAlreadyExist(hash));
         }
 
+        let mut playback_handler = None;
+
         let port = match &source {
             SourceSelection::UdpStream(source_udp_struct) => {
                 let socket_addr = SocketAddrV4::new(source_udp_struct.ip, source_udp_struct.port);
@@ -584,37 +905,32 @@ impl DeviceManager {
 
                 SourceType::Serial(serial_stream)
             }
-        };
+            SourceSelection::Playback(source_playback_struct) => {
+                let (port, handler) =
+                    SourceType::playback(source_playback_struct, &device_selection).await?;
+                playback_handler = Some(handler);
+                port
+            }
+            SourceSelection::Simulated(source_simulated_struct) => {
+                let simulated_type = source_simulated_struct.device_type.device_selection();
 
-        let device = match port {
-            SourceType::Udp(udp_port) => match device_selection {
-                DeviceSelection::Common | DeviceSelection::Auto => {
-                    crate::device::devices::DeviceType::Common(
-                        bluerobotics_ping::common::Device::new(udp_port),
-                    )
-                }
-                DeviceSelection::Ping1D => {
-                    crate::device::devices::DeviceType::Ping1D(Ping1D::new(udp_port))
-                }
-                DeviceSelection::Ping360 => {
-                    crate::device::devices::DeviceType::Ping360(Ping360::new(udp_port))
-                }
-            },
-            SourceType::Serial(serial_port) => match device_selection {
-                DeviceSelection::Common | DeviceSelection::Auto => {
-                    crate::device::devices::DeviceType::Common(
-                        bluerobotics_ping::common::Device::new(serial_port),
-                    )
-                }
-                DeviceSelection::Ping1D => {
-                    crate::device::devices::DeviceType::Ping1D(Ping1D::new(serial_port))
-                }
-                DeviceSelection::Ping360 => {
-                    crate::device::devices::DeviceType::Ping360(Ping360::new(serial_port))
+                if matches!(
+                    device_selection,
+                    DeviceSelection::Ping1D | DeviceSelection::Ping360
+                ) && device_selection != simulated_type
+                {
+                    return Err(ManagerError::DeviceSourceError(format!(
+                        "Simulated device {} is a {simulated_type:?}, not {device_selection:?}",
+                        source_simulated_struct.name
+                    )));
                 }
-            },
+
+                SourceType::Virtual(super::simulator::start_simulator(source_simulated_struct))
+            }
         };
 
+        let device = port.into_device(&device_selection);
+
         let (mut device, handler) = super::devices::DeviceActor::new(device, 10);
 
         if device_selection == DeviceSelection::Auto {
@@ -675,9 +991,17 @@ impl DeviceManager {
         };
 
         self.device.insert(hash, device);
+        if let Some(playback_handler) = playback_handler {
+            self.playback.insert(hash, playback_handler);
+        }
 
         trace!("Updating device properties for: {:?}", hash);
-        self.update_device_properties(hash).await?;
+        if let Err(err) = self.update_device_properties(hash).await {
+            // Don't keep an unresponsive device, it may be discovered later
+            self.device.remove(&hash);
+            self.playback.remove(&hash);
+            return Err(err);
+        }
 
         trace!("Device broadcast enable by default for: {hash:?}");
         let device_info = self.continuous_mode(hash).await?;
@@ -776,24 +1100,18 @@ impl DeviceManager {
 
                 SourceType::Serial(serial_stream)
             }
+            SourceSelection::Playback(source_playback_struct) => {
+                let (port, handler) =
+                    SourceType::playback(source_playback_struct, &device_type).await?;
+                self.playback.insert(device_id, handler);
+                port
+            }
+            SourceSelection::Simulated(source_simulated_struct) => {
+                SourceType::Virtual(super::simulator::start_simulator(source_simulated_struct))
+            }
         };
 
-        let device_type_inner = match port {
-            SourceType::Udp(udp_port) => match device_type {
-                DeviceSelection::Common | DeviceSelection::Auto => {
-                    DeviceType::Common(bluerobotics_ping::common::Device::new(udp_port))
-                }
-                DeviceSelection::Ping1D => DeviceType::Ping1D(Ping1D::new(udp_port)),
-                DeviceSelection::Ping360 => DeviceType::Ping360(Ping360::new(udp_port)),
-            },
-            SourceType::Serial(serial_port) => match device_type {
-                DeviceSelection::Common | DeviceSelection::Auto => {
-                    DeviceType::Common(bluerobotics_ping::common::Device::new(serial_port))
-                }
-                DeviceSelection::Ping1D => DeviceType::Ping1D(Ping1D::new(serial_port)),
-                DeviceSelection::Ping360 => DeviceType::Ping360(Ping360::new(serial_port)),
-            },
-        };
+        let device_type_inner = port.into_device(&device_type);
 
         let (device_actor, handler) = super::devices::DeviceActor::new(device_type_inner, 10);
         let actor = tokio::spawn(async move { device_actor.run().await });
@@ -899,6 +1217,13 @@ impl DeviceManager {
     }
 
     pub async fn delete(&mut self, id: Uuid) -> Result<Answer, ManagerError> {
+        self.pending_restore.retain(|settings| settings.id != id);
+        self.playback.remove(&id);
+        self.nmea_outputs.remove(&id);
+        self.mavlink_outputs.remove(&id);
+        self.sonar_images.remove(&id);
+        self.leases.remove(&id);
+
         let device = self
             .device
             .remove(&id)
@@ -1052,8 +1377,29 @@ impl DeviceManager {
                 device.properties = Some(DeviceProperties::Common(common_properties))
             }
             DeviceSelection::Ping1D => {
+                // Keep the user configuration when properties are refreshed
+                let (config, continuous_mode_settings) = match &device.properties {
+                    Some(DeviceProperties::Ping1D(properties)) => (
+                        properties.config.clone(),
+                        properties.continuous_mode_settings.clone(),
+                    ),
+                    _ => {
+                        let config = match device.source {
+                            // Playback devices only answer with recorded messages
+                            SourceSelection::Playback(_) => Ping1DConfig::default(),
+                            _ => Self::read_ping1d_config(&handler, device_id).await?,
+                        };
+                        (
+                            Arc::new(RwLock::new(config)),
+                            Arc::new(RwLock::new(Ping1DContinuousConfig::default())),
+                        )
+                    }
+                };
+
                 let ping_1d_properties = Ping1DProperties {
                     common: common_properties,
+                    config,
+                    continuous_mode_settings,
                 };
 
                 device.properties = Some(DeviceProperties::Ping1D(ping_1d_properties))
@@ -1091,9 +1437,22 @@ impl DeviceManager {
                     delay: 0,
                 };
 
+                // Keep the user configuration when properties are refreshed
+                let (continuous_mode_settings, scan_schedule) = match &device.properties {
+                    Some(DeviceProperties::Ping360(properties)) => (
+                        properties.continuous_mode_settings.clone(),
+                        properties.scan_schedule.clone(),
+                    ),
+                    _ => (
+                        Arc::new(RwLock::new(auto_transmit)),
+                        Arc::new(RwLock::new(Default::default())),
+                    ),
+                };
+
                 let ping_360_properties = Ping360Properties {
                     common: common_properties,
-                    continuous_mode_settings: Arc::new(RwLock::new(auto_transmit)),
+                    continuous_mode_settings,
+                    scan_schedule,
                 };
 
                 device.properties = Some(DeviceProperties::Ping360(ping_360_properties))
@@ -1122,6 +1481,8 @@ impl DeviceManager {
         device_id: Uuid,
         new_config: Ping360Config,
     ) -> Result<(), ManagerError> {
+        new_config.validate()?;
+
         let device = self.get_device(device_id)?;
         if let Some(DeviceProperties::Ping360(properties)) = &device.properties {
             let mut config = properties
@@ -1150,6 +1511,72 @@ impl DeviceManager {
         ))
     }
 
+    pub async fn update_ping1d_continuous_config(
+        &self,
+        device_id: Uuid,
+        new_config: Ping1DContinuousConfig,
+    ) -> Result<(), ManagerError> {
+        if new_config.messages.is_empty() {
+            return Err(ManagerError::Other(format!(
+                "set_ping1d_continuous_config: At least one message is required, device: {device_id}"
+            )));
+        }
+
+        let device = self.get_device(device_id)?;
+        let Some(DeviceProperties::Ping1D(properties)) = &device.properties else {
+            return Err(ManagerError::DeviceSourceError(
+                "set_ping1d_continuous_config: Can't set Ping1DContinuousConfig".to_string(),
+            ));
+        };
+
+        // Streaming devices are reconfigured right away, others on the next continuous mode start
+        if device.status == DeviceStatus::ContinuousMode {
+            let handler = self.extract_handler(self.get_device_handler(device_id).await?)?;
+            let previous_config = properties
+                .continuous_mode_settings
+                .read()
+                .map_err(|err| ManagerError::Other(err.to_string()))?
+                .clone();
+
+            Self::stop_ping1d_continuous_messages(&handler, &previous_config.messages).await;
+            if let Err(err) =
+                Self::start_ping1d_continuous_messages(&handler, &new_config.messages).await
+            {
+                error!("Failed to apply Ping1DContinuousConfig, restoring previous one: {err:?}, device: {device_id}");
+                Self::stop_ping1d_continuous_messages(&handler, &new_config.messages).await;
+                Self::start_ping1d_continuous_messages(&handler, &previous_config.messages).await?;
+                return Err(err);
+            }
+        }
+
+        *properties
+            .continuous_mode_settings
+            .write()
+            .map_err(|err| ManagerError::Other(err.to_string()))? = new_config;
+
+        Ok(())
+    }
+
+    pub async fn get_ping1d_continuous_config(
+        &self,
+        device_id: Uuid,
+    ) -> Result<Answer, ManagerError> {
+        let device = self.get_device(device_id)?;
+        if let Some(DeviceProperties::Ping1D(properties)) = &device.properties {
+            let config = properties.continuous_mode_settings.read().map_err(|err| {
+                ManagerError::Other(format!(
+                    "get_ping1d_continuous_config: {err}, device: {device_id}"
+                ))
+            })?;
+            return Ok(Answer::DeviceConfig(
+                ModifyDeviceResult::Ping1DContinuousConfig(config.clone()),
+            ));
+        }
+        Err(ManagerError::DeviceSourceError(
+            "get_ping1d_continuous_config: Can't return Ping1DContinuousConfig".to_string(),
+        ))
+    }
+
     pub async fn modify_device(&mut self, request: ModifyDevice) -> Result<Answer, ManagerError> {
         match request.modify {
             ModifyDeviceCommand::SetIp(ip) => {
@@ -1181,9 +1608,53 @@ impl DeviceManager {
                 )))
             }
             ModifyDeviceCommand::GetPing360Config => self.get_ping360_config(request.uuid).await,
+            ModifyDeviceCommand::SetPing360Range(config) => {
+                self.set_ping360_range(request.uuid, config).await
+            }
+            ModifyDeviceCommand::SetPing360ScanSchedule(ref schedule) => {
+                self.set_ping360_scan_schedule(request.uuid, schedule.clone())
+                    .await?;
+                Ok(Answer::DeviceConfig(ModifyDeviceResult::ConfigAcknowledge(
+                    request,
+                )))
+            }
+            ModifyDeviceCommand::GetPing360ScanSchedule => {
+                self.get_ping360_scan_schedule(request.uuid).await
+            }
+            ModifyDeviceCommand::SetPing1DContinuousConfig(ref config) => {
+                self.update_ping1d_continuous_config(request.uuid, config.clone())
+                    .await?;
+                Ok(Answer::DeviceConfig(ModifyDeviceResult::ConfigAcknowledge(
+                    request,
+                )))
+            }
+            ModifyDeviceCommand::GetPing1DContinuousConfig => {
+                self.get_ping1d_continuous_config(request.uuid).await
+            }
+            ModifyDeviceCommand::SetPing1DConfig(config) => {
+                self.update_ping1d_config(request.uuid, config).await?;
+                Ok(Answer::DeviceConfig(ModifyDeviceResult::ConfigAcknowledge(
+                    request,
+                )))
+            }
+            ModifyDeviceCommand::GetPing1DConfig => self.get_ping1d_config(request.uuid).await,
         }
     }
 
+    pub async fn playback(&self, request: PlaybackRequestStruct) -> Result<Answer, ManagerError> {
+        self.check_device_uuid(request.uuid)?;
+
+        let Some(handler) = self.playback.get(&request.uuid) else {
+            return Err(ManagerError::DeviceSourceError(format!(
+                "playback : device {} is not a playback device",
+                request.uuid
+            )));
+        };
+
+        let status = handler.send(request.command).await?;
+        Ok(Answer::PlaybackStatus(status))
+    }
+
     pub async fn modify_device_ip(
         &mut self,
         ip: Ipv4Addr,
@@ -1198,7 +1669,10 @@ impl DeviceManager {
         let command = format!("SetSS1IP {}", ip);
 
         socket
-            .send_to(command.as_bytes(), format!("{destination}:30303"))
+            .send_to(
+                command.as_bytes(),
+                (destination, self.discovery_service.network_address().port()),
+            )
             .map_err(|err| ManagerError::Other(err.to_string()))?;
         Ok(())
     }
@@ -1206,6 +1680,31 @@ impl DeviceManager {
 
 impl ManagerActorHandler {
     pub async fn send(&self, request: Request) -> Result<Answer, ManagerError> {
+        self.send_as(request, None).await
+    }
+
+    // Send a request on behalf of a client, writes are refused while another client holds the device lease
+    pub async fn send_as(
+        &self,
+        request: Request,
+        client: Option<device_lease::LeaseClient>,
+    ) -> Result<Answer, ManagerError> {
+        let failure_event = request.changes_devices().then(|| request.write_target());
+        let result = self.forward(request, client).await;
+        if let (Some(device_id), Err(error)) = (failure_event, &result) {
+            self.publish(device_events::DeviceEvent::Error {
+                device_id,
+                error: error.clone(),
+            });
+        }
+        result
+    }
+
+    async fn forward(
+        &self,
+        request: Request,
+        client: Option<device_lease::LeaseClient>,
+    ) -> Result<Answer, ManagerError> {
         let (result_sender, result_receiver) = oneshot::channel();
 
         match &request {
@@ -1219,6 +1718,7 @@ impl ManagerActorHandler {
                     });
                 let manager_request = ManagerActorRequest {
                     request: handler_request,
+                    client,
                     respond_to: result_sender,
                 };
                 self.sender
@@ -1264,6 +1764,7 @@ impl ManagerActorHandler {
                 trace!("Handling DeviceManager request: {request:?}: Forwarding request.");
                 let device_request = ManagerActorRequest {
                     request: request.clone(),
+                    client,
                     respond_to: result_sender,
                 };
 
@@ -1290,6 +1791,19 @@ impl ManagerActorHandler {
             }
         }
     }
+
+    // Sonar image of a Ping360 device, started on first use
+    pub async fn get_sonar_image(&self, uuid: Uuid) -> Result<SonarImageHandle, ManagerError> {
+        match self
+            .send(Request::GetSonarImageHandle(UuidWrapper { uuid }))
+            .await?
+        {
+            Answer::SonarImageHandle(image) => Ok(image),
+            answer => Err(ManagerError::Other(format!(
+                "Unreachable: get_sonar_image helper, answer: {answer:?}"
+            ))),
+        }
+    }
 }
 
 pub async fn turnoff_device_continuous_mode(source: &SourceSelection) -> Result<(), ManagerError> {
@@ -1332,6 +1846,19 @@ pub async fn turnoff_device_continuous_mode(source: &SourceSelection) -> Result<
                     ))
                 })?;
         }
+        SourceSelection::Playback(playback_config) => {
+            debug!(
+                "Playback device {} has no continuous mode to turn off",
+                playback_config.file_name
+            );
+        }
+        // The simulator stops streaming once the device is dropped
+        SourceSelection::Simulated(simulated_config) => {
+            debug!(
+                "Simulated device {} has no continuous mode to turn off",
+                simulated_config.name
+            );
+        }
     }
 
     Ok(())
@@ -0,0 +1,276 @@
+use bluerobotics_ping::ping1d;
+use tracing::{error, trace};
+use uuid::Uuid;
+
+use crate::device::{
+    devices::{DeviceActorHandler, Ping1DRequest, PingAnswer, PingRequest},
+    manager::{
+        Answer, DeviceManager, DeviceProperties, DeviceStatus, ManagerError, ModifyDeviceResult,
+        Ping1DConfig,
+    },
+};
+
+impl DeviceManager {
+    // Apply all settings to the device and store them once the device reports the same values
+    pub async fn update_ping1d_config(
+        &self,
+        device_id: Uuid,
+        new_config: Ping1DConfig,
+    ) -> Result<(), ManagerError> {
+        self.check_device_status(
+            device_id,
+            &[DeviceStatus::Running, DeviceStatus::ContinuousMode],
+        )?;
+
+        let device = self.get_device(device_id)?;
+        let Some(DeviceProperties::Ping1D(properties)) = &device.properties else {
+            return Err(ManagerError::DeviceSourceError(
+                "set_ping1d_config: Can't set Ping1DConfig".to_string(),
+            ));
+        };
+        let previous_config = *properties
+            .config
+            .read()
+            .map_err(|err| ManagerError::Other(err.to_string()))?;
+
+        let handler = self.extract_handler(self.get_device_handler(device_id).await?)?;
+
+        if let Err(err) = Self::configure_ping1d(&handler, &new_config, device_id).await {
+            error!("Failed to apply Ping1DConfig, restoring previous one: {err:?}, device: {device_id}");
+            if let Err(restore_err) = Self::apply_ping1d_config(&handler, &previous_config).await {
+                error!("Failed to restore Ping1DConfig: {restore_err:?}, device: {device_id}");
+            }
+            return Err(err);
+        }
+
+        *properties
+            .config
+            .write()
+            .map_err(|err| ManagerError::Other(err.to_string()))? = new_config;
+
+        Ok(())
+    }
+
+    pub async fn get_ping1d_config(&self, device_id: Uuid) -> Result<Answer, ManagerError> {
+        let device = self.get_device(device_id)?;
+        if let Some(DeviceProperties::Ping1D(properties)) = &device.properties {
+            return Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping1DConfig(
+                *properties.config.read().map_err(|err| {
+                    ManagerError::Other(format!("get_ping1d_config: {err}, device: {device_id}"))
+                })?,
+            )));
+        }
+        Err(ManagerError::DeviceSourceError(
+            "get_ping1d_config: Can't return Ping1DConfig".to_string(),
+        ))
+    }
+
+    // Apply the settings and read them back to check that the device accepted all of them
+    async fn configure_ping1d(
+        handler: &DeviceActorHandler,
+        config: &Ping1DConfig,
+        device_id: Uuid,
+    ) -> Result<(), ManagerError> {
+        Self::apply_ping1d_config(handler, config).await?;
+
+        let current = Self::read_ping1d_config(handler, device_id).await?;
+        if !config.matches(&current) {
+            return Err(ManagerError::Other(format!(
+                "set_ping1d_config: Device reports {current:?} after applying {config:?}, device: {device_id}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn apply_ping1d_config(
+        handler: &DeviceActorHandler,
+        config: &Ping1DConfig,
+    ) -> Result<(), ManagerError> {
+        let mut requests = vec![
+            Ping1DRequest::SetSpeedOfSound(ping1d::SetSpeedOfSoundStruct {
+                speed_of_sound: config.speed_of_sound,
+            }),
+            Ping1DRequest::SetModeAuto(ping1d::SetModeAutoStruct {
+                mode_auto: config.mode_auto,
+            }),
+        ];
+
+        // Range and gain are chosen by the device on automatic mode
+        if config.mode_auto == 0 {
+            requests.push(Ping1DRequest::SetRange(ping1d::SetRangeStruct {
+                scan_start: config.scan_start,
+                scan_length: config.scan_length,
+            }));
+            requests.push(Ping1DRequest::SetGainSetting(
+                ping1d::SetGainSettingStruct {
+                    gain_setting: config.gain_setting,
+                },
+            ));
+        }
+
+        requests.push(Ping1DRequest::SetPingInterval(
+            ping1d::SetPingIntervalStruct {
+                ping_interval: config.ping_interval,
+            },
+        ));
+
+        for request in requests {
+            handler
+                .send(PingRequest::Ping1D(request))
+                .await
+                .map_err(|err| {
+                    trace!("Something went wrong while applying Ping1DConfig, details: {err:?}");
+                    ManagerError::DeviceError(err)
+                })?;
+        }
+
+        Ok(())
+    }
+
+    // Read the current Ping1D settings
+    pub async fn read_ping1d_config(
+        handler: &DeviceActorHandler,
+        device_id: Uuid,
+    ) -> Result<Ping1DConfig, ManagerError> {
+        let speed_of_sound = match Self::ping1d_request(handler, Ping1DRequest::SpeedOfSound).await? {
+            ping1d::Messages::SpeedOfSound(msg) => msg,
+            unexpected => {
+                return Err(ManagerError::Other(format!(
+                    "Unexpected response while getting speed of sound: {unexpected:?}, device: {device_id}"
+                )))
+            }
+        };
+
+        let general_info = match Self::ping1d_request(handler, Ping1DRequest::GeneralInfo).await? {
+            ping1d::Messages::GeneralInfo(msg) => msg,
+            unexpected => {
+                return Err(ManagerError::Other(format!(
+                    "Unexpected response while getting general info: {unexpected:?}, device: {device_id}"
+                )))
+            }
+        };
+
+        let range = match Self::ping1d_request(handler, Ping1DRequest::Range).await? {
+            ping1d::Messages::Range(msg) => msg,
+            unexpected => {
+                return Err(ManagerError::Other(format!(
+                    "Unexpected response while getting range: {unexpected:?}, device: {device_id}"
+                )))
+            }
+        };
+
+        Ok(Ping1DConfig {
+            speed_of_sound: speed_of_sound.speed_of_sound,
+            mode_auto: general_info.mode_auto,
+            scan_start: range.scan_start,
+            scan_length: range.scan_length,
+            gain_setting: general_info.gain_setting,
+            ping_interval: general_info.ping_interval,
+        })
+    }
+
+    async fn ping1d_request(
+        handler: &DeviceActorHandler,
+        request: Ping1DRequest,
+    ) -> Result<ping1d::Messages, ManagerError> {
+        match handler
+            .send(PingRequest::Ping1D(request))
+            .await
+            .map_err(ManagerError::DeviceError)?
+        {
+            PingAnswer::PingMessage(bluerobotics_ping::Messages::Ping1D(msg)) => Ok(msg),
+            unexpected => Err(ManagerError::Other(format!(
+                "Unexpected answer from Ping1D device, details: {unexpected:?}"
+            ))),
+        }
+    }
+}
+
+impl Ping1DConfig {
+    // Compare with the values reported by the device, range and gain are only fixed on manual mode
+    pub fn matches(&self, current: &Ping1DConfig) -> bool {
+        let manual_settings_match = self.mode_auto != 0
+            || (self.scan_start == current.scan_start
+                && self.scan_length == current.scan_length
+                && self.gain_setting == current.gain_setting);
+
+        self.speed_of_sound == current.speed_of_sound
+            && self.mode_auto == current.mode_auto
+            && self.ping_interval == current.ping_interval
+            && manual_settings_match
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{
+        manager::{DeviceSelection, SourceSelection, SourceSimulatedStruct},
+        simulator::{SimulatedDeviceType, SimulationSettings},
+    };
+
+    async fn ping1d_config(manager: &DeviceManager, device_id: Uuid) -> Ping1DConfig {
+        match manager.get_ping1d_config(device_id).await {
+            Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping1DConfig(config))) => config,
+            answer => panic!("Unexpected answer: {answer:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn ping1d_config_is_applied_and_verified() {
+        let (mut manager, _handler) = DeviceManager::new(10);
+        let source = SourceSelection::Simulated(SourceSimulatedStruct {
+            name: "ping1d_config_is_applied_and_verified".to_string(),
+            device_type: SimulatedDeviceType::Ping1D,
+            settings: SimulationSettings::default(),
+        });
+        let Ok(Answer::DeviceInfo(info)) = manager.create(source, DeviceSelection::Ping1D).await
+        else {
+            panic!("Failed to create simulated Ping1D");
+        };
+        let device_id = info[0].id;
+
+        let config = Ping1DConfig {
+            speed_of_sound: 1_450_000,
+            mode_auto: 0,
+            scan_start: 1000,
+            scan_length: 20000,
+            gain_setting: 3,
+            ping_interval: 50,
+        };
+        manager
+            .update_ping1d_config(device_id, config)
+            .await
+            .unwrap();
+        assert_eq!(ping1d_config(&manager, device_id).await, config);
+
+        let handler = manager
+            .extract_handler(manager.get_device_handler(device_id).await.unwrap())
+            .unwrap();
+        let current = DeviceManager::read_ping1d_config(&handler, device_id)
+            .await
+            .unwrap();
+        assert_eq!(current, config);
+
+        // The simulator refuses empty ranges, so the read back fails and nothing is stored
+        let invalid = Ping1DConfig {
+            scan_length: 0,
+            ..config
+        };
+        assert!(manager
+            .update_ping1d_config(device_id, invalid)
+            .await
+            .is_err());
+        assert_eq!(ping1d_config(&manager, device_id).await, config);
+
+        // Configuration survives continuous mode restarts
+        manager.continuous_mode_off(device_id).await.unwrap();
+        manager.continuous_mode(device_id).await.unwrap();
+        assert_eq!(ping1d_config(&manager, device_id).await, config);
+        let current = DeviceManager::read_ping1d_config(&handler, device_id)
+            .await
+            .unwrap();
+        assert_eq!(current, config);
+    }
+}
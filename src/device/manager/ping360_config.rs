@@ -0,0 +1,206 @@
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
+
+use super::ManagerError;
+
+// Firmware limits, shared by the validation and the scan planning
+pub const MIN_MODE: u8 = 1;
+pub const MAX_MODE: u8 = 1;
+pub const MIN_GAIN_SETTING: u8 = 0;
+pub const MAX_GAIN_SETTING: u8 = 2;
+pub const MIN_TRANSMIT_DURATION_US: u16 = 1;
+pub const MAX_TRANSMIT_DURATION_US: u16 = 1000;
+pub const MIN_SAMPLE_PERIOD_TICKS: u16 = 80;
+pub const MAX_SAMPLE_PERIOD_TICKS: u16 = 40000;
+pub const MIN_TRANSMIT_FREQUENCY_KHZ: u16 = 500;
+pub const MAX_TRANSMIT_FREQUENCY_KHZ: u16 = 1000;
+pub const MIN_NUMBER_OF_SAMPLES: u16 = 200;
+pub const MAX_NUMBER_OF_SAMPLES: u16 = 1200;
+pub const MIN_ANGLE_GRADIANS: u16 = 0;
+pub const MAX_ANGLE_GRADIANS: u16 = 399;
+pub const MIN_NUM_STEPS: u8 = 1;
+pub const MAX_NUM_STEPS: u8 = 10;
+pub const MIN_DELAY_MS: u8 = 0;
+pub const MAX_DELAY_MS: u8 = 100;
+
+// The OpenAPI attributes only take literals, ping360_config_limits checks they match the limits
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Apiv2Schema, Validate)]
+pub struct Ping360Config {
+    /// Operating mode, only 1 is supported by the firmware.
+    #[openapi(minimum = 1, maximum = 1)]
+    #[validate(range(min = MIN_MODE, max = MAX_MODE))]
+    pub mode: u8,
+    /// Analog gain: 0 low, 1 normal and 2 high.
+    #[openapi(minimum = 0, maximum = 2)]
+    #[validate(range(min = MIN_GAIN_SETTING, max = MAX_GAIN_SETTING))]
+    pub gain_setting: u8,
+    /// Acoustic transmission duration, in microseconds.
+    #[openapi(minimum = 1, maximum = 1000)]
+    #[validate(range(min = MIN_TRANSMIT_DURATION_US, max = MAX_TRANSMIT_DURATION_US))]
+    pub transmit_duration: u16,
+    /// Time interval between samples, in 25 ns ticks.
+    #[openapi(minimum = 80, maximum = 40000)]
+    #[validate(range(min = MIN_SAMPLE_PERIOD_TICKS, max = MAX_SAMPLE_PERIOD_TICKS))]
+    pub sample_period: u16,
+    /// Acoustic operating frequency, in kHz.
+    #[openapi(minimum = 500, maximum = 1000)]
+    #[validate(range(min = MIN_TRANSMIT_FREQUENCY_KHZ, max = MAX_TRANSMIT_FREQUENCY_KHZ))]
+    pub transmit_frequency: u16,
+    /// Samples of each ping.
+    #[openapi(minimum = 200, maximum = 1200)]
+    #[validate(range(min = MIN_NUMBER_OF_SAMPLES, max = MAX_NUMBER_OF_SAMPLES))]
+    pub number_of_samples: u16,
+    /// First head angle of the scan, in gradians.
+    #[openapi(minimum = 0, maximum = 399)]
+    #[validate(range(min = MIN_ANGLE_GRADIANS, max = MAX_ANGLE_GRADIANS))]
+    pub start_angle: u16,
+    /// Last head angle of the scan, in gradians, sectors wrap around when lower than start_angle.
+    #[openapi(minimum = 0, maximum = 399)]
+    #[validate(range(min = MIN_ANGLE_GRADIANS, max = MAX_ANGLE_GRADIANS))]
+    pub stop_angle: u16,
+    /// Head steps between pings, in gradians.
+    #[openapi(minimum = 1, maximum = 10)]
+    #[validate(range(min = MIN_NUM_STEPS, max = MAX_NUM_STEPS))]
+    pub num_steps: u8,
+    /// Additional delay between pings, in milliseconds.
+    #[openapi(minimum = 0, maximum = 100)]
+    #[validate(range(min = MIN_DELAY_MS, max = MAX_DELAY_MS))]
+    pub delay: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct InvalidField {
+    pub field: String,
+    pub message: String,
+    pub value: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl From<ValidationErrors> for ManagerError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields: Vec<InvalidField> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, field_errors)| {
+                field_errors.iter().map(move |error| {
+                    let param = |name: &str| error.params.get(name);
+                    let message = match (param("value"), param("min"), param("max")) {
+                        (Some(value), Some(min), Some(max)) => {
+                            format!("{field} should be between {min} and {max}, got {value}")
+                        }
+                        _ => error.code.to_string(),
+                    };
+                    let param = |name: &str| param(name).and_then(|value| value.as_f64());
+                    InvalidField {
+                        field: field.to_string(),
+                        message,
+                        value: param("value"),
+                        min: param("min"),
+                        max: param("max"),
+                    }
+                })
+            })
+            .collect();
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+        ManagerError::InvalidConfig(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping360_config_limits() {
+        let config = Ping360Config {
+            mode: 1,
+            gain_setting: 0,
+            transmit_duration: 11,
+            sample_period: 80,
+            transmit_frequency: 740,
+            number_of_samples: 1200,
+            start_angle: 0,
+            stop_angle: 399,
+            num_steps: 1,
+            delay: 0,
+        };
+        assert!(config.validate().is_ok());
+
+        let invalid = Ping360Config {
+            sample_period: 20,
+            stop_angle: 400,
+            num_steps: 0,
+            ..config
+        };
+        let ManagerError::InvalidConfig(fields) = invalid.validate().unwrap_err().into() else {
+            panic!("Expected an invalid configuration error");
+        };
+        let names: Vec<&str> = fields.iter().map(|field| field.field.as_str()).collect();
+        assert_eq!(names, ["num_steps", "sample_period", "stop_angle"]);
+        assert_eq!(fields[1].value, Some(20.0));
+        assert_eq!(fields[1].min, Some(80.0));
+        assert_eq!(fields[1].max, Some(40_000.0));
+        assert!(fields[2].message.contains("between 0 and 399"));
+
+        // The same limits are exposed on the OpenAPI schema
+        use paperclip::v2::schema::Apiv2Schema as _;
+        let schema = Ping360Config::raw_schema();
+        let sample_period = &schema.properties["sample_period"];
+        assert_eq!(sample_period.minimum, Some(80.0));
+        assert_eq!(sample_period.maximum, Some(40_000.0));
+        assert_eq!(
+            sample_period.description.as_deref(),
+            Some("Time interval between samples, in 25 ns ticks.")
+        );
+        assert_eq!(schema.properties["stop_angle"].maximum, Some(399.0));
+
+        let limits = [
+            ("mode", MIN_MODE as f32, MAX_MODE as f32),
+            (
+                "gain_setting",
+                MIN_GAIN_SETTING as f32,
+                MAX_GAIN_SETTING as f32,
+            ),
+            (
+                "transmit_duration",
+                MIN_TRANSMIT_DURATION_US as f32,
+                MAX_TRANSMIT_DURATION_US as f32,
+            ),
+            (
+                "sample_period",
+                MIN_SAMPLE_PERIOD_TICKS as f32,
+                MAX_SAMPLE_PERIOD_TICKS as f32,
+            ),
+            (
+                "transmit_frequency",
+                MIN_TRANSMIT_FREQUENCY_KHZ as f32,
+                MAX_TRANSMIT_FREQUENCY_KHZ as f32,
+            ),
+            (
+                "number_of_samples",
+                MIN_NUMBER_OF_SAMPLES as f32,
+                MAX_NUMBER_OF_SAMPLES as f32,
+            ),
+            (
+                "start_angle",
+                MIN_ANGLE_GRADIANS as f32,
+                MAX_ANGLE_GRADIANS as f32,
+            ),
+            (
+                "stop_angle",
+                MIN_ANGLE_GRADIANS as f32,
+                MAX_ANGLE_GRADIANS as f32,
+            ),
+            ("num_steps", MIN_NUM_STEPS as f32, MAX_NUM_STEPS as f32),
+            ("delay", MIN_DELAY_MS as f32, MAX_DELAY_MS as f32),
+        ];
+        assert_eq!(limits.len(), schema.properties.len());
+        for (field, min, max) in limits {
+            let property = &schema.properties[field];
+            assert_eq!((property.minimum, property.maximum), (Some(min), Some(max)));
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use super::{
+    ping360_config::{
+        MAX_NUMBER_OF_SAMPLES, MAX_SAMPLE_PERIOD_TICKS, MIN_NUMBER_OF_SAMPLES,
+        MIN_SAMPLE_PERIOD_TICKS,
+    },
+    Answer, DeviceManager, DeviceProperties, ManagerError, ModifyDeviceResult, Ping360Config,
+};
+
+// Transmit duration limits used by Ping Viewer, inside the firmware ones
+static SAMPLE_PERIOD_TICK_S: f32 = 25e-9;
+static MIN_TRANSMIT_DURATION_US: f32 = 5.0;
+static MAX_TRANSMIT_DURATION_US: f32 = 500.0;
+static SPEED_OF_SOUND_LIMITS: (f32, f32) = (1000.0, 2000.0);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct Ping360RangeConfig {
+    /// Desired range, in meters.
+    pub range_m: f32,
+    /// Speed of sound in water, in meters per second.
+    #[serde(default = "default_speed_of_sound")]
+    pub speed_of_sound: f32,
+    /// Desired distance between samples, in meters. The finest resolution is used when not set.
+    #[serde(default)]
+    pub resolution_m: Option<f32>,
+}
+
+fn default_speed_of_sound() -> f32 {
+    1500.0
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct Ping360RangeResult {
+    /// Device configuration with the computed sample period, transmit duration and number of samples.
+    pub config: Ping360Config,
+    /// Range reached with the firmware parameters, in meters.
+    pub range_m: f32,
+    /// Distance between samples, in meters.
+    pub resolution_m: f32,
+}
+
+// Two way travel time of a sample, in ticks of 25 ns
+fn sample_period_ticks(range_m: f32, number_of_samples: u16, speed_of_sound: f32) -> f32 {
+    2.0 * range_m / (number_of_samples as f32 * speed_of_sound * SAMPLE_PERIOD_TICK_S)
+}
+
+pub fn effective_range(config: &Ping360Config, speed_of_sound: f32) -> f32 {
+    config.number_of_samples as f32
+        * config.sample_period as f32
+        * SAMPLE_PERIOD_TICK_S
+        * speed_of_sound
+        / 2.0
+}
+
+// Sample period, number of samples and transmit duration for a range, following Ping Viewer rules
+pub fn range_config(
+    current: &Ping360Config,
+    request: &Ping360RangeConfig,
+) -> Result<Ping360RangeResult, ManagerError> {
+    let Ping360RangeConfig {
+        range_m,
+        speed_of_sound,
+        resolution_m,
+    } = *request;
+
+    let (min_speed, max_speed) = SPEED_OF_SOUND_LIMITS;
+    if !(min_speed..=max_speed).contains(&speed_of_sound) {
+        return Err(ManagerError::Other(format!(
+            "set_ping360_range: Speed of sound should be between {min_speed} and {max_speed} m/s, got {speed_of_sound}"
+        )));
+    }
+    if !(range_m.is_finite() && range_m > 0.0) {
+        return Err(ManagerError::Other(format!(
+            "set_ping360_range: Invalid range: {range_m}"
+        )));
+    }
+
+    let (min_samples, max_samples) = (MIN_NUMBER_OF_SAMPLES, MAX_NUMBER_OF_SAMPLES);
+    let (min_sample_period, max_sample_period) = (MIN_SAMPLE_PERIOD_TICKS, MAX_SAMPLE_PERIOD_TICKS);
+
+    // Points are maximized, unless a coarser resolution was requested
+    let mut number_of_samples = match resolution_m {
+        Some(resolution_m) if resolution_m.is_finite() && resolution_m > 0.0 => {
+            ((range_m / resolution_m).ceil() as u16).clamp(min_samples, max_samples)
+        }
+        Some(resolution_m) => {
+            return Err(ManagerError::Other(format!(
+                "set_ping360_range: Invalid resolution: {resolution_m}"
+            )))
+        }
+        None => max_samples,
+    };
+
+    // Short ranges need fewer samples to respect the minimum sample period
+    while number_of_samples > min_samples
+        && sample_period_ticks(range_m, number_of_samples, speed_of_sound)
+            < min_sample_period as f32
+    {
+        number_of_samples -= 1;
+    }
+
+    let sample_period = sample_period_ticks(range_m, number_of_samples, speed_of_sound).round();
+    if !(min_sample_period as f32..=max_sample_period as f32).contains(&sample_period) {
+        let range_limit = |samples: u16, sample_period: u16| {
+            samples as f32 * sample_period as f32 * SAMPLE_PERIOD_TICK_S * speed_of_sound / 2.0
+        };
+        return Err(ManagerError::Other(format!(
+            "set_ping360_range: Range {range_m} m is out of the device limits, from {} to {} m at {speed_of_sound} m/s",
+            range_limit(min_samples, min_sample_period),
+            range_limit(max_samples, max_sample_period),
+        )));
+    }
+    let sample_period = sample_period as u16;
+
+    // Per firmware engineer: 8000 us per one way meter over the speed of sound, wide enough for
+    // 2.5 sample periods and limited to 64 sample periods
+    let sample_period_us = sample_period as f32 * SAMPLE_PERIOD_TICK_S * 1e6;
+    let max_transmit_duration = MAX_TRANSMIT_DURATION_US.min(64.0 * sample_period_us);
+    let transmit_duration = (8000.0 * range_m / speed_of_sound)
+        .round()
+        .max(2.5 * sample_period_us)
+        .min(max_transmit_duration)
+        .max(MIN_TRANSMIT_DURATION_US)
+        .round() as u16;
+
+    let config = Ping360Config {
+        sample_period,
+        number_of_samples,
+        transmit_duration,
+        ..*current
+    };
+    let range_m = effective_range(&config, speed_of_sound);
+
+    Ok(Ping360RangeResult {
+        config,
+        range_m,
+        resolution_m: range_m / number_of_samples as f32,
+    })
+}
+
+impl DeviceManager {
+    // Replace the timing parameters of the Ping360 continuous mode configuration from a range
+    pub async fn set_ping360_range(
+        &self,
+        device_id: Uuid,
+        request: Ping360RangeConfig,
+    ) -> Result<Answer, ManagerError> {
+        let device = self.get_device(device_id)?;
+        let Some(DeviceProperties::Ping360(properties)) = &device.properties else {
+            return Err(ManagerError::DeviceSourceError(
+                "set_ping360_range: Can't set Ping360Config".to_string(),
+            ));
+        };
+
+        let current = *properties
+            .continuous_mode_settings
+            .read()
+            .map_err(|err| ManagerError::Other(format!("set_ping360_range: {err}")))?;
+        let result = range_config(&current, &request)?;
+
+        self.update_ping360_config(device_id, result.config).await?;
+        info!("Ping360 range set: {result:?}, device: {device_id}");
+
+        Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping360Range(
+            result,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Ping360Config {
+        Ping360Config {
+            mode: 1,
+            gain_setting: 0,
+            transmit_duration: 11,
+            sample_period: 80,
+            transmit_frequency: 740,
+            number_of_samples: 1200,
+            start_angle: 0,
+            stop_angle: 399,
+            num_steps: 1,
+            delay: 0,
+        }
+    }
+
+    fn range(range_m: f32, resolution_m: Option<f32>) -> Result<Ping360RangeResult, ManagerError> {
+        range_config(
+            &config(),
+            &Ping360RangeConfig {
+                range_m,
+                speed_of_sound: 1500.0,
+                resolution_m,
+            },
+        )
+    }
+
+    #[test]
+    fn long_ranges_use_all_samples() {
+        let result = range(50.0, None).unwrap();
+        assert_eq!(result.config.number_of_samples, 1200);
+        assert_eq!(result.config.sample_period, 2222);
+        // 8000 * 50 / 1500 = 267 us, wider than 2.5 sample periods of 55.55 us
+        assert_eq!(result.config.transmit_duration, 267);
+        assert!((result.range_m - 50.0).abs() < 0.01);
+        // Other settings are kept
+        assert_eq!(result.config.transmit_frequency, 740);
+    }
+
+    #[test]
+    fn short_ranges_reduce_samples() {
+        let result = range(1.0, None).unwrap();
+        assert_eq!(result.config.sample_period, 80);
+        assert_eq!(result.config.number_of_samples, 666);
+        // 2.5 sample periods of 2 us, over 8000 * 1 / 1500 = 5.33 us
+        assert_eq!(result.config.transmit_duration, 5);
+        assert!((result.range_m - 0.999).abs() < 0.01);
+    }
+
+    #[test]
+    fn resolution_and_limits() {
+        let result = range(20.0, Some(0.1)).unwrap();
+        assert_eq!(result.config.number_of_samples, 200);
+        assert!((result.resolution_m - 0.1).abs() < 0.001);
+
+        assert!(range(1000.0, None).is_err());
+        assert!(range(0.1, None).is_err());
+        assert!(range(-1.0, None).is_err());
+        assert!(range(10.0, Some(0.0)).is_err());
+        assert!(range_config(
+            &config(),
+            &Ping360RangeConfig {
+                range_m: 10.0,
+                speed_of_sound: 340.0,
+                resolution_m: None,
+            }
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,361 @@
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::RwLock};
+use tracing::info;
+use uuid::Uuid;
+
+use super::{
+    ping360_config::{MAX_ANGLE_GRADIANS, MAX_NUM_STEPS, MIN_ANGLE_GRADIANS, MIN_NUM_STEPS},
+    Answer, DeviceManager, DeviceProperties, ManagerError, ModifyDeviceResult, Ping360Config,
+};
+
+static GRADIANS: i32 = 400;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct Ping360ScanProfile {
+    pub name: String,
+    /// First head angle of the sector, in gradians.
+    #[openapi(minimum = 0, maximum = 399)]
+    pub start_angle: u16,
+    /// Last head angle of the sector, in gradians, sectors wrap around when lower than start_angle.
+    #[openapi(minimum = 0, maximum = 399)]
+    pub stop_angle: u16,
+    /// Head steps between pings, in gradians.
+    #[openapi(minimum = 1, maximum = 10)]
+    pub num_steps: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct Ping360ScanStep {
+    /// Name of the profile scanned on this step.
+    pub profile: String,
+    /// Sweeps over the profile sector before moving to the next step.
+    #[openapi(minimum = 1)]
+    pub sweeps: u32,
+}
+
+/// Named sectors of a Ping360 and the pattern alternating between them, the pattern repeats forever.
+/// While the pattern is empty the sector of the Ping360Config is scanned.
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct Ping360ScanSchedule {
+    pub profiles: Vec<Ping360ScanProfile>,
+    #[serde(default)]
+    pub pattern: Vec<Ping360ScanStep>,
+}
+
+impl Default for Ping360ScanSchedule {
+    fn default() -> Self {
+        let profile = |name: &str, start_angle, stop_angle| Ping360ScanProfile {
+            name: name.to_string(),
+            start_angle,
+            stop_angle,
+            num_steps: 1,
+        };
+
+        Self {
+            profiles: vec![
+                profile("full_circle", 0, 399),
+                // 90 degrees ahead of the head
+                profile("forward_sector", 350, 50),
+                // 36 degrees ahead of the head
+                profile("narrow_track", 380, 20),
+            ],
+            pattern: Vec::new(),
+        }
+    }
+}
+
+impl Ping360ScanSchedule {
+    pub fn validate(&self) -> Result<(), ManagerError> {
+        let mut names = HashSet::new();
+        for profile in &self.profiles {
+            let name = &profile.name;
+            if name.is_empty() || !names.insert(name.as_str()) {
+                return Err(ManagerError::Other(format!(
+                    "set_ping360_scan_schedule: Profile names should be unique and not empty, got: {name:?}"
+                )));
+            }
+
+            let (min_angle, max_angle) = (MIN_ANGLE_GRADIANS, MAX_ANGLE_GRADIANS);
+            for angle in [profile.start_angle, profile.stop_angle] {
+                if !(min_angle..=max_angle).contains(&angle) {
+                    return Err(ManagerError::Other(format!(
+                        "set_ping360_scan_schedule: Profile {name}: angles should be between {min_angle} and {max_angle}, got {angle}"
+                    )));
+                }
+            }
+
+            let (min_steps, max_steps) = (MIN_NUM_STEPS, MAX_NUM_STEPS);
+            if !(min_steps..=max_steps).contains(&profile.num_steps) {
+                return Err(ManagerError::Other(format!(
+                    "set_ping360_scan_schedule: Profile {name}: num_steps should be between {min_steps} and {max_steps}, got {}",
+                    profile.num_steps
+                )));
+            }
+        }
+
+        for step in &self.pattern {
+            if !names.contains(step.profile.as_str()) {
+                return Err(ManagerError::Other(format!(
+                    "set_ping360_scan_schedule: Unknown profile on pattern: {}",
+                    step.profile
+                )));
+            }
+            if step.sweeps == 0 {
+                return Err(ManagerError::Other(format!(
+                    "set_ping360_scan_schedule: Profile {} should be scanned at least once",
+                    step.profile
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn profile(&self, name: &str) -> Option<&Ping360ScanProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+}
+
+// Count sweeps from the head angles, a sweep ends when the head turns back or restarts the sector
+#[derive(Debug, Default)]
+pub struct SweepCounter {
+    previous: Option<i32>,
+    backwards: bool,
+}
+
+impl SweepCounter {
+    pub fn update(&mut self, config: &Ping360Config, angle: u16) -> bool {
+        // Position inside the sector, 0 at start_angle, so wrap-around sectors work the same way
+        let linear = |angle: u16| (angle as i32 - config.start_angle as i32).rem_euclid(GRADIANS);
+        let sector_len = linear(config.stop_angle);
+        let position = linear(angle);
+        // Late pings from a previous sector
+        if position > sector_len {
+            return false;
+        }
+
+        let Some(previous) = self.previous.replace(position) else {
+            return false;
+        };
+        let delta = position - previous;
+        if delta == 0 {
+            return false;
+        }
+
+        let reversed = (delta < 0) != self.backwards;
+        if reversed {
+            // Sectors restarted from the beginning keep going forward, like full circles
+            self.backwards = delta < 0 && position > config.num_steps as i32;
+        }
+        reversed
+    }
+}
+
+// Keep track of the pattern step, the profile sector replaces the one of the Ping360Config
+#[derive(Debug)]
+pub struct ScanScheduler {
+    pub schedule: Ping360ScanSchedule,
+    step: usize,
+    sweeps: u32,
+    counter: SweepCounter,
+}
+
+impl ScanScheduler {
+    pub fn new(schedule: Ping360ScanSchedule) -> Self {
+        Self {
+            schedule,
+            step: 0,
+            sweeps: 0,
+            counter: SweepCounter::default(),
+        }
+    }
+
+    // Restart the pattern when the stored schedule changed, returns true when it did
+    pub fn sync(&mut self, stored: &RwLock<Ping360ScanSchedule>) -> bool {
+        let Ok(stored) = stored.read() else {
+            return false;
+        };
+        if *stored == self.schedule {
+            return false;
+        }
+
+        *self = Self::new(stored.clone());
+        true
+    }
+
+    pub fn profile(&self) -> Option<&Ping360ScanProfile> {
+        let step = self.schedule.pattern.get(self.step)?;
+        self.schedule.profile(&step.profile)
+    }
+
+    pub fn apply(&self, config: &Ping360Config) -> Ping360Config {
+        match self.profile() {
+            Some(profile) => Ping360Config {
+                start_angle: profile.start_angle,
+                stop_angle: profile.stop_angle,
+                num_steps: profile.num_steps,
+                ..*config
+            },
+            None => *config,
+        }
+    }
+
+    // Register a ping of the scanned config, returns true when the next profile should be scanned
+    pub fn ping(&mut self, scanned: &Ping360Config, angle: u16) -> bool {
+        let Some(step) = self.schedule.pattern.get(self.step) else {
+            return false;
+        };
+        if !self.counter.update(scanned, angle) {
+            return false;
+        }
+
+        self.sweeps += 1;
+        if self.sweeps < step.sweeps {
+            return false;
+        }
+
+        let previous = self.profile().map(|profile| profile.name.clone());
+        self.step = (self.step + 1) % self.schedule.pattern.len();
+        self.sweeps = 0;
+        self.counter = SweepCounter::default();
+        previous != self.profile().map(|profile| profile.name.clone())
+    }
+}
+
+impl DeviceManager {
+    pub async fn set_ping360_scan_schedule(
+        &self,
+        device_id: Uuid,
+        schedule: Ping360ScanSchedule,
+    ) -> Result<(), ManagerError> {
+        schedule.validate()?;
+
+        let device = self.get_device(device_id)?;
+        let Some(DeviceProperties::Ping360(properties)) = &device.properties else {
+            return Err(ManagerError::DeviceSourceError(
+                "set_ping360_scan_schedule: Can't set Ping360ScanSchedule".to_string(),
+            ));
+        };
+
+        *properties
+            .scan_schedule
+            .write()
+            .map_err(|err| ManagerError::Other(err.to_string()))? = schedule;
+        info!("Ping360 scan schedule updated, device: {device_id}");
+
+        Ok(())
+    }
+
+    pub async fn get_ping360_scan_schedule(&self, device_id: Uuid) -> Result<Answer, ManagerError> {
+        let device = self.get_device(device_id)?;
+        let Some(DeviceProperties::Ping360(properties)) = &device.properties else {
+            return Err(ManagerError::DeviceSourceError(
+                "get_ping360_scan_schedule: Can't return Ping360ScanSchedule".to_string(),
+            ));
+        };
+
+        let schedule = properties
+            .scan_schedule
+            .read()
+            .map_err(|err| {
+                ManagerError::Other(format!(
+                    "get_ping360_scan_schedule: {err}, device: {device_id}"
+                ))
+            })?
+            .clone();
+        Ok(Answer::DeviceConfig(
+            ModifyDeviceResult::Ping360ScanSchedule(schedule),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(start_angle: u16, stop_angle: u16) -> Ping360Config {
+        Ping360Config {
+            mode: 1,
+            gain_setting: 0,
+            transmit_duration: 11,
+            sample_period: 80,
+            transmit_frequency: 740,
+            number_of_samples: 1200,
+            start_angle,
+            stop_angle,
+            num_steps: 1,
+            delay: 0,
+        }
+    }
+
+    fn count_sweeps(config: &Ping360Config, angles: impl IntoIterator<Item = u16>) -> usize {
+        let mut counter = SweepCounter::default();
+        angles
+            .into_iter()
+            .filter(|&angle| counter.update(config, angle))
+            .count()
+    }
+
+    #[test]
+    fn sweeps_are_counted_for_every_scan_kind() {
+        // Back and forth over a wrap-around sector
+        let sector = config(350, 50);
+        let forward = (350..400).chain(0..=50);
+        let angles = forward.clone().chain(forward.clone().rev()).chain(forward);
+        assert_eq!(count_sweeps(&sector, angles), 2);
+
+        // Sector restarted from the beginning, like auto transmit
+        let angles = (0..3).flat_map(|_| (350..400).chain(0..=50));
+        assert_eq!(count_sweeps(&sector, angles), 2);
+
+        // Full circle turns and late pings from another sector
+        let circle = config(0, 399);
+        assert_eq!(count_sweeps(&circle, (0..3).flat_map(|_| 0..400)), 2);
+        assert_eq!(count_sweeps(&sector, [360, 200, 361, 362]), 0);
+    }
+
+    #[test]
+    fn scheduler_alternates_profiles() {
+        let schedule = Ping360ScanSchedule {
+            pattern: vec![
+                Ping360ScanStep {
+                    profile: "forward_sector".to_string(),
+                    sweeps: 2,
+                },
+                Ping360ScanStep {
+                    profile: "full_circle".to_string(),
+                    sweeps: 1,
+                },
+            ],
+            ..Default::default()
+        };
+        schedule.validate().unwrap();
+
+        let mut scheduler = ScanScheduler::new(schedule);
+        let scanned = scheduler.apply(&config(0, 399));
+        assert_eq!((scanned.start_angle, scanned.stop_angle), (350, 50));
+
+        let forward = (350..400).chain(0..=50);
+        let mut switches = forward
+            .clone()
+            .chain(forward.clone().rev())
+            .chain(forward)
+            .filter(|&angle| scheduler.ping(&scanned, angle));
+        assert!(switches.next().is_some());
+        assert_eq!(scheduler.profile().unwrap().name, "full_circle");
+
+        let scanned = scheduler.apply(&config(0, 399));
+        assert!((0..400)
+            .chain(0..2)
+            .any(|angle| scheduler.ping(&scanned, angle)));
+        assert_eq!(scheduler.profile().unwrap().name, "forward_sector");
+
+        let mut invalid = Ping360ScanSchedule::default();
+        invalid.pattern.push(Ping360ScanStep {
+            profile: "unknown".to_string(),
+            sweeps: 1,
+        });
+        assert!(invalid.validate().is_err());
+    }
+}
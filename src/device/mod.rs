@@ -4,6 +4,10 @@
 /// The `DeviceHandler` can forward requests defined in the `PingRequest` enum.
 pub mod devices;
 
+/// The `export` module converts Ping360 recordings into georeferenced points, using the
+/// recorded vehicle poses and the sonar mounting offsets.
+pub mod export;
+
 /// The `manager` module provides the `Manager` and `ManagerHandler` structures.
 ///
 /// The `Manager` can handle requests from multiple threads. The `ManagerHandler`
@@ -14,6 +18,26 @@ pub mod devices;
 /// and made available again.
 pub mod manager;
 
+/// The `playback` module replays MCAP recordings, exposing them as virtual devices
+/// with original timing and pause, seek and speed controls.
+pub mod playback;
+
+/// The `simulator` module generates synthetic Ping1D and Ping360 data, answering
+/// protocol requests like a real device so it can be used without hardware.
+pub mod simulator;
+
+/// The `output` module forwards device measurements to external consumers, like
+/// NMEA 0183 depth sentences over UDP or serial ports.
+pub mod output;
+
 /// The `recording` module provides functionalities for recording device measurements
 /// and managing current recording sessions.
 pub mod recording;
+
+/// The `sonar_image` module renders the Ping360 scans of a device as a Cartesian image,
+/// updated for every received angle.
+pub mod sonar_image;
+
+/// The `virtual_device` module holds helpers shared by devices that exist only in software,
+/// like recordings playback and simulators.
+pub mod virtual_device;
@@ -0,0 +1,258 @@
+use ::mavlink::{
+    ardupilotmega::{MavDistanceSensor, MavMessage, MavSensorOrientation, DISTANCE_SENSOR_DATA},
+    MavHeader,
+};
+use bluerobotics_ping::message::ProtocolMessage;
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::broadcast::{error::RecvError, Receiver},
+    time::Instant,
+};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use super::{DepthMeasurement, MeasurementSource, OutputDestination, OutputWriter};
+use crate::device::manager::ManagerError;
+
+// Ping1D maximum range, used while DistanceSimple messages don't report the scan range
+static DEFAULT_SCAN_RANGE_MM: (u32, u32) = (0, 50_000);
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub enum MavlinkDestination {
+    /// Publish to the zenoh topic used by mavlink-server to receive messages.
+    Zenoh(MavlinkZenohStruct),
+    /// Send MAVLink v2 frames directly to a UDP endpoint or serial port.
+    Direct(OutputDestination),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub struct MavlinkZenohStruct {
+    pub topic: String,
+}
+
+impl Default for MavlinkZenohStruct {
+    fn default() -> Self {
+        Self {
+            topic: "mavlink/in".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub struct MavlinkOutputConfig {
+    pub destination: MavlinkDestination,
+    #[serde(default = "default_system_id")]
+    pub system_id: u8,
+    /// Defaults to MAV_COMP_ID_ONBOARD_COMPUTER.
+    #[serde(default = "default_component_id")]
+    pub component_id: u8,
+    /// Onboard id of the sensor, allows multiple rangefinders on the same vehicle.
+    #[serde(default)]
+    pub sensor_id: u8,
+    /// Measurements with lower confidence, in percent, are not sent.
+    #[serde(default)]
+    pub min_confidence: u8,
+}
+
+fn default_system_id() -> u8 {
+    1
+}
+
+fn default_component_id() -> u8 {
+    191
+}
+
+impl MavlinkOutputConfig {
+    pub fn validate(&self) -> Result<(), ManagerError> {
+        if let MavlinkDestination::Zenoh(zenoh) = &self.destination {
+            if zenoh.topic.trim().is_empty() {
+                return Err(ManagerError::Other(
+                    "mavlink_output: Zenoh topic can't be empty".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Downward facing DISTANCE_SENSOR, ranges are in centimeters and confidence is the signal quality
+pub fn distance_sensor(
+    config: &MavlinkOutputConfig,
+    measurement: &DepthMeasurement,
+    scan_range_mm: (u32, u32),
+    time_boot_ms: u32,
+) -> Option<MavMessage> {
+    if measurement.confidence < config.min_confidence {
+        return None;
+    }
+
+    let centimeters = |millimeters: u32| (millimeters / 10).min(u16::MAX as u32) as u16;
+    let (scan_start, scan_end) = scan_range_mm;
+
+    Some(MavMessage::DISTANCE_SENSOR(DISTANCE_SENSOR_DATA {
+        time_boot_ms,
+        min_distance: centimeters(scan_start),
+        max_distance: centimeters(scan_end),
+        current_distance: centimeters(measurement.distance_mm),
+        mavtype: MavDistanceSensor::MAV_DISTANCE_SENSOR_ULTRASOUND,
+        id: config.sensor_id,
+        orientation: MavSensorOrientation::MAV_SENSOR_ROTATION_PITCH_270,
+        covariance: u8::MAX,
+        // 0 means unknown and 1 invalid, a valid reading is at least 2
+        signal_quality: measurement.confidence.clamp(2, 100),
+        ..Default::default()
+    }))
+}
+
+#[derive(Serialize)]
+struct MavlinkJson<'a> {
+    header: MavHeader,
+    message: &'a MavMessage,
+}
+
+enum MavlinkWriter {
+    Zenoh(zenoh::Session, String),
+    Direct(OutputWriter),
+}
+
+impl MavlinkWriter {
+    async fn open(destination: &MavlinkDestination, device_id: Uuid) -> Result<Self, ManagerError> {
+        match destination {
+            MavlinkDestination::Direct(destination) => {
+                Ok(Self::Direct(OutputWriter::open(destination).await?))
+            }
+            MavlinkDestination::Zenoh(zenoh) => {
+                let config = crate::vehicle::make_default_config(
+                    env!("CARGO_PKG_NAME"),
+                    &crate::cli::manager::zenoh_settings(),
+                );
+                let session = zenoh::open(config).await.map_err(|err| {
+                    ManagerError::Other(format!("mavlink_output: Zenoh session error: {err}"))
+                })?;
+                info!(
+                    "MAVLink output connected to zenoh, topic: {}, device: {device_id}",
+                    zenoh.topic
+                );
+                Ok(Self::Zenoh(session, zenoh.topic.clone()))
+            }
+        }
+    }
+
+    async fn write(&mut self, header: MavHeader, message: &MavMessage) -> Result<(), String> {
+        match self {
+            Self::Zenoh(session, topic) => {
+                let payload = serde_json::to_string(&MavlinkJson { header, message })
+                    .map_err(|err| err.to_string())?;
+                session
+                    .put(topic.as_str(), payload)
+                    .await
+                    .map_err(|err| err.to_string())
+            }
+            Self::Direct(writer) => {
+                let mut frame = Vec::new();
+                ::mavlink::write_v2_msg(&mut frame, header, message)
+                    .map_err(|err| err.to_string())?;
+                writer.write(&frame).await.map_err(|err| err.to_string())
+            }
+        }
+    }
+}
+
+// Publish the device measurements as DISTANCE_SENSOR until the device stream closes
+pub async fn start_mavlink_output(
+    device_id: Uuid,
+    config: MavlinkOutputConfig,
+    mut subscriber: Receiver<ProtocolMessage>,
+) -> Result<tokio::task::JoinHandle<()>, ManagerError> {
+    config.validate()?;
+    // Connections are opened here so configuration errors reach the caller
+    let mut writer = MavlinkWriter::open(&config.destination, device_id).await?;
+
+    Ok(tokio::spawn(async move {
+        let mut source = MeasurementSource::default();
+        let start = Instant::now();
+        let mut sequence: u8 = 0;
+        let mut scan_range_mm = DEFAULT_SCAN_RANGE_MM;
+
+        loop {
+            let message = match subscriber.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("MAVLink output skipped {skipped} messages, device: {device_id}");
+                    continue;
+                }
+                Err(RecvError::Closed) => {
+                    debug!("MAVLink output stopped, device stream closed, device: {device_id}");
+                    break;
+                }
+            };
+
+            let Some(measurement) = source.measurement(&message) else {
+                continue;
+            };
+            if let Some(range) = measurement.scan_range_mm {
+                scan_range_mm = range;
+            }
+
+            let time_boot_ms = start.elapsed().as_millis() as u32;
+            let Some(distance_sensor) =
+                distance_sensor(&config, &measurement, scan_range_mm, time_boot_ms)
+            else {
+                continue;
+            };
+
+            let header = MavHeader {
+                system_id: config.system_id,
+                component_id: config.component_id,
+                sequence,
+            };
+            sequence = sequence.wrapping_add(1);
+
+            if let Err(err) = writer.write(header, &distance_sensor).await {
+                error!("MAVLink output failed to send DISTANCE_SENSOR: {err}, device: {device_id}");
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_sensor_from_measurement() {
+        let config = MavlinkOutputConfig {
+            destination: MavlinkDestination::Zenoh(MavlinkZenohStruct::default()),
+            system_id: default_system_id(),
+            component_id: default_component_id(),
+            sensor_id: 1,
+            min_confidence: 50,
+        };
+        let mut measurement = DepthMeasurement {
+            distance_mm: 2345,
+            confidence: 87,
+            scan_range_mm: Some((500, 10500)),
+        };
+
+        let Some(MavMessage::DISTANCE_SENSOR(data)) =
+            distance_sensor(&config, &measurement, (500, 10500), 42)
+        else {
+            panic!("DISTANCE_SENSOR expected");
+        };
+        assert_eq!(data.time_boot_ms, 42);
+        assert_eq!(data.min_distance, 50);
+        assert_eq!(data.max_distance, 1050);
+        assert_eq!(data.current_distance, 234);
+        assert_eq!(data.id, 1);
+        assert_eq!(
+            data.orientation,
+            MavSensorOrientation::MAV_SENSOR_ROTATION_PITCH_270
+        );
+        assert_eq!(data.signal_quality, 87);
+
+        measurement.confidence = 10;
+        assert!(distance_sensor(&config, &measurement, (500, 10500), 42).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use bluerobotics_ping::{message::ProtocolMessage, ping1d, Messages};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::{Duration, Instant},
+};
+use tokio::{io::AsyncWriteExt, net::UdpSocket};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::device::manager::ManagerError;
+
+// Another message type is used as measurement source once the current one stops for this long
+static MEASUREMENT_SOURCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// MAVLink DISTANCE_SENSOR messages built from Ping1D measurements.
+pub mod mavlink;
+/// NMEA 0183 depth sentences built from Ping1D measurements.
+pub mod nmea;
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub enum OutputDestination {
+    Udp(OutputUdpStruct),
+    Serial(OutputSerialStruct),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub struct OutputUdpStruct {
+    pub ip: Ipv4Addr,
+    pub port: u16,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub struct OutputSerialStruct {
+    pub path: String,
+    pub baudrate: u32,
+}
+
+// Connection used to deliver data to an output destination
+pub enum OutputWriter {
+    Udp(UdpSocket, SocketAddr),
+    Serial(SerialStream),
+}
+
+impl OutputWriter {
+    pub async fn open(destination: &OutputDestination) -> Result<Self, ManagerError> {
+        match destination {
+            OutputDestination::Udp(udp) => {
+                let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+                    .await
+                    .map_err(|err| ManagerError::DeviceSourceError(err.to_string()))?;
+                // Allow sending to the whole network, like most NMEA consumers expect
+                socket
+                    .set_broadcast(true)
+                    .map_err(|err| ManagerError::DeviceSourceError(err.to_string()))?;
+                Ok(Self::Udp(
+                    socket,
+                    SocketAddrV4::new(udp.ip, udp.port).into(),
+                ))
+            }
+            OutputDestination::Serial(serial) => {
+                let stream = tokio_serial::new(&serial.path, serial.baudrate)
+                    .open_native_async()
+                    .map_err(|err| ManagerError::DeviceSourceError(err.to_string()))?;
+                Ok(Self::Serial(stream))
+            }
+        }
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::Udp(socket, address) => socket.send_to(data, *address).await.map(|_| ()),
+            Self::Serial(stream) => stream.write_all(data).await,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthMeasurement {
+    pub distance_mm: u32,
+    /// Confidence in percent.
+    pub confidence: u8,
+    /// Scan start and end in millimeters, not reported by DistanceSimple messages.
+    pub scan_range_mm: Option<(u32, u32)>,
+}
+
+impl DepthMeasurement {
+    // Extract the distance from Ping1D Distance, DistanceSimple and Profile messages,
+    // a distance of zero means no bottom was found
+    pub fn from_message(message: &ProtocolMessage) -> Option<Self> {
+        Self::from_ping1d_message(message).filter(|measurement| measurement.distance_mm > 0)
+    }
+
+    fn from_ping1d_message(message: &ProtocolMessage) -> Option<Self> {
+        let Ok(Messages::Ping1D(message)) = Messages::try_from(message) else {
+            return None;
+        };
+
+        match message {
+            ping1d::Messages::Distance(distance) => Some(Self {
+                distance_mm: distance.distance,
+                confidence: distance.confidence.min(100) as u8,
+                scan_range_mm: Some((
+                    distance.scan_start,
+                    distance.scan_start + distance.scan_length,
+                )),
+            }),
+            ping1d::Messages::DistanceSimple(distance) => Some(Self {
+                distance_mm: distance.distance,
+                confidence: distance.confidence.min(100),
+                scan_range_mm: None,
+            }),
+            ping1d::Messages::Profile(profile) => Some(Self {
+                distance_mm: profile.distance,
+                confidence: profile.confidence.min(100) as u8,
+                scan_range_mm: Some((profile.scan_start, profile.scan_start + profile.scan_length)),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Measurements from a single message type, so each reading is sent once when the device
+// streams more than one of Distance, DistanceSimple and Profile
+#[derive(Debug, Default)]
+pub struct MeasurementSource {
+    current: Option<(u16, Instant)>,
+}
+
+impl MeasurementSource {
+    pub fn measurement(&mut self, message: &ProtocolMessage) -> Option<DepthMeasurement> {
+        let measurement = DepthMeasurement::from_message(message)?;
+        let now = Instant::now();
+        if let Some((message_id, last_seen)) = self.current {
+            if message_id != message.message_id
+                && now.duration_since(last_seen) < MEASUREMENT_SOURCE_TIMEOUT
+            {
+                return None;
+            }
+        }
+        self.current = Some((message.message_id, now));
+        Some(measurement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bluerobotics_ping::message::MessageInfo;
+
+    fn protocol_message(message: ping1d::Messages) -> ProtocolMessage {
+        let mut protocol_message = ProtocolMessage::new();
+        protocol_message.set_message(&message);
+        protocol_message
+    }
+
+    #[test]
+    fn measurements_from_a_single_message_type() {
+        let distance = |distance| {
+            protocol_message(ping1d::Messages::Distance(ping1d::DistanceStruct {
+                distance,
+                confidence: 100,
+                ..Default::default()
+            }))
+        };
+        let profile = protocol_message(ping1d::Messages::Profile(ping1d::ProfileStruct {
+            distance: 1500,
+            confidence: 100,
+            ..Default::default()
+        }));
+        assert_eq!(distance(0).message_id, ping1d::DistanceStruct::id());
+
+        // No bottom found
+        let mut source = MeasurementSource::default();
+        assert!(source.measurement(&distance(0)).is_none());
+
+        assert_eq!(
+            source
+                .measurement(&profile)
+                .map(|measurement| measurement.distance_mm),
+            Some(1500)
+        );
+        assert!(source.measurement(&distance(1500)).is_none());
+        assert!(source.measurement(&profile).is_some());
+    }
+}
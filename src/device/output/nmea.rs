@@ -0,0 +1,200 @@
+use bluerobotics_ping::message::ProtocolMessage;
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use super::{DepthMeasurement, MeasurementSource, OutputDestination, OutputWriter};
+use crate::device::manager::ManagerError;
+
+static FEET_PER_METER: f32 = 3.28084;
+static FATHOMS_PER_METER: f32 = 0.546807;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub enum NmeaSentence {
+    /// Depth below transducer.
+    DBT,
+    /// Depth with transducer offset and maximum range.
+    DPT,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub struct NmeaOutputConfig {
+    pub destination: OutputDestination,
+    #[serde(default = "default_sentences")]
+    pub sentences: Vec<NmeaSentence>,
+    /// Two uppercase letters identifying the sender, SD for sounders.
+    #[serde(default = "default_talker_id")]
+    pub talker_id: String,
+    /// Meters reported on DPT, positive from transducer to water line, negative to keel.
+    #[serde(default)]
+    pub offset: f32,
+    /// Measurements with lower confidence, in percent, are not sent.
+    #[serde(default)]
+    pub min_confidence: u8,
+}
+
+fn default_sentences() -> Vec<NmeaSentence> {
+    vec![NmeaSentence::DBT, NmeaSentence::DPT]
+}
+
+fn default_talker_id() -> String {
+    "SD".to_string()
+}
+
+impl NmeaOutputConfig {
+    pub fn validate(&self) -> Result<(), ManagerError> {
+        if self.talker_id.len() != 2
+            || !self
+                .talker_id
+                .chars()
+                .all(|character| character.is_ascii_uppercase())
+        {
+            return Err(ManagerError::Other(format!(
+                "nmea_output: Invalid talker id {:?}, expected two uppercase letters",
+                self.talker_id
+            )));
+        }
+
+        if self.sentences.is_empty() {
+            return Err(ManagerError::Other(
+                "nmea_output: At least one sentence is required".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Build the configured sentences for a measurement, with checksum and line ending
+pub fn sentences(config: &NmeaOutputConfig, measurement: &DepthMeasurement) -> Vec<String> {
+    if measurement.confidence < config.min_confidence {
+        return Vec::new();
+    }
+
+    let depth = measurement.distance_mm as f32 / 1000.0;
+
+    config
+        .sentences
+        .iter()
+        .map(|sentence| match sentence {
+            NmeaSentence::DBT => format!(
+                "{}DBT,{:.1},f,{:.2},M,{:.1},F",
+                config.talker_id,
+                depth * FEET_PER_METER,
+                depth,
+                depth * FATHOMS_PER_METER
+            ),
+            NmeaSentence::DPT => {
+                let max_range = measurement
+                    .scan_range_mm
+                    .map(|(_, end)| format!("{:.1}", end as f32 / 1000.0))
+                    .unwrap_or_default();
+                format!(
+                    "{}DPT,{:.2},{:.2},{}",
+                    config.talker_id, depth, config.offset, max_range
+                )
+            }
+        })
+        .map(|body| format!("${body}*{:02X}\r\n", checksum(&body)))
+        .collect()
+}
+
+fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+// Forward the device measurements to the destination until the device stream closes
+pub async fn start_nmea_output(
+    device_id: Uuid,
+    config: NmeaOutputConfig,
+    mut subscriber: Receiver<ProtocolMessage>,
+) -> Result<tokio::task::JoinHandle<()>, ManagerError> {
+    config.validate()?;
+    let mut writer = OutputWriter::open(&config.destination).await?;
+
+    Ok(tokio::spawn(async move {
+        let mut source = MeasurementSource::default();
+        loop {
+            let message = match subscriber.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("NMEA output skipped {skipped} messages, device: {device_id}");
+                    continue;
+                }
+                Err(RecvError::Closed) => {
+                    debug!("NMEA output stopped, device stream closed, device: {device_id}");
+                    break;
+                }
+            };
+
+            let Some(measurement) = source.measurement(&message) else {
+                continue;
+            };
+
+            for sentence in sentences(&config, &measurement) {
+                if let Err(err) = writer.write(sentence.as_bytes()).await {
+                    error!("NMEA output failed to send sentence: {err}, device: {device_id}");
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::output::OutputUdpStruct;
+    use std::net::Ipv4Addr;
+
+    fn config(sentences: Vec<NmeaSentence>) -> NmeaOutputConfig {
+        NmeaOutputConfig {
+            destination: OutputDestination::Udp(OutputUdpStruct {
+                ip: Ipv4Addr::LOCALHOST,
+                port: 10110,
+            }),
+            sentences,
+            talker_id: default_talker_id(),
+            offset: 0.5,
+            min_confidence: 50,
+        }
+    }
+
+    #[test]
+    fn depth_sentences() {
+        let measurement = DepthMeasurement {
+            distance_mm: 2400,
+            confidence: 100,
+            scan_range_mm: Some((0, 10000)),
+        };
+
+        assert_eq!(
+            sentences(&config(default_sentences()), &measurement),
+            vec![
+                "$SDDBT,7.9,f,2.40,M,1.3,F*3C\r\n".to_string(),
+                "$SDDPT,2.40,0.50,10.0*67\r\n".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn low_confidence_is_not_sent() {
+        let measurement = DepthMeasurement {
+            distance_mm: 2400,
+            confidence: 20,
+            scan_range_mm: None,
+        };
+
+        assert!(sentences(&config(default_sentences()), &measurement).is_empty());
+    }
+
+    #[test]
+    fn talker_id_validation() {
+        let mut config = config(vec![NmeaSentence::DBT]);
+        assert!(config.validate().is_ok());
+
+        config.talker_id = "sd".to_string();
+        assert!(config.validate().is_err());
+    }
+}
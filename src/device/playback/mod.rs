@@ -0,0 +1,738 @@
+use bluerobotics_ping::{
+    common::{self, DeviceInformationStruct, ProtocolVersionStruct},
+    decoder::{Decoder, DecoderResult},
+    message::{MessageInfo, ProtocolMessage},
+    ping1d::{self, ProfileStruct},
+    ping360::{self, AutoDeviceDataStruct, DeviceDataStruct},
+    Messages,
+};
+use mcap::read::Summary;
+use memmap2::Mmap;
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf},
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+use tracing::{debug, error, info, trace};
+
+use crate::device::{
+    manager::{DeviceSelection, ManagerError},
+    recording::map_recording,
+    virtual_device::{nack, protocol_message, wait_until, VIRTUAL_DEVICE_BUFFER_SIZE},
+};
+
+static RECORDINGS_PATH: &str = "recordings";
+static MAX_PLAYBACK_SPEED: f32 = 32.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+#[serde(tag = "command", content = "payload")]
+pub enum PlaybackCommand {
+    Play,
+    Pause,
+    Seek(PlaybackSeekStruct),
+    SetSpeed(PlaybackSpeedStruct),
+    GetStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct PlaybackSeekStruct {
+    pub position_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct PlaybackSpeedStruct {
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Apiv2Schema)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub struct PlaybackStatus {
+    pub file_name: String,
+    pub device_type: DeviceSelection,
+    pub state: PlaybackState,
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub speed: f32,
+}
+
+struct PlaybackFrame {
+    // Nanoseconds since the first frame of the recording
+    time: u64,
+    message_id: u16,
+    // Chunk of the recording holding the message, and its position among the chunk messages
+    chunk: usize,
+    position: usize,
+}
+
+/// Recording mapped in memory with an index of its frames, messages are read when played.
+pub struct PlaybackRecording {
+    pub device_type: DeviceSelection,
+    file_path: PathBuf,
+    data: Mmap,
+    summary: Summary,
+    frames: Vec<PlaybackFrame>,
+    // Messages of the last chunk read, by their position in the chunk
+    chunk: Option<(usize, Vec<Option<ProtocolMessage>>)>,
+}
+
+impl PlaybackRecording {
+    pub fn duration(&self) -> u64 {
+        self.frames.last().map(|frame| frame.time).unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Recorded message of a frame, the other messages of its chunk are kept for the next frames
+    fn message(&mut self, index: usize) -> Result<ProtocolMessage, ManagerError> {
+        let Some(frame) = self.frames.get(index) else {
+            return Err(ManagerError::Other(format!(
+                "Playback frame {index} out of {} frames",
+                self.frames.len()
+            )));
+        };
+        let (chunk, position) = (frame.chunk, frame.position);
+
+        if self.chunk.as_ref().map(|(cached, _)| *cached) != Some(chunk) {
+            let parse_error = |err| parse_error(&self.file_path, err);
+            let messages = self
+                .summary
+                .stream_chunk(&self.data, &self.summary.chunk_indexes[chunk])
+                .map_err(parse_error)?;
+
+            let mut chunk_messages = Vec::new();
+            for message in messages {
+                let message = message.map_err(parse_error)?;
+                chunk_messages.push(decode_message(&message.channel.topic, &message.data)?);
+            }
+            self.chunk = Some((chunk, chunk_messages));
+        }
+
+        self.chunk
+            .as_ref()
+            .and_then(|(_, messages)| messages.get(position)?.clone())
+            .ok_or_else(|| {
+                ManagerError::DeviceSourceError(format!(
+                    "Recording {:?} has no device message at chunk {chunk}, position {position}",
+                    self.file_path
+                ))
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaybackHandler {
+    sender: mpsc::Sender<PlaybackActorRequest>,
+}
+
+#[derive(Debug)]
+struct PlaybackActorRequest {
+    command: PlaybackCommand,
+    respond_to: oneshot::Sender<Result<PlaybackStatus, ManagerError>>,
+}
+
+struct PlaybackActor {
+    file_name: String,
+    recording: PlaybackRecording,
+    receiver: mpsc::Receiver<PlaybackActorRequest>,
+    writer: WriteHalf<DuplexStream>,
+    decoder: Decoder,
+    state: PlaybackState,
+    speed: f32,
+    // Index of the next frame to be sent
+    index: usize,
+    // Recording time matching the `anchor` instant, both are updated on every playback change
+    anchor_time: u64,
+    anchor: Instant,
+    last_messages: HashMap<u16, ProtocolMessage>,
+}
+
+// Resolve a recording file name, only files inside the recordings folder are accepted
+pub fn recording_path(file_name: &str) -> Result<PathBuf, ManagerError> {
+    if Path::new(file_name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        != Some(file_name)
+    {
+        return Err(ManagerError::DeviceSourceError(format!(
+            "Invalid recording file name: {file_name}"
+        )));
+    }
+
+    Ok(Path::new(RECORDINGS_PATH).join(file_name))
+}
+
+fn parse_error(file_path: &Path, err: mcap::McapError) -> ManagerError {
+    ManagerError::DeviceSourceError(format!("Failed to parse recording {file_path:?}: {err}"))
+}
+
+// Device type and message id recorded on a channel, the topics are named by RecordingManager
+fn topic_message(topic: &str) -> Option<(DeviceSelection, u16)> {
+    if topic.ends_with("/Ping360") {
+        Some((DeviceSelection::Ping360, AutoDeviceDataStruct::id()))
+    } else if topic.ends_with("/Ping1D") {
+        Some((DeviceSelection::Ping1D, ProfileStruct::id()))
+    } else {
+        None
+    }
+}
+
+fn decode_message(topic: &str, data: &[u8]) -> Result<Option<ProtocolMessage>, ManagerError> {
+    let message = match topic_message(topic) {
+        Some((DeviceSelection::Ping360, _)) => {
+            let data: AutoDeviceDataStruct = serde_json::from_slice(data).map_err(|err| {
+                ManagerError::DeviceSourceError(format!(
+                    "Invalid Ping360 message on {topic}: {err}"
+                ))
+            })?;
+            protocol_message(&ping360::Messages::AutoDeviceData(data))
+        }
+        Some(_) => {
+            let data: ProfileStruct = serde_json::from_slice(data).map_err(|err| {
+                ManagerError::DeviceSourceError(format!("Invalid Ping1D message on {topic}: {err}"))
+            })?;
+            protocol_message(&ping1d::Messages::Profile(data))
+        }
+        None => return Ok(None),
+    };
+
+    Ok(Some(message))
+}
+
+// Index the Ping1D or Ping360 messages of a MCAP file created by RecordingManager,
+// only the time and location of each message are kept in memory
+pub fn load_recording(file_path: &Path) -> Result<PlaybackRecording, ManagerError> {
+    let data = map_recording(file_path)?;
+    let summary = Summary::read(&data)
+        .map_err(|err| parse_error(file_path, err))?
+        .ok_or_else(|| {
+            ManagerError::DeviceSourceError(format!(
+                "Recording {file_path:?} has no summary, it was not closed properly"
+            ))
+        })?;
+
+    let mut device_type = None;
+    let mut frames = Vec::new();
+
+    for (chunk, index) in summary.chunk_indexes.iter().enumerate() {
+        let messages = summary
+            .stream_chunk(&data, index)
+            .map_err(|err| parse_error(file_path, err))?;
+
+        for (position, message) in messages.enumerate() {
+            let message = message.map_err(|err| parse_error(file_path, err))?;
+            let Some((message_type, message_id)) = topic_message(&message.channel.topic) else {
+                continue;
+            };
+
+            match &device_type {
+                None => device_type = Some(message_type),
+                Some(device_type) if *device_type != message_type => {
+                    return Err(ManagerError::DeviceSourceError(format!(
+                        "Recording {file_path:?} contains messages from more than one device type"
+                    )));
+                }
+                _ => {}
+            }
+
+            frames.push(PlaybackFrame {
+                time: message.log_time,
+                message_id,
+                chunk,
+                position,
+            });
+        }
+    }
+
+    let Some(device_type) = device_type else {
+        return Err(ManagerError::DeviceSourceError(format!(
+            "Recording {file_path:?} has no Ping1D or Ping360 messages"
+        )));
+    };
+
+    // MCAP files don't guarantee messages ordered by time
+    frames.sort_by_key(|frame| frame.time);
+    let start = frames.first().map(|frame| frame.time).unwrap_or_default();
+    frames.iter_mut().for_each(|frame| frame.time -= start);
+
+    Ok(PlaybackRecording {
+        device_type,
+        file_path: file_path.to_path_buf(),
+        data,
+        summary,
+        frames,
+        chunk: None,
+    })
+}
+
+// Open a recording and return the stream that should be used by the ping device
+pub async fn start_playback(
+    file_name: &str,
+) -> Result<(DuplexStream, PlaybackHandler, DeviceSelection), ManagerError> {
+    let file_path = recording_path(file_name)?;
+    let recording = tokio::task::spawn_blocking(move || load_recording(&file_path))
+        .await
+        .map_err(|err| ManagerError::Other(err.to_string()))??;
+
+    let device_type = recording.device_type.clone();
+    info!(
+        "Playback started for {file_name}: {device_type:?}, {} messages",
+        recording.len()
+    );
+
+    let (stream, handler) = spawn_playback(file_name.to_string(), recording);
+    Ok((stream, handler, device_type))
+}
+
+fn spawn_playback(
+    file_name: String,
+    recording: PlaybackRecording,
+) -> (DuplexStream, PlaybackHandler) {
+    let (device_stream, playback_stream) = tokio::io::duplex(VIRTUAL_DEVICE_BUFFER_SIZE);
+    let (reader, writer) = tokio::io::split(playback_stream);
+    let (sender, receiver) = mpsc::channel(10);
+
+    let actor = PlaybackActor {
+        file_name,
+        recording,
+        receiver,
+        writer,
+        decoder: Decoder::new(),
+        state: PlaybackState::Playing,
+        speed: 1.0,
+        index: 0,
+        anchor_time: 0,
+        anchor: Instant::now(),
+        last_messages: HashMap::new(),
+    };
+
+    tokio::spawn(actor.run(reader));
+
+    (device_stream, PlaybackHandler { sender })
+}
+
+impl PlaybackActor {
+    async fn run(mut self, mut reader: ReadHalf<DuplexStream>) {
+        let mut buffer = [0u8; 1024];
+
+        loop {
+            let deadline = self.next_deadline();
+
+            tokio::select! {
+                Some(request) = self.receiver.recv() => {
+                    let result = self.handle_command(request.command);
+                    if let Err(err) = request.respond_to.send(result) {
+                        error!("Playback: Failed to return response: {err:?}");
+                    }
+                }
+                result = reader.read(&mut buffer) => match result {
+                    Ok(0) => {
+                        debug!("Playback device closed, stopping playback of {}", self.file_name);
+                        break;
+                    }
+                    Ok(size) => {
+                        if let Err(err) = self.handle_incoming(&buffer[..size]).await {
+                            error!("Playback failed to answer device request: {err:?}");
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        error!("Playback failed to read device request: {err:?}");
+                        break;
+                    }
+                },
+                _ = wait_until(deadline) => {
+                    if let Err(err) = self.send_next_frame().await {
+                        error!("Playback failed to send recorded message: {err:?}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: PlaybackCommand) -> Result<PlaybackStatus, ManagerError> {
+        trace!("Playback {}: Received command {command:?}", self.file_name);
+
+        match command {
+            PlaybackCommand::Play => match self.state {
+                PlaybackState::Playing => {}
+                PlaybackState::Paused => {
+                    self.rebase(self.anchor_time);
+                    self.state = PlaybackState::Playing;
+                }
+                PlaybackState::Finished => {
+                    self.index = 0;
+                    self.rebase(0);
+                    self.state = PlaybackState::Playing;
+                }
+            },
+            PlaybackCommand::Pause => {
+                if self.state == PlaybackState::Playing {
+                    self.rebase(self.position());
+                    self.state = PlaybackState::Paused;
+                }
+            }
+            PlaybackCommand::Seek(seek) => {
+                let time = seek
+                    .position_ms
+                    .saturating_mul(1_000_000)
+                    .min(self.recording.duration());
+                self.index = self
+                    .recording
+                    .frames
+                    .partition_point(|frame| frame.time < time);
+                self.rebase(time);
+                if self.index >= self.recording.len() {
+                    self.state = PlaybackState::Finished;
+                } else if self.state == PlaybackState::Finished {
+                    self.state = PlaybackState::Paused;
+                }
+            }
+            PlaybackCommand::SetSpeed(speed) => {
+                if !(speed.speed > 0.0 && speed.speed <= MAX_PLAYBACK_SPEED) {
+                    return Err(ManagerError::Other(format!(
+                        "Invalid playback speed {}, expected a value between 0 and {MAX_PLAYBACK_SPEED}",
+                        speed.speed
+                    )));
+                }
+                self.rebase(self.position());
+                self.speed = speed.speed;
+            }
+            PlaybackCommand::GetStatus => {}
+        }
+
+        Ok(self.status())
+    }
+
+    fn status(&self) -> PlaybackStatus {
+        PlaybackStatus {
+            file_name: self.file_name.clone(),
+            device_type: self.recording.device_type.clone(),
+            state: self.state,
+            position_ms: self.position() / 1_000_000,
+            duration_ms: self.recording.duration() / 1_000_000,
+            speed: self.speed,
+        }
+    }
+
+    // Current time in the recording, in nanoseconds
+    fn position(&self) -> u64 {
+        match self.state {
+            PlaybackState::Playing => {
+                let elapsed = self.anchor.elapsed().as_nanos() as f64 * self.speed as f64;
+                (self.anchor_time + elapsed as u64).min(self.recording.duration())
+            }
+            PlaybackState::Paused | PlaybackState::Finished => self.anchor_time,
+        }
+    }
+
+    fn rebase(&mut self, time: u64) {
+        self.anchor_time = time;
+        self.anchor = Instant::now();
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        if self.state != PlaybackState::Playing {
+            return None;
+        }
+
+        let frame = self.recording.frames.get(self.index)?;
+        let wait = frame.time.saturating_sub(self.anchor_time) as f64 / self.speed as f64;
+        Some(self.anchor + Duration::from_nanos(wait as u64))
+    }
+
+    async fn send_next_frame(&mut self) -> std::io::Result<()> {
+        if self.index >= self.recording.len() {
+            return Ok(());
+        }
+
+        match self.recording.message(self.index) {
+            Ok(message) => {
+                self.writer.write_all(&message.serialized()).await?;
+                self.last_messages.insert(message.message_id, message);
+            }
+            Err(err) => error!(
+                "Playback {}: Skipping recorded message {}: {err:?}",
+                self.file_name, self.index
+            ),
+        }
+        self.index += 1;
+
+        if self.index >= self.recording.len() {
+            info!("Playback finished for {}", self.file_name);
+            self.anchor_time = self.recording.duration();
+            self.state = PlaybackState::Finished;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_incoming(&mut self, data: &[u8]) -> std::io::Result<()> {
+        for byte in data {
+            if let DecoderResult::Success(message) = self.decoder.parse_byte(*byte) {
+                let answer = self.answer(&message);
+                self.writer.write_all(&answer.serialized()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Playback devices only answer requests, any command that would change the device is refused
+    fn answer(&mut self, message: &ProtocolMessage) -> ProtocolMessage {
+        if let Ok(Messages::Common(common::Messages::GeneralRequest(request))) =
+            Messages::try_from(message)
+        {
+            if let Some(answer) = self.general_request_answer(request.requested_id) {
+                return answer;
+            }
+            return nack(request.requested_id, "Message not available on playback");
+        }
+
+        nack(message.message_id, "Command not supported by playback")
+    }
+
+    fn general_request_answer(&mut self, requested_id: u16) -> Option<ProtocolMessage> {
+        if requested_id == DeviceInformationStruct::id() {
+            return Some(protocol_message(&common::Messages::DeviceInformation(
+                DeviceInformationStruct {
+                    device_type: match self.recording.device_type {
+                        DeviceSelection::Ping1D => 1,
+                        DeviceSelection::Ping360 => 2,
+                        DeviceSelection::Common | DeviceSelection::Auto => 0,
+                    },
+                    device_revision: 0,
+                    // Versions with support of Ping360 auto transmit mode
+                    firmware_version_major: 3,
+                    firmware_version_minor: 3,
+                    firmware_version_patch: 0,
+                    reserved: 0,
+                },
+            )));
+        }
+
+        if requested_id == ProtocolVersionStruct::id() {
+            return Some(protocol_message(&common::Messages::ProtocolVersion(
+                ProtocolVersionStruct {
+                    version_major: 1,
+                    version_minor: 0,
+                    version_patch: 0,
+                    reserved: 0,
+                },
+            )));
+        }
+
+        if requested_id == DeviceDataStruct::id() {
+            let reference = self.reference_message(AutoDeviceDataStruct::id())?;
+            let Ok(Messages::Ping360(ping360::Messages::AutoDeviceData(data))) =
+                Messages::try_from(&reference)
+            else {
+                return None;
+            };
+            return Some(protocol_message(&ping360::Messages::DeviceData(
+                DeviceDataStruct {
+                    mode: data.mode,
+                    gain_setting: data.gain_setting,
+                    angle: data.angle,
+                    transmit_duration: data.transmit_duration,
+                    sample_period: data.sample_period,
+                    transmit_frequency: data.transmit_frequency,
+                    number_of_samples: data.number_of_samples,
+                    data_length: data.data_length,
+                    data: data.data,
+                },
+            )));
+        }
+
+        self.reference_message(requested_id)
+    }
+
+    // Last message sent with this id, or the first one available in the recording
+    fn reference_message(&mut self, message_id: u16) -> Option<ProtocolMessage> {
+        if let Some(message) = self.last_messages.get(&message_id) {
+            return Some(message.clone());
+        }
+
+        let index = self
+            .recording
+            .frames
+            .iter()
+            .position(|frame| frame.message_id == message_id)?;
+        self.recording
+            .message(index)
+            .inspect_err(|err| error!("Playback {}: {err:?}", self.file_name))
+            .ok()
+    }
+}
+
+impl PlaybackHandler {
+    pub async fn send(&self, command: PlaybackCommand) -> Result<PlaybackStatus, ManagerError> {
+        let (respond_to, receiver) = oneshot::channel();
+
+        self.sender
+            .send(PlaybackActorRequest {
+                command,
+                respond_to,
+            })
+            .await
+            .map_err(|err| ManagerError::TokioMpsc(err.to_string()))?;
+
+        receiver
+            .await
+            .map_err(|err| ManagerError::TokioMpsc(err.to_string()))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bluerobotics_ping::device::{Ping1D, PingDevice};
+
+    fn profile(ping_number: u32) -> ProfileStruct {
+        ProfileStruct {
+            distance: 1000,
+            confidence: 100,
+            transmit_duration: 100,
+            ping_number,
+            scan_start: 0,
+            scan_length: 5000,
+            gain_setting: 1,
+            profile_data_length: 4,
+            profile_data: vec![1, 2, 3, 4],
+        }
+    }
+
+    // Write the profiles with their log time in a new recording, returning its folder and path
+    fn write_recording(profiles: &[(u32, u64)]) -> (PathBuf, PathBuf) {
+        let directory =
+            std::env::temp_dir().join(format!("ping-viewer-next-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file_path = directory.join("device.mcap");
+
+        let ctx = foxglove::Context::new();
+        // A chunk for each message, so frames are read across chunks
+        let options = foxglove::McapWriteOptions::new().chunk_size(Some(1));
+        let writer = foxglove::McapWriter::with_options(options)
+            .context(&ctx)
+            .create_new_buffered_file(&file_path)
+            .unwrap();
+        let channel = ctx
+            .channel_builder("device_0/Ping1D")
+            .build::<ProfileStruct>();
+        for (ping_number, time) in profiles {
+            channel.log_with_time(&profile(*ping_number), *time);
+        }
+        writer.close().unwrap();
+
+        (directory, file_path)
+    }
+
+    #[test]
+    fn load_recording_from_mcap() {
+        // Messages are logged out of order on purpose
+        let (directory, file_path) =
+            write_recording(&[(2, 3_000_000_000u64), (1, 1_000_000_000u64)]);
+
+        let mut recording = load_recording(&file_path).unwrap();
+        assert_eq!(recording.device_type, DeviceSelection::Ping1D);
+        assert_eq!(recording.len(), 2);
+        assert!(recording.summary.chunk_indexes.len() > 1);
+        assert_eq!(recording.duration(), 2_000_000_000);
+        assert_eq!(
+            recording.message(0).unwrap(),
+            protocol_message(&ping1d::Messages::Profile(profile(1)))
+        );
+        assert_eq!(
+            recording.message(1).unwrap(),
+            protocol_message(&ping1d::Messages::Profile(profile(2)))
+        );
+        assert!(recording.message(2).is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn recording_path_rejects_directories() {
+        assert!(recording_path("device.mcap").is_ok());
+        assert!(recording_path("../settings.json").is_err());
+        assert!(recording_path("/tmp/device.mcap").is_err());
+    }
+
+    #[tokio::test]
+    async fn playback_answers_requests_and_seeks() {
+        let (directory, file_path) = write_recording(
+            &(0..3)
+                .map(|index| (index, index as u64 * 10_000_000_000))
+                .collect::<Vec<_>>(),
+        );
+        let recording = load_recording(&file_path).unwrap();
+        let (stream, handler) = spawn_playback("test.mcap".to_string(), recording);
+        let device = Ping1D::new(stream);
+        let mut subscriber = device.subscribe();
+
+        let information = device.device_information().await.unwrap();
+        assert_eq!(information.device_type, 1);
+        assert!(device.set_ping_interval(100).await.is_err());
+
+        let status = handler.send(PlaybackCommand::Pause).await.unwrap();
+        assert_eq!(status.state, PlaybackState::Paused);
+        assert_eq!(status.duration_ms, 20_000);
+
+        let status = handler
+            .send(PlaybackCommand::Seek(PlaybackSeekStruct {
+                position_ms: 15_000,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(status.position_ms, 15_000);
+
+        handler.send(PlaybackCommand::Play).await.unwrap();
+        let status = handler
+            .send(PlaybackCommand::SetSpeed(PlaybackSpeedStruct {
+                speed: 10.0,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(status.state, PlaybackState::Playing);
+
+        // The first frame was sent before the seek, the next one must be the last frame
+        let first_frame = protocol_message(&ping1d::Messages::Profile(profile(0)));
+        let message = loop {
+            let message = subscriber.recv().await.unwrap();
+            if message.message_id == ProfileStruct::id() && message != first_frame {
+                break message;
+            }
+        };
+        assert_eq!(
+            message,
+            protocol_message(&ping1d::Messages::Profile(profile(2)))
+        );
+        assert!(handler
+            .send(PlaybackCommand::SetSpeed(PlaybackSpeedStruct {
+                speed: 0.0
+            }))
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
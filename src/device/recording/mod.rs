@@ -1,14 +1,17 @@
 use bluerobotics_ping::{ping1d::ProfileStruct, ping360::AutoDeviceDataStruct};
-use foxglove::Context;
+use foxglove::schemas::Timestamp;
 use foxglove::McapWriterHandle;
+use foxglove::{Channel, Context};
+use memmap2::Mmap;
 use paperclip::actix::Apiv2Schema;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::BufWriter;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 use tokio::sync::oneshot;
 use tokio::sync::{
@@ -22,10 +25,13 @@ use crate::device::{
     devices::DeviceActorHandler,
     manager::{DeviceSelection, ManagerError},
 };
-use crate::vehicle::VehicleData;
+use crate::vehicle::{unix_time_ms, PoseHistory, VehicleData};
 
 use super::manager::{ManagerActorHandler, UuidWrapper};
 
+// Pending vehicle poses are also checked while no sample is received
+static PENDING_POSES_PERIOD: Duration = Duration::from_millis(200);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecordingSession {
     pub device_id: Uuid,
@@ -46,7 +52,7 @@ pub struct RecordingManager {
     base_path: PathBuf,
     status_broadcast: broadcast::Sender<RecordingSession>,
     devices_manager_handler: ManagerActorHandler,
-    vehicle_data: Arc<RwLock<Option<VehicleData>>>,
+    vehicle_data: Arc<RwLock<PoseHistory>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
@@ -84,7 +90,7 @@ impl RecordingManager {
         size: usize,
         base_path: impl AsRef<Path>,
         device_manager: ManagerActorHandler,
-        vehicle_data: Arc<RwLock<Option<VehicleData>>>,
+        vehicle_data: Arc<RwLock<PoseHistory>>,
     ) -> (Self, RecordingsManagerHandler) {
         let (sender, receiver) = mpsc::channel(size);
         let actor_handler: RecordingsManagerHandler = RecordingsManagerHandler { sender };
@@ -105,7 +111,12 @@ impl RecordingManager {
         base_path: impl AsRef<Path>,
         device_manager: ManagerActorHandler,
     ) -> (Self, RecordingsManagerHandler) {
-        Self::new_with_pose(size, base_path, device_manager, Arc::new(RwLock::new(None)))
+        Self::new_with_pose(
+            size,
+            base_path,
+            device_manager,
+            Arc::new(RwLock::new(PoseHistory::default())),
+        )
     }
 
     pub async fn run(mut self) {
@@ -289,7 +300,7 @@ impl RecordingManager {
         sessions: Arc<RwLock<HashMap<Uuid, SessionGuard>>>,
         device_id: Uuid,
         ctx: Arc<Context>,
-        vehicle_data: Arc<RwLock<Option<VehicleData>>>,
+        vehicle_data: Arc<RwLock<PoseHistory>>,
     ) -> Result<(), ManagerError> {
         let subscriber = handler
             .send(super::devices::PingRequest::GetSubscriber)
@@ -318,6 +329,8 @@ impl RecordingManager {
             .channel_builder(&ping360_topic)
             .build::<AutoDeviceDataStruct>();
         let vehicle_channel = ctx.channel_builder(&vehicle_topic).build::<VehicleData>();
+        // Samples waiting for a later pose, so their pose is interpolated instead of held
+        let mut pending_poses = VecDeque::new();
 
         while {
             let sessions_guard = sessions.read().await;
@@ -326,9 +339,10 @@ impl RecordingManager {
                 .map(|s| s.session.is_active)
                 .unwrap_or(false)
         } {
-            match receiver.recv().await {
-                Ok(msg) => {
-                    let timestamp = foxglove::schemas::Timestamp::now();
+            match tokio::time::timeout(PENDING_POSES_PERIOD, receiver.recv()).await {
+                Ok(Ok(msg)) => {
+                    let timestamp = Timestamp::now();
+                    let receive_time_ms = unix_time_ms();
                     // Handle Ping360
                     if let Ok(bluerobotics_ping::Messages::Ping360(
                         bluerobotics_ping::ping360::Messages::AutoDeviceData(answer),
@@ -361,22 +375,67 @@ impl RecordingManager {
                     {
                         ping1d_channel.log_with_time(&answer, timestamp);
                     }
-                    if let Some(vehicle) = vehicle_data.read().await.as_ref() {
-                        vehicle_channel.log_with_time(vehicle, timestamp);
-                    }
+                    pending_poses.push_back((receive_time_ms, timestamp));
                 }
-                Err(e) => {
+                Ok(Err(e)) => {
                     error!("Failed to receive broadcasted message: {:?}", e);
                     break;
                 }
+                Err(_) => (),
             }
+            log_pending_poses(
+                &*vehicle_data.read().await,
+                &mut pending_poses,
+                &vehicle_channel,
+                false,
+            );
         }
+        log_pending_poses(
+            &*vehicle_data.read().await,
+            &mut pending_poses,
+            &vehicle_channel,
+            true,
+        );
 
         sessions.write().await.remove(&device_id);
         Ok(())
     }
 }
 
+// Pose at the receive time of each sample, once it is settled or when the recording ends,
+// flagged when no recent pose is available
+fn log_pending_poses(
+    history: &PoseHistory,
+    pending: &mut VecDeque<(u64, Timestamp)>,
+    channel: &Channel<VehicleData>,
+    finish: bool,
+) {
+    let now_ms = unix_time_ms();
+    while let Some(&(receive_time_ms, timestamp)) = pending.front() {
+        if !finish && !history.is_settled(receive_time_ms, now_ms) {
+            break;
+        }
+        pending.pop_front();
+        if let Some(vehicle) = history.pose_at(receive_time_ms) {
+            if vehicle.stale {
+                trace!("Recording stale vehicle pose at {receive_time_ms}");
+            }
+            channel.log_with_time(&vehicle, timestamp);
+        }
+    }
+}
+
+// Map a recording in memory, its pages are only read from the disk when the messages are accessed
+pub fn map_recording(file_path: &Path) -> Result<Mmap, ManagerError> {
+    let file = File::open(file_path).map_err(|err| {
+        ManagerError::Other(format!("Failed to open recording {file_path:?}: {err}"))
+    })?;
+    // SAFETY: The mapping is read only and recordings are never truncated or rewritten,
+    // RecordingManager only appends to the file of an active recording
+    unsafe { Mmap::map(&file) }
+        .map_err(|err| ManagerError::Other(format!("Failed to map recording {file_path:?}: {err}")))
+}
+
 impl RecordingsManagerHandler {
     pub async fn send(&self, request: RecordingManagerCommand) -> Result<Answer, ManagerError> {
         let (result_sender, result_receiver) = oneshot::channel();
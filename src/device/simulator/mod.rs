@@ -0,0 +1,952 @@
+/// Ping360 Ethernet emulator, answering discovery and the ping protocol over UDP.
+pub mod udp_emulator;
+
+use bluerobotics_ping::{
+    common::{self, DeviceInformationStruct, ProtocolVersionStruct},
+    decoder::{Decoder, DecoderResult},
+    message::{DeserializeGenericMessage, MessageInfo, ProtocolMessage},
+    ping1d, ping360,
+};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    time::Instant,
+};
+use tracing::{debug, error, trace};
+
+use crate::device::{
+    manager::{DeviceSelection, SourceSimulatedStruct},
+    virtual_device::{ack, nack, protocol_message, wait_until, VIRTUAL_DEVICE_BUFFER_SIZE},
+};
+
+static SPEED_OF_SOUND: u32 = 1_500_000; // mm/s
+static PING1D_PROFILE_SAMPLES: u16 = 200;
+static PING360_GRADIANS: u16 = 400;
+
+#[derive(Clone, Debug, Deserialize, Serialize, Hash, Apiv2Schema, PartialEq)]
+pub enum SimulatedDeviceType {
+    Ping1D,
+    Ping360,
+}
+
+impl SimulatedDeviceType {
+    pub fn device_selection(&self) -> DeviceSelection {
+        match self {
+            SimulatedDeviceType::Ping1D => DeviceSelection::Ping1D,
+            SimulatedDeviceType::Ping360 => DeviceSelection::Ping360,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Hash, Apiv2Schema, PartialEq)]
+pub struct SimulationSettings {
+    /// Distance to the bottom, or to the surrounding walls for Ping360, in millimeters.
+    pub depth_mm: u32,
+    /// Extra echoes besides the bottom.
+    pub targets: Vec<SimulatedTarget>,
+    /// Maximum amplitude of the random noise added to every sample.
+    pub noise: u8,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            depth_mm: 8000,
+            targets: Vec::new(),
+            noise: 20,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Hash, Apiv2Schema, PartialEq)]
+pub struct SimulatedTarget {
+    pub distance_mm: u32,
+    pub strength: u8,
+    /// Ping360 head angle in gradians, targets without angle are visible from any direction.
+    #[serde(default)]
+    pub angle: Option<u16>,
+}
+
+struct Ping1DState {
+    device_id: u8,
+    speed_of_sound: u32,
+    scan_start: u32,
+    scan_length: u32,
+    mode_auto: u8,
+    ping_interval: u16,
+    gain_setting: u32,
+    ping_enabled: u8,
+    transmit_duration: u16,
+    ping_number: u32,
+    // Message ids requested by ContinuousStart
+    continuous: Vec<u16>,
+}
+
+struct Ping360State {
+    device_id: u8,
+    angle: u16,
+    last_data: ping360::DeviceDataStruct,
+    auto_transmit: Option<ping360::AutoTransmitStruct>,
+}
+
+// Synthetic echo sounder, answers ping protocol messages like a real device would
+pub struct Simulator {
+    device_type: SimulatedDeviceType,
+    settings: SimulationSettings,
+    random: u64,
+    ping1d: Ping1DState,
+    ping360: Ping360State,
+}
+
+impl Simulator {
+    pub fn new(device_type: SimulatedDeviceType, settings: SimulationSettings, seed: u64) -> Self {
+        Self {
+            device_type,
+            settings,
+            // Xorshift can't start from zero
+            random: seed.max(1),
+            ping1d: Ping1DState {
+                device_id: 1,
+                speed_of_sound: SPEED_OF_SOUND,
+                scan_start: 0,
+                scan_length: 10_000,
+                mode_auto: 1,
+                ping_interval: 100,
+                gain_setting: 0,
+                ping_enabled: 1,
+                transmit_duration: 100,
+                ping_number: 0,
+                continuous: Vec::new(),
+            },
+            ping360: Ping360State {
+                device_id: 1,
+                angle: 0,
+                last_data: ping360::DeviceDataStruct {
+                    mode: 1,
+                    gain_setting: 0,
+                    angle: 0,
+                    transmit_duration: 32,
+                    sample_period: 80,
+                    transmit_frequency: 750,
+                    number_of_samples: 1200,
+                    data_length: 0,
+                    data: Vec::new(),
+                },
+                auto_transmit: None,
+            },
+        }
+    }
+
+    // Answers for a message received from the host
+    pub fn handle_message(&mut self, message: &ProtocolMessage) -> Vec<ProtocolMessage> {
+        trace!("Simulator received message id {}", message.message_id);
+
+        if let Ok(common_message) = <common::Messages as DeserializeGenericMessage>::deserialize(
+            message.message_id,
+            &message.payload,
+        ) {
+            return self.handle_common(common_message);
+        }
+
+        match self.device_type {
+            SimulatedDeviceType::Ping1D => {
+                match <ping1d::Messages as DeserializeGenericMessage>::deserialize(
+                    message.message_id,
+                    &message.payload,
+                ) {
+                    Ok(ping1d_message) => self.handle_ping1d(message.message_id, ping1d_message),
+                    Err(_) => vec![nack(message.message_id, "Unknown message")],
+                }
+            }
+            SimulatedDeviceType::Ping360 => {
+                match <ping360::Messages as DeserializeGenericMessage>::deserialize(
+                    message.message_id,
+                    &message.payload,
+                ) {
+                    Ok(ping360_message) => self.handle_ping360(message.message_id, ping360_message),
+                    Err(_) => vec![nack(message.message_id, "Unknown message")],
+                }
+            }
+        }
+    }
+
+    // Interval between messages sent without request, when continuous or auto transmit mode is on
+    pub fn stream_interval(&self) -> Option<Duration> {
+        match self.device_type {
+            SimulatedDeviceType::Ping1D => {
+                if self.ping1d.ping_enabled == 0 || self.ping1d.continuous.is_empty() {
+                    return None;
+                }
+                Some(Duration::from_millis(
+                    self.ping1d.ping_interval.max(10) as u64
+                ))
+            }
+            SimulatedDeviceType::Ping360 => {
+                let auto_transmit = self.ping360.auto_transmit.as_ref()?;
+                // Time to receive all samples, plus motor steps and the requested delay
+                let acquisition = auto_transmit.number_of_samples as u64
+                    * auto_transmit.sample_period as u64
+                    * 25;
+                Some(
+                    Duration::from_nanos(acquisition)
+                        + Duration::from_millis(
+                            5 * auto_transmit.num_steps.max(1) as u64 + auto_transmit.delay as u64,
+                        ),
+                )
+            }
+        }
+    }
+
+    // Messages sent without request, on every stream interval
+    pub fn stream_messages(&mut self) -> Vec<ProtocolMessage> {
+        match self.device_type {
+            SimulatedDeviceType::Ping1D => {
+                let profile = self.ping1d_profile();
+                self.ping1d
+                    .continuous
+                    .clone()
+                    .into_iter()
+                    .filter_map(|id| self.ping1d_measurement(id, &profile))
+                    .collect()
+            }
+            SimulatedDeviceType::Ping360 => {
+                let Some(auto_transmit) = self.ping360.auto_transmit.clone() else {
+                    return Vec::new();
+                };
+
+                let angle = self.ping360.angle;
+                self.ping360.angle = next_angle(&auto_transmit, angle);
+
+                let data = self.ping360_data(
+                    angle,
+                    auto_transmit.sample_period,
+                    auto_transmit.number_of_samples,
+                    auto_transmit.gain_setting,
+                );
+                self.ping360.last_data = ping360::DeviceDataStruct {
+                    mode: auto_transmit.mode,
+                    gain_setting: auto_transmit.gain_setting,
+                    angle,
+                    transmit_duration: auto_transmit.transmit_duration,
+                    sample_period: auto_transmit.sample_period,
+                    transmit_frequency: auto_transmit.transmit_frequency,
+                    number_of_samples: auto_transmit.number_of_samples,
+                    data_length: data.len() as u16,
+                    data: data.clone(),
+                };
+
+                vec![protocol_message(&ping360::Messages::AutoDeviceData(
+                    ping360::AutoDeviceDataStruct {
+                        mode: auto_transmit.mode,
+                        gain_setting: auto_transmit.gain_setting,
+                        angle,
+                        transmit_duration: auto_transmit.transmit_duration,
+                        sample_period: auto_transmit.sample_period,
+                        transmit_frequency: auto_transmit.transmit_frequency,
+                        start_angle: auto_transmit.start_angle,
+                        stop_angle: auto_transmit.stop_angle,
+                        num_steps: auto_transmit.num_steps,
+                        delay: auto_transmit.delay,
+                        number_of_samples: auto_transmit.number_of_samples,
+                        data_length: data.len() as u16,
+                        data,
+                    },
+                ))]
+            }
+        }
+    }
+
+    // Same as a line break on serial or an empty datagram on UDP for real devices
+    pub fn stop_streaming(&mut self) {
+        self.ping1d.continuous.clear();
+        self.ping360.auto_transmit = None;
+    }
+
+    fn handle_common(&mut self, message: common::Messages) -> Vec<ProtocolMessage> {
+        match message {
+            common::Messages::GeneralRequest(request) => match self.request(request.requested_id) {
+                Some(answer) => vec![answer],
+                None => vec![nack(request.requested_id, "Unknown message")],
+            },
+            common::Messages::SetDeviceId(request) => {
+                self.ping1d.device_id = request.device_id;
+                self.ping360.device_id = request.device_id;
+                vec![ack(common::SetDeviceIdStruct::id())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn handle_ping1d(
+        &mut self,
+        message_id: u16,
+        message: ping1d::Messages,
+    ) -> Vec<ProtocolMessage> {
+        let state = &mut self.ping1d;
+        let acked_id = match message {
+            ping1d::Messages::SetDeviceId(request) => {
+                state.device_id = request.device_id;
+                ping1d::SetDeviceIdStruct::id()
+            }
+            ping1d::Messages::SetRange(request) => {
+                state.scan_start = request.scan_start;
+                state.scan_length = request.scan_length.max(1);
+                ping1d::SetRangeStruct::id()
+            }
+            ping1d::Messages::SetSpeedOfSound(request) => {
+                state.speed_of_sound = request.speed_of_sound;
+                ping1d::SetSpeedOfSoundStruct::id()
+            }
+            ping1d::Messages::SetModeAuto(request) => {
+                state.mode_auto = request.mode_auto;
+                ping1d::SetModeAutoStruct::id()
+            }
+            ping1d::Messages::SetPingInterval(request) => {
+                state.ping_interval = request.ping_interval;
+                ping1d::SetPingIntervalStruct::id()
+            }
+            ping1d::Messages::SetGainSetting(request) => {
+                state.gain_setting = request.gain_setting as u32;
+                ping1d::SetGainSettingStruct::id()
+            }
+            ping1d::Messages::SetPingEnable(request) => {
+                state.ping_enabled = request.ping_enabled;
+                ping1d::SetPingEnableStruct::id()
+            }
+            ping1d::Messages::SetOssProfileConfiguration(_) => {
+                ping1d::SetOssProfileConfigurationStruct::id()
+            }
+            ping1d::Messages::ContinuousStart(request) => {
+                if !state.continuous.contains(&request.id) {
+                    state.continuous.push(request.id);
+                }
+                return Vec::new();
+            }
+            ping1d::Messages::ContinuousStop(request) => {
+                state.continuous.retain(|id| *id != request.id);
+                return Vec::new();
+            }
+            ping1d::Messages::GotoBootloader(_) => return Vec::new(),
+            _ => return vec![nack(message_id, "Unsupported command")],
+        };
+
+        vec![ack(acked_id)]
+    }
+
+    fn handle_ping360(
+        &mut self,
+        message_id: u16,
+        message: ping360::Messages,
+    ) -> Vec<ProtocolMessage> {
+        match message {
+            ping360::Messages::Transducer(request) => {
+                self.ping360.auto_transmit = None;
+                self.ping360.angle = request.angle % PING360_GRADIANS;
+
+                let data = if request.transmit == 1 {
+                    self.ping360_data(
+                        self.ping360.angle,
+                        request.sample_period,
+                        request.number_of_samples,
+                        request.gain_setting,
+                    )
+                } else {
+                    Vec::new()
+                };
+
+                self.ping360.last_data = ping360::DeviceDataStruct {
+                    mode: request.mode,
+                    gain_setting: request.gain_setting,
+                    angle: self.ping360.angle,
+                    transmit_duration: request.transmit_duration,
+                    sample_period: request.sample_period,
+                    transmit_frequency: request.transmit_frequency,
+                    number_of_samples: request.number_of_samples,
+                    data_length: data.len() as u16,
+                    data,
+                };
+
+                vec![protocol_message(&ping360::Messages::DeviceData(
+                    self.ping360.last_data.clone(),
+                ))]
+            }
+            ping360::Messages::AutoTransmit(request) => {
+                debug!("Simulator starting auto transmit: {request:?}");
+                self.ping360.angle = request.start_angle % PING360_GRADIANS;
+                self.ping360.auto_transmit = Some(request);
+                Vec::new()
+            }
+            ping360::Messages::MotorOff(_) => {
+                self.ping360.auto_transmit = None;
+                vec![ack(ping360::MotorOffStruct::id())]
+            }
+            ping360::Messages::Reset(_) => {
+                self.ping360.auto_transmit = None;
+                Vec::new()
+            }
+            ping360::Messages::SetDeviceId(request) => {
+                self.ping360.device_id = request.id;
+                vec![ack(ping360::SetDeviceIdStruct::id())]
+            }
+            _ => vec![nack(message_id, "Unsupported command")],
+        }
+    }
+
+    fn request(&mut self, requested_id: u16) -> Option<ProtocolMessage> {
+        if requested_id == DeviceInformationStruct::id() {
+            let (device_type, firmware_version_minor) = match self.device_type {
+                SimulatedDeviceType::Ping1D => (1, 29),
+                SimulatedDeviceType::Ping360 => (2, 3),
+            };
+            return Some(protocol_message(&common::Messages::DeviceInformation(
+                DeviceInformationStruct {
+                    device_type,
+                    device_revision: 1,
+                    firmware_version_major: 3,
+                    firmware_version_minor,
+                    firmware_version_patch: 0,
+                    reserved: 0,
+                },
+            )));
+        }
+
+        if requested_id == ProtocolVersionStruct::id() {
+            return Some(protocol_message(&common::Messages::ProtocolVersion(
+                ProtocolVersionStruct {
+                    version_major: 1,
+                    version_minor: 0,
+                    version_patch: 0,
+                    reserved: 0,
+                },
+            )));
+        }
+
+        match self.device_type {
+            SimulatedDeviceType::Ping1D => {
+                let profile = self.ping1d_profile();
+                self.ping1d_measurement(requested_id, &profile)
+            }
+            SimulatedDeviceType::Ping360 => {
+                if requested_id == ping360::DeviceDataStruct::id() {
+                    return Some(protocol_message(&ping360::Messages::DeviceData(
+                        self.ping360.last_data.clone(),
+                    )));
+                }
+                None
+            }
+        }
+    }
+
+    // Any Ping1D message that can be requested, measurements are taken from the given profile
+    fn ping1d_measurement(
+        &self,
+        requested_id: u16,
+        profile: &ping1d::ProfileStruct,
+    ) -> Option<ProtocolMessage> {
+        let state = &self.ping1d;
+        let message = match requested_id {
+            id if id == ping1d::ProfileStruct::id() => ping1d::Messages::Profile(profile.clone()),
+            id if id == ping1d::DistanceStruct::id() => {
+                ping1d::Messages::Distance(ping1d::DistanceStruct {
+                    distance: profile.distance,
+                    confidence: profile.confidence,
+                    transmit_duration: profile.transmit_duration,
+                    ping_number: profile.ping_number,
+                    scan_start: profile.scan_start,
+                    scan_length: profile.scan_length,
+                    gain_setting: profile.gain_setting,
+                })
+            }
+            id if id == ping1d::DistanceSimpleStruct::id() => {
+                ping1d::Messages::DistanceSimple(ping1d::DistanceSimpleStruct {
+                    distance: profile.distance,
+                    confidence: profile.confidence.min(100) as u8,
+                })
+            }
+            id if id == ping1d::FirmwareVersionStruct::id() => {
+                ping1d::Messages::FirmwareVersion(ping1d::FirmwareVersionStruct {
+                    device_type: 1,
+                    device_model: 1,
+                    firmware_version_major: 3,
+                    firmware_version_minor: 29,
+                })
+            }
+            id if id == ping1d::DeviceIdStruct::id() => {
+                ping1d::Messages::DeviceId(ping1d::DeviceIdStruct {
+                    device_id: state.device_id,
+                })
+            }
+            id if id == ping1d::Voltage5Struct::id() => {
+                ping1d::Messages::Voltage5(ping1d::Voltage5Struct { voltage_5: 5000 })
+            }
+            id if id == ping1d::SpeedOfSoundStruct::id() => {
+                ping1d::Messages::SpeedOfSound(ping1d::SpeedOfSoundStruct {
+                    speed_of_sound: state.speed_of_sound,
+                })
+            }
+            id if id == ping1d::RangeStruct::id() => ping1d::Messages::Range(ping1d::RangeStruct {
+                scan_start: profile.scan_start,
+                scan_length: profile.scan_length,
+            }),
+            id if id == ping1d::ModeAutoStruct::id() => {
+                ping1d::Messages::ModeAuto(ping1d::ModeAutoStruct {
+                    mode_auto: state.mode_auto,
+                })
+            }
+            id if id == ping1d::PingIntervalStruct::id() => {
+                ping1d::Messages::PingInterval(ping1d::PingIntervalStruct {
+                    ping_interval: state.ping_interval,
+                })
+            }
+            id if id == ping1d::GainSettingStruct::id() => {
+                ping1d::Messages::GainSetting(ping1d::GainSettingStruct {
+                    gain_setting: state.gain_setting,
+                })
+            }
+            id if id == ping1d::TransmitDurationStruct::id() => {
+                ping1d::Messages::TransmitDuration(ping1d::TransmitDurationStruct {
+                    transmit_duration: state.transmit_duration,
+                })
+            }
+            id if id == ping1d::GeneralInfoStruct::id() => {
+                ping1d::Messages::GeneralInfo(ping1d::GeneralInfoStruct {
+                    firmware_version_major: 3,
+                    firmware_version_minor: 29,
+                    voltage_5: 5000,
+                    ping_interval: state.ping_interval,
+                    gain_setting: state.gain_setting as u8,
+                    mode_auto: state.mode_auto,
+                })
+            }
+            id if id == ping1d::ProcessorTemperatureStruct::id() => {
+                ping1d::Messages::ProcessorTemperature(ping1d::ProcessorTemperatureStruct {
+                    processor_temperature: 3500,
+                })
+            }
+            id if id == ping1d::PcbTemperatureStruct::id() => {
+                ping1d::Messages::PcbTemperature(ping1d::PcbTemperatureStruct {
+                    pcb_temperature: 3000,
+                })
+            }
+            id if id == ping1d::PingEnableStruct::id() => {
+                ping1d::Messages::PingEnable(ping1d::PingEnableStruct {
+                    ping_enabled: state.ping_enabled,
+                })
+            }
+            _ => return None,
+        };
+
+        Some(protocol_message(&message))
+    }
+
+    fn ping1d_profile(&mut self) -> ping1d::ProfileStruct {
+        self.ping1d.ping_number = self.ping1d.ping_number.wrapping_add(1);
+
+        let depth = self.settings.depth_mm;
+        if self.ping1d.mode_auto == 1 {
+            // Keep the bottom around the middle of the profile, rounded to meters
+            self.ping1d.scan_start = 0;
+            self.ping1d.scan_length = (depth.saturating_mul(2) / 1000 + 1) * 1000;
+        }
+
+        let scan_start = self.ping1d.scan_start;
+        let scan_length = self.ping1d.scan_length;
+        let gain = 0.5 + self.ping1d.gain_setting as f64 * 0.25;
+        let width = (scan_length / 100).max(50) as f64;
+
+        let profile_data = (0..PING1D_PROFILE_SAMPLES as u32)
+            .map(|index| {
+                let distance = scan_start as f64
+                    + scan_length as f64 * index as f64 / PING1D_PROFILE_SAMPLES as f64;
+                self.sample(distance, None, width, gain)
+            })
+            .collect::<Vec<u8>>();
+
+        let visible = depth >= scan_start && depth <= scan_start.saturating_add(scan_length);
+        let confidence = if visible {
+            100 - (self.settings.noise as u16 * 50 / 255)
+        } else {
+            0
+        };
+
+        ping1d::ProfileStruct {
+            distance: if visible { depth } else { 0 },
+            confidence,
+            transmit_duration: self.ping1d.transmit_duration,
+            ping_number: self.ping1d.ping_number,
+            scan_start,
+            scan_length,
+            gain_setting: self.ping1d.gain_setting,
+            profile_data_length: profile_data.len() as u16,
+            profile_data,
+        }
+    }
+
+    fn ping360_data(
+        &mut self,
+        angle: u16,
+        sample_period: u16,
+        number_of_samples: u16,
+        gain_setting: u8,
+    ) -> Vec<u8> {
+        // Each sample covers sample_period * 25ns of round trip time
+        let sample_distance = sample_period as f64 * 25e-9 * SPEED_OF_SOUND as f64 / 2.0;
+        let width = (sample_distance * number_of_samples as f64 / 100.0).max(50.0);
+        let gain = 0.5 + gain_setting as f64 * 0.5;
+
+        (0..number_of_samples)
+            .map(|index| self.sample(index as f64 * sample_distance, Some(angle), width, gain))
+            .collect()
+    }
+
+    // Signal strength at a distance in millimeters, from the bottom, targets and noise
+    fn sample(&mut self, distance: f64, angle: Option<u16>, width: f64, gain: f64) -> u8 {
+        let depth = self.settings.depth_mm as f64;
+        let mut strength = if distance < depth {
+            echo(distance, depth, width, 255.0)
+        } else {
+            // Weaker returns keep coming from the bottom material
+            255.0 * (-(distance - depth) / (width * 2.0)).exp()
+        };
+
+        for target in &self.settings.targets {
+            let visible = match (target.angle, angle) {
+                (Some(target_angle), Some(angle)) => angle_distance(target_angle, angle) <= 4,
+                _ => true,
+            };
+            if visible {
+                strength += echo(
+                    distance,
+                    target.distance_mm as f64,
+                    width,
+                    target.strength as f64,
+                );
+            }
+        }
+
+        let noise = if self.settings.noise == 0 {
+            0.0
+        } else {
+            (self.next_random() % (self.settings.noise as u64 + 1)) as f64
+        };
+
+        (strength * gain + noise).clamp(0.0, 255.0) as u8
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random
+    }
+}
+
+fn echo(distance: f64, position: f64, width: f64, strength: f64) -> f64 {
+    strength * (-((distance - position) / width).powi(2)).exp()
+}
+
+fn angle_distance(first: u16, second: u16) -> u16 {
+    let difference = (first % PING360_GRADIANS).abs_diff(second % PING360_GRADIANS);
+    difference.min(PING360_GRADIANS - difference)
+}
+
+// Next head angle on auto transmit mode, going back to the start angle at the end of the sector
+fn next_angle(auto_transmit: &ping360::AutoTransmitStruct, angle: u16) -> u16 {
+    let start = auto_transmit.start_angle % PING360_GRADIANS;
+    let stop = auto_transmit.stop_angle % PING360_GRADIANS;
+    let next = (angle + auto_transmit.num_steps.max(1) as u16) % PING360_GRADIANS;
+
+    let in_sector = if start <= stop {
+        next >= start && next <= stop
+    } else {
+        next >= start || next <= stop
+    };
+
+    if in_sector {
+        next
+    } else {
+        start
+    }
+}
+
+// Create a simulator and return the stream that should be used by the ping device
+pub fn start_simulator(source: &SourceSimulatedStruct) -> DuplexStream {
+    let mut hasher = DefaultHasher::new();
+    source.name.hash(&mut hasher);
+
+    let simulator = Simulator::new(
+        source.device_type.clone(),
+        source.settings.clone(),
+        hasher.finish(),
+    );
+    let (device_stream, simulator_stream) = tokio::io::duplex(VIRTUAL_DEVICE_BUFFER_SIZE);
+
+    tokio::spawn(run(simulator, simulator_stream));
+
+    device_stream
+}
+
+async fn run(mut simulator: Simulator, stream: DuplexStream) {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut decoder = Decoder::new();
+    let mut buffer = [0u8; 1024];
+    let mut next_stream: Option<Instant> = None;
+
+    loop {
+        let mut answers = Vec::new();
+
+        tokio::select! {
+            result = reader.read(&mut buffer) => match result {
+                Ok(0) => {
+                    debug!("Simulated device closed, stopping simulator");
+                    break;
+                }
+                Ok(size) => {
+                    for byte in &buffer[..size] {
+                        if let DecoderResult::Success(message) = decoder.parse_byte(*byte) {
+                            answers.extend(simulator.handle_message(&message));
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!("Simulator failed to read device request: {err:?}");
+                    break;
+                }
+            },
+            _ = wait_until(next_stream) => {
+                next_stream = None;
+                answers.extend(simulator.stream_messages());
+            }
+        }
+
+        for answer in answers {
+            if let Err(err) = writer.write_all(&answer.serialized()).await {
+                error!("Simulator failed to send message: {err:?}");
+                return;
+            }
+        }
+
+        next_stream = match (simulator.stream_interval(), next_stream) {
+            (None, _) => None,
+            (Some(interval), None) => Some(Instant::now() + interval),
+            (Some(_), deadline) => deadline,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{
+        devices::{PingAnswer, PingRequest},
+        manager::{
+            Answer, CreateStruct, DeviceManager, DeviceStatus, Request, SourceSelection,
+            UuidWrapper,
+        },
+    };
+    use bluerobotics_ping::common::GeneralRequestStruct;
+
+    fn general_request(requested_id: u16) -> ProtocolMessage {
+        protocol_message(&common::Messages::GeneralRequest(GeneralRequestStruct {
+            requested_id,
+        }))
+    }
+
+    fn settings() -> SimulationSettings {
+        SimulationSettings {
+            depth_mm: 4000,
+            targets: vec![SimulatedTarget {
+                distance_mm: 1000,
+                strength: 200,
+                angle: Some(100),
+            }],
+            noise: 0,
+        }
+    }
+
+    #[test]
+    fn ping1d_answers_requests() {
+        let mut simulator = Simulator::new(SimulatedDeviceType::Ping1D, settings(), 1);
+
+        let answers = simulator.handle_message(&general_request(ping1d::DistanceStruct::id()));
+        let Ok(ping1d::Messages::Distance(distance)) =
+            <ping1d::Messages as DeserializeGenericMessage>::deserialize(
+                answers[0].message_id,
+                &answers[0].payload,
+            )
+        else {
+            panic!("Unexpected answer: {answers:?}");
+        };
+        assert_eq!(distance.distance, 4000);
+        assert_eq!(distance.confidence, 100);
+
+        let answers = simulator.handle_message(&protocol_message(&ping1d::Messages::SetRange(
+            ping1d::SetRangeStruct {
+                scan_start: 0,
+                scan_length: 2000,
+            },
+        )));
+        assert_eq!(answers, vec![ack(ping1d::SetRangeStruct::id())]);
+
+        // Messages the simulator doesn't handle are refused with their own id
+        let answers = simulator.handle_message(&protocol_message(&ping1d::Messages::Distance(
+            Default::default(),
+        )));
+        assert_eq!(
+            answers,
+            vec![nack(ping1d::DistanceStruct::id(), "Unsupported command")]
+        );
+
+        // Bottom is out of the manual range
+        simulator.handle_message(&protocol_message(&ping1d::Messages::SetModeAuto(
+            ping1d::SetModeAutoStruct { mode_auto: 0 },
+        )));
+        let profile = simulator.ping1d_profile();
+        assert_eq!(profile.distance, 0);
+        assert_eq!(profile.profile_data.len(), PING1D_PROFILE_SAMPLES as usize);
+    }
+
+    #[test]
+    fn ping1d_continuous_mode() {
+        let mut simulator = Simulator::new(SimulatedDeviceType::Ping1D, settings(), 1);
+        assert_eq!(simulator.stream_interval(), None);
+
+        simulator.handle_message(&protocol_message(&ping1d::Messages::ContinuousStart(
+            ping1d::ContinuousStartStruct {
+                id: ping1d::ProfileStruct::id(),
+            },
+        )));
+        assert_eq!(
+            simulator.stream_interval(),
+            Some(Duration::from_millis(100))
+        );
+
+        let messages = simulator.stream_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message_id, ping1d::ProfileStruct::id());
+
+        simulator.handle_message(&protocol_message(&ping1d::Messages::ContinuousStop(
+            ping1d::ContinuousStopStruct {
+                id: ping1d::ProfileStruct::id(),
+            },
+        )));
+        assert_eq!(simulator.stream_interval(), None);
+    }
+
+    #[test]
+    fn ping360_echoes() {
+        let mut simulator = Simulator::new(SimulatedDeviceType::Ping360, settings(), 1);
+
+        // 80 * 25ns sample period is 1.5mm per sample at 1500m/s
+        let data = simulator.ping360_data(0, 80, 4000, 0);
+        let peak = data
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, value)| **value)
+            .map(|(index, _)| index)
+            .unwrap();
+        assert!(peak.abs_diff(2666) < 10, "Bottom echo at sample {peak}");
+
+        // Target is only visible around its angle
+        let target_sample = 666;
+        let with_target = simulator.ping360_data(100, 80, 4000, 0)[target_sample];
+        let without_target = simulator.ping360_data(300, 80, 4000, 0)[target_sample];
+        assert!(with_target > without_target);
+    }
+
+    #[test]
+    fn ping360_auto_transmit_sector() {
+        let mut simulator = Simulator::new(SimulatedDeviceType::Ping360, settings(), 1);
+
+        simulator.handle_message(&protocol_message(&ping360::Messages::AutoTransmit(
+            ping360::AutoTransmitStruct {
+                mode: 1,
+                gain_setting: 0,
+                transmit_duration: 32,
+                sample_period: 80,
+                transmit_frequency: 750,
+                number_of_samples: 100,
+                start_angle: 390,
+                stop_angle: 10,
+                num_steps: 10,
+                delay: 0,
+            },
+        )));
+        assert!(simulator.stream_interval().is_some());
+
+        let angles: Vec<u16> = (0..4)
+            .map(|_| {
+                let message = simulator.stream_messages().remove(0);
+                match <ping360::Messages as DeserializeGenericMessage>::deserialize(
+                    message.message_id,
+                    &message.payload,
+                ) {
+                    Ok(ping360::Messages::AutoDeviceData(data)) => data.angle,
+                    other => panic!("Unexpected message: {other:?}"),
+                }
+            })
+            .collect();
+        assert_eq!(angles, vec![390, 0, 10, 390]);
+
+        let answers = simulator.handle_message(&protocol_message(&ping360::Messages::MotorOff(
+            ping360::MotorOffStruct {},
+        )));
+        assert_eq!(answers, vec![ack(ping360::MotorOffStruct::id())]);
+        assert_eq!(simulator.stream_interval(), None);
+    }
+
+    #[tokio::test]
+    async fn simulated_ping360_continuous_mode() {
+        let (manager, handler) = DeviceManager::new(10);
+        tokio::spawn(async move { manager.run().await });
+
+        let source = SourceSelection::Simulated(SourceSimulatedStruct {
+            name: "simulated_ping360_continuous_mode".to_string(),
+            device_type: SimulatedDeviceType::Ping360,
+            settings: SimulationSettings::default(),
+        });
+        let answer = handler
+            .send(Request::Create(CreateStruct {
+                source,
+                device_selection: DeviceSelection::Auto,
+            }))
+            .await
+            .unwrap();
+        let Answer::DeviceInfo(info) = answer else {
+            panic!("Unexpected answer: {answer:?}");
+        };
+        assert_eq!(info[0].device_type, DeviceSelection::Ping360);
+        assert_eq!(info[0].status, DeviceStatus::ContinuousMode);
+
+        let Answer::InnerDeviceHandler(device_handler) = handler
+            .send(Request::GetDeviceHandler(UuidWrapper { uuid: info[0].id }))
+            .await
+            .unwrap()
+        else {
+            panic!("Unexpected device handler answer");
+        };
+        let Ok(PingAnswer::Subscriber(mut subscriber)) =
+            device_handler.send(PingRequest::GetSubscriber).await
+        else {
+            panic!("Unexpected subscriber answer");
+        };
+
+        let message = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let message = subscriber.recv().await.unwrap();
+                if message.message_id == ping360::AutoDeviceDataStruct::id() {
+                    return message;
+                }
+            }
+        })
+        .await
+        .expect("No auto device data received from simulator");
+        assert!(!message.payload.is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+use bluerobotics_ping::decoder::{Decoder, DecoderResult};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+};
+use tokio::{net::UdpSocket, sync::watch, time::Instant};
+use tracing::{debug, info, trace, warn};
+
+use super::{SimulatedDeviceType, SimulationSettings, Simulator};
+use crate::device::virtual_device::wait_until;
+
+pub static DISCOVERY_PORT: u16 = 30303;
+pub static DATA_PORT: u16 = 12345;
+
+#[derive(Clone, Debug)]
+pub struct UdpEmulatorConfig {
+    /// Address of the emulated device, must be a local address, changed by SetSS1IP commands.
+    pub ip: Ipv4Addr,
+    pub discovery_port: u16,
+    pub data_port: u16,
+    pub mac_address: String,
+    pub settings: SimulationSettings,
+}
+
+impl Default for UdpEmulatorConfig {
+    fn default() -> Self {
+        Self {
+            ip: Ipv4Addr::LOCALHOST,
+            discovery_port: DISCOVERY_PORT,
+            data_port: DATA_PORT,
+            mac_address: "54-10-EC-00-00-01".to_string(),
+            settings: SimulationSettings::default(),
+        }
+    }
+}
+
+// Emulate a Ping360 Ethernet unit, answering network discovery and the ping protocol over UDP
+pub async fn run_udp_emulator(config: UdpEmulatorConfig) -> std::io::Result<()> {
+    let discovery_socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, config.discovery_port)).await?;
+    let data_socket = UdpSocket::bind((config.ip, config.data_port)).await?;
+
+    info!(
+        "Ping360 emulator running, discovery on port {}, data on port {}",
+        config.discovery_port, config.data_port
+    );
+
+    let (ip_sender, ip_receiver) = watch::channel(config.ip);
+    let discovery = tokio::spawn(run_discovery(
+        discovery_socket,
+        ip_sender,
+        config.mac_address.clone(),
+    ));
+
+    let result = run_data(data_socket, ip_receiver, config.data_port, config.settings).await;
+    discovery.abort();
+    result
+}
+
+// ASCII reply sent by the Ping360 Ethernet board to discovery requests
+pub fn discovery_response(ip: Ipv4Addr, mac_address: &str) -> String {
+    let [first, second, third, fourth] = ip.octets();
+    format!(
+        "SONAR PING360\r\nBlue Robotics\r\nMAC Address:- {mac_address}\r\nIP Address:- {first:03}.{second:03}.{third:03}.{fourth:03}\r\n"
+    )
+}
+
+async fn run_discovery(socket: UdpSocket, ip: watch::Sender<Ipv4Addr>, mac_address: String) {
+    let mut buffer = [0u8; 1024];
+
+    loop {
+        let (size, peer) = match socket.recv_from(&mut buffer).await {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("Ping360 emulator: Failed to receive discovery request: {err}");
+                continue;
+            }
+        };
+
+        let request = String::from_utf8_lossy(&buffer[..size]);
+        let request = request.trim();
+
+        if request == "Discovery" {
+            let response = discovery_response(*ip.borrow(), &mac_address);
+            if let Err(err) = socket.send_to(response.as_bytes(), peer).await {
+                warn!("Ping360 emulator: Failed to answer discovery from {peer}: {err}");
+            }
+        } else if let Some(new_ip) = request.strip_prefix("SetSS1IP ") {
+            match new_ip.trim().parse::<Ipv4Addr>() {
+                Ok(new_ip) => {
+                    info!("Ping360 emulator: IP address changed to {new_ip}");
+                    ip.send_replace(new_ip);
+                }
+                Err(err) => warn!("Ping360 emulator: Invalid SetSS1IP request {request:?}: {err}"),
+            }
+        } else {
+            debug!("Ping360 emulator: Ignoring discovery request {request:?} from {peer}");
+        }
+    }
+}
+
+async fn run_data(
+    mut socket: UdpSocket,
+    mut ip: watch::Receiver<Ipv4Addr>,
+    port: u16,
+    settings: SimulationSettings,
+) -> std::io::Result<()> {
+    let mut simulator = Simulator::new(SimulatedDeviceType::Ping360, settings, 1);
+    let mut decoders: HashMap<SocketAddr, Decoder> = HashMap::new();
+    let mut buffer = [0u8; 2048];
+    let mut next_stream: Option<Instant> = None;
+    // Auto transmit data goes to the last host that talked to the device
+    let mut host: Option<SocketAddr> = None;
+
+    loop {
+        let answers = tokio::select! {
+            result = socket.recv_from(&mut buffer) => {
+                let (size, peer) = match result {
+                    Ok(result) => result,
+                    // Hosts that went away make the next receive fail, keep serving the others
+                    Err(err) => {
+                        debug!("Ping360 emulator: Failed to receive data: {err}");
+                        continue;
+                    }
+                };
+
+                // Empty datagrams are used to stop the auto transmit mode
+                if size == 0 {
+                    debug!("Ping360 emulator: Empty datagram from {peer}, stopping auto transmit");
+                    simulator.stop_streaming();
+                    continue;
+                }
+
+                host = Some(peer);
+                let decoder = decoders.entry(peer).or_insert_with(Decoder::new);
+                let mut answers = Vec::new();
+                for byte in &buffer[..size] {
+                    if let DecoderResult::Success(message) = decoder.parse_byte(*byte) {
+                        trace!("Ping360 emulator: Message {} from {peer}", message.message_id);
+                        answers.extend(simulator.handle_message(&message));
+                    }
+                }
+                answers
+            }
+            _ = wait_until(next_stream) => {
+                next_stream = None;
+                simulator.stream_messages()
+            }
+            Ok(()) = ip.changed() => {
+                // The device restarts with the new address, like the real one
+                let new_ip = *ip.borrow_and_update();
+                drop(socket);
+                socket = UdpSocket::bind((new_ip, port)).await?;
+                simulator.stop_streaming();
+                decoders.clear();
+                host = None;
+                continue;
+            }
+        };
+
+        if let Some(host) = host {
+            for answer in answers {
+                if let Err(err) = socket.send_to(&answer.serialized(), host).await {
+                    warn!("Ping360 emulator: Failed to send message to {host}: {err}");
+                    break;
+                }
+            }
+        }
+
+        next_stream = match (simulator.stream_interval(), next_stream) {
+            (None, _) => None,
+            (Some(interval), None) => Some(Instant::now() + interval),
+            (Some(_), deadline) => deadline,
+        };
+    }
+}
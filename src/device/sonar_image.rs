@@ -0,0 +1,323 @@
+use bluerobotics_ping::{
+    message::{MessageInfo, ProtocolMessage},
+    ping360, Messages,
+};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::{
+    sync::{
+        broadcast::{error::RecvError, Receiver},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::device::{export::png, manager::ManagerError};
+
+static GRADIANS: usize = 400;
+// Ping360 sample period unit, in seconds
+static SAMPLE_PERIOD_TICK_S: f32 = 25e-9;
+// Gradians filled between two pings, larger jumps are a new scan and not a gap
+static MAX_FILLED_GRADIANS: usize = 10;
+
+#[derive(Clone, Debug, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+#[serde(default)]
+pub struct SonarImageConfig {
+    /// Width and height of the image, in pixels.
+    pub size: u32,
+    /// Speed of sound in water, in meters per second.
+    pub speed_of_sound: f32,
+}
+
+impl Default for SonarImageConfig {
+    fn default() -> Self {
+        Self {
+            size: 512,
+            speed_of_sound: 1500.0,
+        }
+    }
+}
+
+impl SonarImageConfig {
+    pub fn validate(&self) -> Result<(), ManagerError> {
+        if !(64..=2048).contains(&self.size) {
+            return Err(ManagerError::Other(format!(
+                "sonar_image: Size should be between 64 and 2048 pixels, got {}",
+                self.size
+            )));
+        }
+        if !(self.speed_of_sound.is_finite() && self.speed_of_sound > 0.0) {
+            return Err(ManagerError::Other(format!(
+                "sonar_image: Invalid speed of sound: {}",
+                self.speed_of_sound
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Cartesian image of the last Ping360 scan, the head angle 0 points up and the sonar is in the center.
+pub struct SonarImage {
+    size: u32,
+    // Gray and alpha, pixels never drawn are transparent
+    pixels: Vec<u8>,
+    // Pixels of each gradian, with the distance from the center normalized by the image radius
+    wedges: Vec<Vec<(usize, f32)>>,
+    range_m: f32,
+    last_angle: Option<u16>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Apiv2Schema, PartialEq)]
+pub struct SonarImageInfo {
+    pub config: SonarImageConfig,
+    /// Distance represented by the image radius, in meters.
+    pub range_m: f32,
+    /// Head angle of the last drawn ping, in gradians.
+    pub angle: Option<u16>,
+    /// Incremented for every drawn ping.
+    pub revision: u64,
+}
+
+impl SonarImage {
+    pub fn new(size: u32) -> Self {
+        let center = size as f32 / 2.0;
+        let mut wedges = vec![Vec::new(); GRADIANS];
+
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x as f32 + 0.5 - center;
+                let dy = center - (y as f32 + 0.5);
+                let radius = (dx * dx + dy * dy).sqrt() / center;
+                if radius > 1.0 {
+                    continue;
+                }
+                // Clockwise from the top, like the Ping360 head
+                let angle = dx.atan2(dy).to_degrees().rem_euclid(360.0);
+                let gradian = (angle * GRADIANS as f32 / 360.0).round() as usize % GRADIANS;
+                wedges[gradian].push(((y * size + x) as usize, radius));
+            }
+        }
+
+        Self {
+            size,
+            pixels: vec![0; (size * size * 2) as usize],
+            wedges,
+            range_m: 0.0,
+            last_angle: None,
+        }
+    }
+
+    pub fn range_m(&self) -> f32 {
+        self.range_m
+    }
+
+    pub fn last_angle(&self) -> Option<u16> {
+        self.last_angle
+    }
+
+    // Draw the samples of a ping, the image is cleared when the range changes
+    pub fn draw(&mut self, angle: u16, sample_period: u16, data: &[u8], speed_of_sound: f32) {
+        if data.is_empty() {
+            return;
+        }
+
+        let range_m =
+            data.len() as f32 * sample_period as f32 * SAMPLE_PERIOD_TICK_S * speed_of_sound / 2.0;
+        if (range_m - self.range_m).abs() > 1e-3 {
+            self.pixels.fill(0);
+            self.range_m = range_m;
+            self.last_angle = None;
+        }
+
+        let angle = angle as usize % GRADIANS;
+        // Pings more than one gradian apart would leave gaps, the arc from the last ping is filled
+        let (first, count) = match self.last_angle.map(|last| last as usize) {
+            Some(last) => {
+                let forward = (angle + GRADIANS - last) % GRADIANS;
+                if forward <= GRADIANS / 2 {
+                    (last + 1, forward)
+                } else {
+                    (angle, GRADIANS - forward)
+                }
+            }
+            None => (angle, 1),
+        };
+        let (first, count) = if (1..=MAX_FILLED_GRADIANS).contains(&count) {
+            (first, count)
+        } else {
+            (angle, 1)
+        };
+
+        for gradian in (first..first + count).map(|gradian| gradian % GRADIANS) {
+            for &(pixel, radius) in &self.wedges[gradian] {
+                let sample = ((radius * data.len() as f32) as usize).min(data.len() - 1);
+                self.pixels[pixel * 2] = data[sample];
+                self.pixels[pixel * 2 + 1] = u8::MAX;
+            }
+        }
+
+        self.last_angle = Some(angle as u16);
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        let mut png = Vec::new();
+        let row_size = self.size as usize * 2;
+        png::write_gray_alpha(&mut png, self.size, self.size, self.pixels.chunks(row_size))
+            .expect("Writing to memory can't fail");
+        png
+    }
+}
+
+// Head angle, sample period and samples of Ping360 data messages
+fn ping_data(message: &ProtocolMessage) -> Option<(u16, u16, Vec<u8>)> {
+    if message.message_id != ping360::DeviceDataStruct::id()
+        && message.message_id != ping360::AutoDeviceDataStruct::id()
+    {
+        return None;
+    }
+
+    match Messages::try_from(message).ok()? {
+        Messages::Ping360(ping360::Messages::DeviceData(data)) => {
+            Some((data.angle, data.sample_period, data.data))
+        }
+        Messages::Ping360(ping360::Messages::AutoDeviceData(data)) => {
+            Some((data.angle, data.sample_period, data.data))
+        }
+        _ => None,
+    }
+}
+
+/// Shared access to the image of a device, the revision changes after every drawn ping.
+#[derive(Clone)]
+pub struct SonarImageHandle {
+    pub config: SonarImageConfig,
+    image: Arc<Mutex<SonarImage>>,
+    revision: watch::Receiver<u64>,
+}
+
+impl std::fmt::Debug for SonarImageHandle {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter
+            .debug_struct("SonarImageHandle")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl SonarImageHandle {
+    pub fn info(&self) -> SonarImageInfo {
+        let image = self.image.lock().unwrap();
+        SonarImageInfo {
+            config: self.config.clone(),
+            range_m: image.range_m(),
+            angle: image.last_angle(),
+            revision: *self.revision.borrow(),
+        }
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        self.image.lock().unwrap().encode_png()
+    }
+
+    // Receiver notified after every drawn ping, closed once the device stream ends
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.revision.clone()
+    }
+}
+
+// Keep the image updated with the device pings until the device stream closes
+pub fn start_sonar_image(
+    device_id: Uuid,
+    config: SonarImageConfig,
+    mut subscriber: Receiver<ProtocolMessage>,
+) -> Result<(SonarImageHandle, JoinHandle<()>), ManagerError> {
+    config.validate()?;
+
+    let image = Arc::new(Mutex::new(SonarImage::new(config.size)));
+    let (revision_sender, revision) = watch::channel(0u64);
+    let handle = SonarImageHandle {
+        config: config.clone(),
+        image: image.clone(),
+        revision,
+    };
+
+    let task = tokio::spawn(async move {
+        loop {
+            let message = match subscriber.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Sonar image skipped {skipped} messages, device: {device_id}");
+                    continue;
+                }
+                Err(RecvError::Closed) => {
+                    debug!("Sonar image stopped, device stream closed, device: {device_id}");
+                    break;
+                }
+            };
+
+            let Some((angle, sample_period, data)) = ping_data(&message) else {
+                continue;
+            };
+            image
+                .lock()
+                .unwrap()
+                .draw(angle, sample_period, &data, config.speed_of_sound);
+            revision_sender.send_modify(|revision| *revision += 1);
+        }
+    });
+
+    Ok((handle, task))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Farthest pixel of a gradian, with the sample expected there
+    fn wedge_pixel(image: &SonarImage, gradian: usize, samples: usize) -> ([u8; 2], u8) {
+        let &(pixel, radius) = image.wedges[gradian]
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        let expected = ((radius * samples as f32) as usize).min(samples - 1) as u8;
+        (
+            [image.pixels[pixel * 2], image.pixels[pixel * 2 + 1]],
+            expected,
+        )
+    }
+
+    #[test]
+    fn draws_pings_by_angle_and_range() {
+        let mut image = SonarImage::new(256);
+        assert!(image.wedges.iter().all(|wedge| !wedge.is_empty()));
+        let data: Vec<u8> = (0..100).collect();
+        // 1 meter per sample at 1500 m/s
+        image.draw(0, 53_333, &data, 1500.0);
+        assert!((image.range_m() - 100.0).abs() < 0.1);
+
+        let (pixel, expected) = wedge_pixel(&image, 0, data.len());
+        assert_eq!(pixel, [expected, 255]);
+        assert!(expected > 90);
+        assert_eq!(wedge_pixel(&image, 2, data.len()).0, [0, 0]);
+
+        // The arc between both pings is filled, but not a new scan far away
+        image.draw(4, 53_333, &data, 1500.0);
+        for gradian in 1..=4 {
+            let (pixel, expected) = wedge_pixel(&image, gradian, data.len());
+            assert_eq!(pixel, [expected, 255]);
+        }
+        image.draw(200, 53_333, &data, 1500.0);
+        assert_eq!(wedge_pixel(&image, 199, data.len()).0, [0, 0]);
+
+        // Range changes clear the previous scan
+        image.draw(100, 26_666, &data, 1500.0);
+        assert_eq!(wedge_pixel(&image, 0, data.len()).0, [0, 0]);
+        assert_eq!(wedge_pixel(&image, 100, data.len()).0[1], 255);
+
+        let png = image.encode_png();
+        assert_eq!(png[..8], png::PNG_SIGNATURE);
+    }
+}
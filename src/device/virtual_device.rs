@@ -0,0 +1,34 @@
+use bluerobotics_ping::{
+    common,
+    message::{PingMessage, ProtocolMessage},
+};
+use tokio::time::Instant;
+
+// Size of the in-memory stream between a ping device and its virtual counterpart
+pub static VIRTUAL_DEVICE_BUFFER_SIZE: usize = 64 * 1024;
+
+// Wrap a message into a package ready to be sent
+pub fn protocol_message(message: &impl PingMessage) -> ProtocolMessage {
+    let mut package = ProtocolMessage::new();
+    package.set_message(message);
+    package
+}
+
+pub fn ack(acked_id: u16) -> ProtocolMessage {
+    protocol_message(&common::Messages::Ack(common::AckStruct { acked_id }))
+}
+
+pub fn nack(nacked_id: u16, nack_message: &str) -> ProtocolMessage {
+    protocol_message(&common::Messages::Nack(common::NackStruct {
+        nacked_id,
+        nack_message: nack_message.to_string(),
+    }))
+}
+
+// Sleep until the deadline, or forever when nothing is scheduled
+pub async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
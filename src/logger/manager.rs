@@ -41,8 +41,11 @@ pub fn init() {
 
     let dir = get_app_log_dir();
 
+    #[cfg(feature = "desktop-app")]
+    let migrated_logs = migrate_desktop_logs(&dir);
+
     let file_appender = custom_rolling_appender(
-        dir,
+        &dir,
         tracing_appender::rolling::Rotation::HOURLY,
         "ping-viewer",
         "log",
@@ -113,6 +116,13 @@ pub fn init() {
         env!("VERGEN_CARGO_DEPENDENCIES"),
     );
 
+    #[cfg(feature = "desktop-app")]
+    match migrated_logs {
+        Ok(0) => (),
+        Ok(moved) => info!("Moved {moved} log files from the previous log folder into {dir:?}"),
+        Err(err) => warn!("Failed to move the previous log files into {dir:?}: {err}"),
+    }
+
     info!(
         "Starting at {}",
         chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
@@ -139,13 +149,48 @@ fn custom_rolling_appender<P: AsRef<std::path::Path>>(
         .expect("failed to initialize rolling file appender")
 }
 
-#[allow(unused)]
 #[cfg(feature = "desktop-app")]
 static APP_DIR: &str = "Ping-Viewer-Next";
 
+// The desktop app keeps its settings, logs and certificates in its own folder of the home directory
+#[cfg(feature = "desktop-app")]
+pub fn get_app_home_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("failed to get homedir")
+        .join(APP_DIR)
+}
+
+// The desktop app used to write its logs to `~/logs`, move its own files to the new log folder
 #[cfg(feature = "desktop-app")]
+fn migrate_desktop_logs(dir: &std::path::Path) -> std::io::Result<usize> {
+    let previous_dir = dirs::home_dir()
+        .expect("failed to get homedir")
+        .join("logs");
+    if !previous_dir.is_dir() {
+        return Ok(0);
+    }
+
+    std::fs::create_dir_all(dir)?;
+    let mut moved = 0;
+    for entry in std::fs::read_dir(&previous_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // Only the files from our rolling appender, the folder may be shared with other apps
+        if name.starts_with("ping-viewer.") && name.ends_with(".log") {
+            std::fs::rename(&path, dir.join(name))?;
+            moved += 1;
+        }
+    }
+
+    Ok(moved)
+}
+
+// Outside the desktop app, files are kept next to the working directory, like logs and recordings.
+#[cfg(not(feature = "desktop-app"))]
 pub fn get_app_home_dir() -> PathBuf {
-    dirs::home_dir().expect("failed to get homedir")
+    PathBuf::from(".")
 }
 
 pub fn get_app_log_dir() -> PathBuf {
@@ -1,8 +1,11 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::RwLock;
-use tracing::info;
+use tracing::{error, info};
 
-use ping_viewer_next::{cli, device, logger, server, vehicle::zenoh_client_bridge};
+use ping_viewer_next::{
+    cli, device, logger, server, settings,
+    vehicle::{vehicle_data_bridge, PoseHistory},
+};
 
 #[tokio::main]
 async fn main() {
@@ -11,14 +14,47 @@ async fn main() {
     // Logger should start before everything else to register any log information
     logger::manager::init();
 
-    let vehicle_data = Arc::new(RwLock::new(None));
+    if let Some(export) = cli::manager::export_command() {
+        match device::export::export_recording(
+            &export.recording,
+            &export.output,
+            export.format,
+            &export.settings,
+        ) {
+            Ok(summary) => info!("Export finished: {summary:?}"),
+            Err(err) => {
+                error!("Export failed: {err:?}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Settings should start before the DeviceManager to allow devices to be restored
+    settings::manager::init();
+
+    let vehicle_data = Arc::new(RwLock::new(PoseHistory::new(
+        Duration::from_secs(10),
+        cli::manager::vehicle_pose_max_age(),
+    )));
 
-    // Start the Zenoh-client with shared data
-    tokio::spawn(zenoh_client_bridge(vehicle_data.clone()));
+    // Start the selected vehicle data source with shared data
+    tokio::spawn(vehicle_data_bridge(
+        cli::manager::vehicle_bridge_settings(),
+        vehicle_data.clone(),
+    ));
 
     let (mut manager, handler) = device::manager::DeviceManager::new(10);
 
-    //Todo: Load previous devices
+    match manager.restore_devices().await {
+        Ok(answer) => {
+            info!("DeviceManager restored the following devices from settings: {answer:?}")
+        }
+        Err(err) => {
+            error!("DeviceManager unable to restore devices from settings, details {err:?}")
+        }
+    }
+
     if cli::manager::is_enable_auto_create() {
         match manager.auto_create().await {
             Ok(answer) => info!("DeviceManager initialized with following devices: {answer:?}"),
@@ -0,0 +1,362 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, Method},
+    middleware::Next,
+    web::{Data, Query},
+    HttpMessage, HttpRequest,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::{
+    device::manager::{ModifyDeviceCommand, Request},
+    server::protocols::v1::errors::Error,
+};
+
+// First path segments served with authentication, the frontend and the API documentation stay public
+static PROTECTED_SEGMENTS: [&str; 7] = [
+    "device_manager",
+    "recordings_manager",
+    "recordings",
+    "exports",
+    "vehicle",
+    "websocket_manager",
+    "ws",
+];
+
+/// Access granted by an API token, admin tokens can also read.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    Read,
+    Admin,
+}
+
+impl FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "read" => Ok(Self::Read),
+            "admin" => Ok(Self::Admin),
+            unknown => Err(format!(
+                "Invalid token scope {unknown:?}, expected read or admin"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiToken {
+    pub token: String,
+    pub scope: TokenScope,
+}
+
+// Parse TOKEN:SCOPE, tokens without a scope can only read
+impl FromStr for ApiToken {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (token, scope) = match value.rsplit_once(':') {
+            Some((token, scope)) => (token, scope.parse()?),
+            None => (value, TokenScope::Read),
+        };
+        if token.is_empty() {
+            return Err(format!("Invalid API token {value:?}, the token is empty"));
+        }
+
+        Ok(Self {
+            token: token.to_string(),
+            scope,
+        })
+    }
+}
+
+/// Tokens accepted by the server, authentication is disabled while there are none.
+#[derive(Debug, Clone, Default)]
+pub struct AuthSettings {
+    tokens: Vec<ApiToken>,
+}
+
+impl AuthSettings {
+    pub fn new(tokens: Vec<ApiToken>) -> Self {
+        Self { tokens }
+    }
+
+    // Tokens from the command line and the settings file
+    pub fn from_config() -> Self {
+        let mut tokens = crate::cli::manager::api_tokens();
+        tokens.extend(crate::settings::manager::api_tokens());
+        Self::new(tokens)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    pub fn scope(&self, token: &str) -> Option<TokenScope> {
+        // Every token is compared to not leak which one matched through timing
+        self.tokens
+            .iter()
+            .filter(|api_token| constant_time_eq(api_token.token.as_bytes(), token.as_bytes()))
+            .map(|api_token| api_token.scope)
+            .max()
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+// Bearer token from the Authorization header, or the token query for websockets and download links
+fn request_token(req: &ServiceRequest) -> Option<String> {
+    if let Some(value) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    {
+        return value
+            .strip_prefix("Bearer ")
+            .map(|token| token.trim().to_string());
+    }
+
+    Query::<TokenQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.into_inner().token)
+}
+
+// Request line for the access log, with the token query redacted
+pub fn logged_request_line(req: &ServiceRequest) -> String {
+    let query = req
+        .query_string()
+        .split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| match parameter.split_once('=') {
+            Some(("token", _)) => "token=REDACTED",
+            _ => parameter,
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    let target = if query.is_empty() {
+        req.path().to_string()
+    } else {
+        format!("{}?{query}", req.path())
+    };
+    format!("{} {target} {:?}", req.method(), req.version())
+}
+
+// Scope required by a route, device manager requests are also checked one by one
+pub fn required_scope(method: &Method, path: &str) -> Option<TokenScope> {
+    let path = path.trim_start_matches('/');
+    let path = path.strip_prefix("v1/").unwrap_or(path);
+    let segment = path.split('/').next().unwrap_or_default();
+    if !PROTECTED_SEGMENTS.contains(&segment) {
+        return None;
+    }
+
+    if segment == "device_manager" || matches!(*method, Method::GET | Method::HEAD) {
+        Some(TokenScope::Read)
+    } else {
+        Some(TokenScope::Admin)
+    }
+}
+
+// Scope required by a device manager request
+pub fn request_scope(request: &Request) -> TokenScope {
+    match request {
+        Request::List
+        | Request::Info(_)
+        | Request::Search
+        | Request::GetNmeaOutput(_)
+        | Request::GetMavlinkOutput(_)
+        | Request::GetLease(_) => TokenScope::Read,
+        Request::ModifyDevice(modify) => match modify.modify {
+            ModifyDeviceCommand::GetPing360Config
+            | ModifyDeviceCommand::GetPing360ScanSchedule
+            | ModifyDeviceCommand::GetPing1DContinuousConfig
+            | ModifyDeviceCommand::GetPing1DConfig => TokenScope::Read,
+            _ => TokenScope::Admin,
+        },
+        _ => TokenScope::Admin,
+    }
+}
+
+// Scope granted to a request, servers without the auth middleware have no tokens
+pub fn granted_scope(req: &HttpRequest) -> TokenScope {
+    req.extensions()
+        .get::<TokenScope>()
+        .copied()
+        .unwrap_or(TokenScope::Admin)
+}
+
+pub fn authorize_request(req: &HttpRequest, request: &Request) -> Result<(), Error> {
+    let required = request_scope(request);
+    if granted_scope(req) < required {
+        return Err(Error::Forbidden(format!(
+            "The API token can't be used for {required:?} requests"
+        )));
+    }
+    Ok(())
+}
+
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let settings = req
+        .app_data::<Data<AuthSettings>>()
+        .map(|settings| settings.get_ref().clone())
+        .unwrap_or_default();
+
+    // Preflight requests carry no credentials
+    let required = if settings.is_enabled() && req.method() != Method::OPTIONS {
+        required_scope(req.method(), req.path())
+    } else {
+        None
+    };
+    let Some(required) = required else {
+        req.extensions_mut().insert(TokenScope::Admin);
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    let error = match request_token(&req).and_then(|token| settings.scope(&token)) {
+        None => Error::Unauthorized("Missing or invalid API token".to_string()),
+        Some(scope) if scope < required => Error::Forbidden(format!(
+            "The API token can't be used for {} {}",
+            req.method(),
+            req.path()
+        )),
+        Some(scope) => {
+            req.extensions_mut().insert(scope);
+            return Ok(next.call(req).await?.map_into_boxed_body());
+        }
+    };
+
+    Ok(req.error_response(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::StatusCode,
+        middleware::from_fn,
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    #[test]
+    fn tokens_and_scopes() {
+        let admin: ApiToken = "secret:admin".parse().unwrap();
+        assert_eq!(admin.scope, TokenScope::Admin);
+        let read: ApiToken = "viewer".parse().unwrap();
+        assert_eq!(
+            (read.token.as_str(), read.scope),
+            ("viewer", TokenScope::Read)
+        );
+        assert!("secret:owner".parse::<ApiToken>().is_err());
+        assert!(":admin".parse::<ApiToken>().is_err());
+
+        let settings = AuthSettings::new(vec![admin, read]);
+        assert_eq!(settings.scope("secret"), Some(TokenScope::Admin));
+        assert_eq!(settings.scope("viewer"), Some(TokenScope::Read));
+        assert_eq!(settings.scope("secre"), None);
+
+        assert_eq!(required_scope(&Method::GET, "/docs"), None);
+        assert_eq!(required_scope(&Method::GET, "/index.html"), None);
+        assert_eq!(
+            required_scope(&Method::GET, "/v1/recordings/download/a.mcap"),
+            Some(TokenScope::Read)
+        );
+        assert_eq!(
+            required_scope(&Method::DELETE, "/recordings/delete/a.mcap"),
+            Some(TokenScope::Admin)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/device_manager/request"),
+            Some(TokenScope::Read)
+        );
+        assert_eq!(required_scope(&Method::GET, "/ws"), Some(TokenScope::Read));
+        assert_eq!(
+            logged_request_line(
+                &TestRequest::get()
+                    .uri("/ws?format=binary&token=secret")
+                    .to_srv_request()
+            ),
+            "GET /ws?format=binary&token=REDACTED HTTP/1.1"
+        );
+        assert_eq!(request_scope(&Request::List), TokenScope::Read);
+        assert_eq!(request_scope(&Request::AutoCreate), TokenScope::Admin);
+    }
+
+    #[actix_web::test]
+    async fn middleware_checks_tokens() {
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(AuthSettings::new(vec![
+                    "secret:admin".parse().unwrap(),
+                    "viewer:read".parse().unwrap(),
+                ])))
+                .wrap(from_fn(authenticate))
+                .route("/docs", web::get().to(HttpResponse::Ok))
+                .route("/recordings/list", web::get().to(HttpResponse::Ok))
+                .route(
+                    "/recordings/delete/{file}",
+                    web::delete().to(HttpResponse::Ok),
+                ),
+        )
+        .await;
+
+        let status = |request: TestRequest| {
+            let app = &app;
+            async move { call_service(app, request.to_request()).await.status() }
+        };
+        let bearer = |token: &str| (header::AUTHORIZATION, format!("Bearer {token}"));
+
+        assert_eq!(
+            status(TestRequest::get().uri("/docs")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(TestRequest::get().uri("/recordings/list")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(
+                TestRequest::get()
+                    .uri("/recordings/list")
+                    .insert_header(bearer("wrong"))
+            )
+            .await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(TestRequest::get().uri("/recordings/list?token=viewer")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(
+                TestRequest::delete()
+                    .uri("/recordings/delete/a.mcap")
+                    .insert_header(bearer("viewer"))
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(
+                TestRequest::delete()
+                    .uri("/recordings/delete/a.mcap")
+                    .insert_header(bearer("secret"))
+            )
+            .await,
+            StatusCode::OK
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use crate::device::{manager::ManagerActorHandler, recording::RecordingsManagerHandler};
 
-use super::protocols;
+use super::{auth::AuthSettings, protocols, tls};
 use actix_cors::Cors;
 use actix_web::{middleware, web::Data, App, HttpServer};
-use tracing::info;
+use tracing::{info, warn};
 
 use paperclip::actix::{
     web::{self, Scope},
@@ -22,6 +22,17 @@ pub async fn run(
     let server_address = server_address.to_string();
     info!("ServerManager: Service starting");
 
+    let auth = AuthSettings::from_config();
+    if auth.is_enabled() {
+        info!("ServerManager: API token authentication enabled");
+    } else {
+        warn!("ServerManager: No API tokens configured, authentication is disabled");
+    }
+
+    tokio::spawn(protocols::v1::websocket::forward_recording_status(
+        recordings_handler.clone(),
+    ));
+
     let server = HttpServer::new(move || {
         let cors = Cors::permissive();
 
@@ -31,8 +42,17 @@ pub async fn run(
         App::new()
             .app_data(Data::new(devices_manager_handler.clone()))
             .app_data(Data::new(recordings_handler.clone()))
+            .app_data(Data::new(auth.clone()))
+            // Cors wraps the authentication to answer preflight requests and add headers to its errors
+            .wrap(middleware::from_fn(super::auth::authenticate))
             .wrap(cors)
-            .wrap(middleware::Logger::default())
+            // Default format, with tokens removed from the request line
+            .wrap(
+                middleware::Logger::new(
+                    "%a \"%{request_line}xi\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T",
+                )
+                .custom_request_replace("request_line", super::auth::logged_request_line),
+            )
             .wrap_api()
             .with_json_spec_at("/api/spec")
             .with_swagger_ui_at("/docs")
@@ -40,10 +60,19 @@ pub async fn run(
             .service(protocols::v1::rest::server_metadata)
             .service(protocols::v1::websocket::websocket)
             .service(protocols::v1::websocket::recording_websocket)
+            .service(protocols::v1::websocket::sonar_image_websocket)
+            .service(protocols::v1::websocket::device_events_websocket)
             .service(default)
             .build()
     });
 
+    if let Some(tls_settings) = crate::cli::manager::tls_settings() {
+        let config = tls::server_config(&tls_settings, &tls::certificate_names(&server_address))?;
+        let listener = std::net::TcpListener::bind(&server_address)?;
+        info!("ServerManager: HTTPS server running at https://{server_address}");
+        return server.listen_rustls_0_23(listener, config)?.run().await;
+    }
+
     info!("ServerManager: HTTP server running at http://{server_address}");
     server.bind(server_address)?.run().await
 }
@@ -1,5 +1,7 @@
+pub mod auth;
 pub mod manager;
 pub mod protocols;
+pub mod tls;
 
 // The Server module consists of a manager and all available layers that provide access to internal services.
 //
@@ -20,6 +22,37 @@ pub mod protocols;
 // Users can use the following queries:
 //     ?filter="some_desired_string_to_use_regex"
 //     ?device-number="00000000-0000-0000-b9c0-f5752d453eb3" // The UUID provided by the source of the device created
+//     ?format=binary // Device messages as binary frames: 16 bytes of the device UUID followed by the Ping protocol message
+//     ?max_rate=10 // Maximum continuous mode messages per second for each device
+//     ?lease_token=TOKEN // Token of a device lease acquired through the REST API
 // Otherwise, if they are not defined, the WebSocket channel will receive all available messages.
 // All operations made through REST API and WebSocket routes will be broadcast to all clients subscribed to device-number=null (default),
 // except for errors, which are forwarded directly to the requester.
+// Each client has a bounded queue, the oldest messages are dropped when it can't keep up with the devices,
+// queued, sent, dropped and decimated messages of each client are available on {address}/websocket_manager/clients.
+// Clients can replace the filter by subscriptions, changed at any time and acknowledged by the server:
+//     {"Subscribe": {"devices": ["UUID"], "kinds": [{"DeviceMessage": {"device_type": "Ping360", "message": "AutoDeviceData"}}]}}
+//     {"Subscribe": {"kinds": ["DeviceStatus", "DeviceAnswer", "RecordingStatus", "Error"]}} // Empty lists select everything
+//     {"Unsubscribe": {"ids": [1]}} // Or {"Unsubscribe": {}} to remove all of them
+//     "Subscriptions" // List the client subscriptions
+// Once a client subscribes, it only receives the subscribed messages.
+//
+// Device events:
+// Devices discovered, created, deleted, status and properties changes, continuous mode start and stop and failed
+// requests are published as typed events on the {address}/ws/events websocket, or as Server-Sent Events on
+// {address}/device_manager/events, without polling the device list.
+//
+// Device leases:
+// A client can lease a device on {address}/device_manager/{device}/lease, the answer carries a token only returned
+// to it, sent as the X-Lease-Token header to change the device. Other clients only see the lease expiry.
+// Websocket connections use the tokens of the leases they acquired.
+//
+// Authentication:
+// Optional API tokens are configured with --api-token TOKEN:SCOPE or on the settings file api_tokens list.
+// Once a token is configured, device manager, recordings, exports, vehicle and websocket routes require one,
+// sent as an "Authorization: Bearer TOKEN" header or with the ?token=TOKEN query, used by websockets and downloads.
+// Read tokens can list and query devices, admin tokens are required for any change.
+//
+// TLS:
+// With --tls the server uses HTTPS and secure websockets (wss://) on the same address,
+// using the --tls-cert and --tls-key files or a self-signed certificate generated on first run.
@@ -0,0 +1,189 @@
+use actix_web::web::Bytes;
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+// Messages waiting for a client before the oldest ones are dropped
+pub static CLIENT_QUEUE_SIZE: usize = 64;
+
+/// Maximum device messages per second with the interval it allows between them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaxRate {
+    pub rate: f32,
+    pub min_interval: Duration,
+}
+
+impl MaxRate {
+    // None for rates that aren't positive or whose interval doesn't fit a Duration
+    pub fn new(rate: f32) -> Option<Self> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return None;
+        }
+        let min_interval = Duration::try_from_secs_f64(1.0 / rate as f64).ok()?;
+        Some(Self { rate, min_interval })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutgoingMessage {
+    Text(String),
+    Binary(Bytes),
+}
+
+/// Delivery statistics of a websocket client.
+#[derive(Debug, Clone, Serialize, Apiv2Schema, PartialEq)]
+pub struct WebsocketClientStats {
+    /// Connection id.
+    pub id: Uuid,
+    pub device_number: Option<Uuid>,
+    /// Maximum device messages per second, for each device.
+    pub max_rate: Option<f32>,
+    /// Messages waiting to be written to the socket.
+    pub queued: usize,
+    /// Messages written to the socket.
+    pub sent: u64,
+    /// Oldest messages dropped while the client was not reading fast enough.
+    pub dropped: u64,
+    /// Device messages skipped to respect max_rate.
+    pub decimated: u64,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    messages: VecDeque<OutgoingMessage>,
+    // Last device message accepted for each device, for max_rate
+    last_accepted: HashMap<Uuid, Instant>,
+    // A flush was requested and the client didn't take the messages yet
+    flush_pending: bool,
+    sent: u64,
+    dropped: u64,
+    decimated: u64,
+}
+
+// Bounded drop-oldest queue between the broadcasters and a websocket client, the client only
+// takes the messages while its socket is writable, so slow clients drop frames instead of
+// growing their mailbox
+#[derive(Debug)]
+pub struct ClientQueue {
+    pub id: Uuid,
+    capacity: usize,
+    max_rate: Option<MaxRate>,
+    state: Mutex<QueueState>,
+}
+
+impl ClientQueue {
+    pub fn new(capacity: usize, max_rate: Option<MaxRate>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            capacity: capacity.max(1),
+            max_rate,
+            state: Mutex::new(QueueState::default()),
+        }
+    }
+
+    // Device messages closer than the minimum interval to the previous one are decimated
+    pub fn accept_device_message(&self, device_id: Uuid, now: Instant) -> bool {
+        let Some(max_rate) = self.max_rate else {
+            return true;
+        };
+
+        let mut state = self.state.lock().unwrap();
+        match state.last_accepted.get(&device_id) {
+            Some(last) if now.saturating_duration_since(*last) < max_rate.min_interval => {
+                state.decimated += 1;
+                false
+            }
+            _ => {
+                state.last_accepted.insert(device_id, now);
+                true
+            }
+        }
+    }
+
+    // Queue the message, returns true when the client should be asked to flush
+    pub fn push(&self, message: OutgoingMessage) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.messages.len() >= self.capacity {
+            state.messages.pop_front();
+            state.dropped += 1;
+        }
+        state.messages.push_back(message);
+
+        !std::mem::replace(&mut state.flush_pending, true)
+    }
+
+    pub fn take(&self) -> VecDeque<OutgoingMessage> {
+        let mut state = self.state.lock().unwrap();
+        state.flush_pending = false;
+        state.sent += state.messages.len() as u64;
+        std::mem::take(&mut state.messages)
+    }
+
+    pub fn stats(&self, device_number: Option<Uuid>) -> WebsocketClientStats {
+        let state = self.state.lock().unwrap();
+        WebsocketClientStats {
+            id: self.id,
+            device_number,
+            max_rate: self.max_rate.map(|max_rate| max_rate.rate),
+            queued: state.messages.len(),
+            sent: state.sent,
+            dropped: state.dropped,
+            decimated: state.decimated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(index: usize) -> OutgoingMessage {
+        OutgoingMessage::Text(index.to_string())
+    }
+
+    #[test]
+    fn slow_clients_drop_oldest_messages() {
+        let queue = ClientQueue::new(3, None);
+        // Only the first message asks for a flush until the client takes them
+        assert!(queue.push(text(0)));
+        assert!((1..5).all(|index| !queue.push(text(index))));
+
+        let stats = queue.stats(None);
+        assert_eq!((stats.queued, stats.dropped, stats.sent), (3, 2, 0));
+        assert_eq!(queue.take(), VecDeque::from([text(2), text(3), text(4)]));
+        assert!(queue.push(text(5)));
+        queue.take();
+
+        let stats = queue.stats(None);
+        assert_eq!((stats.queued, stats.dropped, stats.sent), (0, 2, 4));
+    }
+
+    #[test]
+    fn device_messages_are_decimated_per_device() {
+        let queue = ClientQueue::new(CLIENT_QUEUE_SIZE, MaxRate::new(10.0));
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // 40 Hz devices are reduced to 10 Hz each
+        let accepted = (0..40)
+            .filter(|index| queue.accept_device_message(first, at(index * 25)))
+            .count();
+        assert_eq!(accepted, 10);
+        assert!(queue.accept_device_message(second, at(975)));
+
+        let stats = queue.stats(None);
+        assert_eq!((stats.decimated, stats.max_rate), (30, Some(10.0)));
+        assert!(ClientQueue::new(1, None).accept_device_message(first, at(0)));
+
+        // Rates without a representable interval are rejected before reaching the queue
+        assert!([0.0, -1.0, f32::NAN, f32::INFINITY, 1e-30]
+            .into_iter()
+            .all(|rate| MaxRate::new(rate).is_none()));
+    }
+}
@@ -7,6 +7,12 @@ use validator::ValidationErrors;
 #[api_v2_errors(
     code = 400,
     description = "Bad Request: The client's request contains invalid or malformed data.",
+    code = 401,
+    description = "Unauthorized: A valid API token is required.",
+    code = 403,
+    description = "Forbidden: The API token scope doesn't allow the request.",
+    code = 409,
+    description = "Conflict: The device is leased by another client.",
     code = 500,
     description = "Internal Server Error: An unexpected server error has occurred."
 )]
@@ -14,6 +20,12 @@ use validator::ValidationErrors;
 pub enum Error {
     #[error("Bad Request: {0}")]
     BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
     #[error("Internal Server Error: {0}")]
     Internal(String),
 }
@@ -22,6 +34,9 @@ impl ResponseError for Error {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Conflict(_) => StatusCode::CONFLICT,
             Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -35,6 +50,11 @@ impl From<ValidationErrors> for Error {
 
 impl From<crate::device::manager::ManagerError> for Error {
     fn from(error: crate::device::manager::ManagerError) -> Self {
-        Self::Internal(serde_json::to_string_pretty(&error).unwrap_or_default())
+        let details = serde_json::to_string_pretty(&error).unwrap_or_default();
+        match error {
+            crate::device::manager::ManagerError::InvalidConfig(_) => Self::BadRequest(details),
+            crate::device::manager::ManagerError::DeviceLeased(_) => Self::Conflict(details),
+            _ => Self::Internal(details),
+        }
     }
 }
@@ -1,3 +1,5 @@
+pub mod client_queue;
 pub mod errors;
 pub mod rest;
+pub mod subscription;
 pub mod websocket;
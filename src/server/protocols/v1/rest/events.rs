@@ -0,0 +1,98 @@
+use crate::device::manager::{device_events::DeviceEvent, ManagerActorHandler};
+use actix_web::{
+    body::{BodySize, MessageBody},
+    web::Bytes,
+    Responder,
+};
+use paperclip::actix::{
+    api_v2_operation, get,
+    web::{self, HttpResponse},
+};
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+
+// Comment sent while there are no events, so proxies keep the connection and closed clients are noticed
+static KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// Server-Sent Events chunks written by the forwarding task
+struct EventStream {
+    chunks: mpsc::Receiver<Bytes>,
+}
+
+impl MessageBody for EventStream {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        self.get_mut()
+            .chunks
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(Ok))
+    }
+}
+
+fn event_chunk(event: &DeviceEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Bytes::from(format!("event: {}\ndata: {data}\n\n", event.name()))
+}
+
+async fn forward_events(mut events: broadcast::Receiver<DeviceEvent>, chunks: mpsc::Sender<Bytes>) {
+    loop {
+        let chunk = match tokio::time::timeout(KEEP_ALIVE, events.recv()).await {
+            Ok(Ok(event)) => event_chunk(&event),
+            Ok(Err(broadcast::error::RecvError::Lagged(skipped))) => {
+                warn!("ServerManager: {skipped} device events skipped for a slow client");
+                Bytes::from(format!(": {skipped} events skipped\n\n"))
+            }
+            Ok(Err(broadcast::error::RecvError::Closed)) => break,
+            Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+        };
+        // The client disconnected
+        if chunks.send(chunk).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Device lifecycle events as Server-Sent Events, named after the event with its JSON as data
+#[api_v2_operation(tags("Device Manager"))]
+#[get("device_manager/events")]
+async fn device_manager_events(manager_handler: web::Data<ManagerActorHandler>) -> impl Responder {
+    let (sender, chunks) = mpsc::channel(16);
+    tokio::spawn(forward_events(manager_handler.subscribe_events(), sender));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .append_header(("Cache-Control", "no-cache"))
+        .body(EventStream { chunks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn events_are_formatted_as_server_sent_events() {
+        let device_id = Uuid::nil();
+        let chunk = event_chunk(&DeviceEvent::ContinuousModeStarted { device_id });
+        assert_eq!(
+            chunk,
+            format!(
+                "event: ContinuousModeStarted\ndata: {{\"ContinuousModeStarted\":{{\"device_id\":\"{device_id}\"}}}}\n\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::device::export::jobs::{
+    delete_export, export_job, export_jobs, export_output, export_world_file, start_export,
+    ExportJob, ExportRequest,
+};
+use crate::server::protocols::v1::errors::Error;
+use actix_web::body::SizedStream;
+use paperclip::actix::{
+    api_v2_operation, delete, get, post,
+    web::{self, HttpResponse, Json},
+};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+/// Start exporting a Ping360 recording as georeferenced points
+#[api_v2_operation(tags("Exports"))]
+#[post("exports")]
+async fn post_export(json: web::Json<ExportRequest>) -> Result<Json<ExportJob>, Error> {
+    Ok(Json(start_export(json.into_inner())?))
+}
+
+#[api_v2_operation(tags("Exports"))]
+#[get("exports")]
+async fn get_exports() -> Result<Json<Vec<ExportJob>>, Error> {
+    Ok(Json(export_jobs()))
+}
+
+#[api_v2_operation(tags("Exports"))]
+#[get("exports/{job}")]
+async fn get_export(job: web::Path<Uuid>) -> Result<Json<ExportJob>, Error> {
+    let job = job.into_inner();
+    export_job(job)
+        .map(Json)
+        .ok_or_else(|| Error::BadRequest(format!("Export job not found: {job}")))
+}
+
+/// Remove a finished or failed export job and its files
+#[api_v2_operation(tags("Exports"))]
+#[delete("exports/{job}")]
+async fn delete_export_job(job: web::Path<Uuid>) -> Result<Json<ExportJob>, Error> {
+    Ok(Json(delete_export(job.into_inner())?))
+}
+
+/// Download the exported points or mosaic once the job has finished
+#[api_v2_operation(tags("Exports"))]
+#[get("exports/{job}/download")]
+async fn download_export(job: web::Path<Uuid>) -> HttpResponse {
+    match export_output(job.into_inner()) {
+        Ok(path) => download_file(&path).await,
+        Err(err) => HttpResponse::NotFound().body(format!("{err:?}")),
+    }
+}
+
+/// Download the world file that georeferences a PNG mosaic
+#[api_v2_operation(tags("Exports"))]
+#[get("exports/{job}/world_file")]
+async fn download_export_world_file(job: web::Path<Uuid>) -> HttpResponse {
+    match export_world_file(job.into_inner()) {
+        Ok(path) => download_file(&path).await,
+        Err(err) => HttpResponse::NotFound().body(format!("{err:?}")),
+    }
+}
+
+// Stream the file, exports of long recordings don't fit in memory
+async fn download_file(path: &std::path::Path) -> HttpResponse {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let file = async {
+        let file = tokio::fs::File::open(path).await?;
+        let size = file.metadata().await?.len();
+        Ok::<_, std::io::Error>((file, size))
+    };
+
+    match file.await {
+        Ok((file, size)) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .append_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{file_name}\""),
+            ))
+            .body(SizedStream::new(size, ReaderStream::new(file))),
+        Err(err) => HttpResponse::InternalServerError()
+            .body(format!("Failed to read export {path:?}: {err}")),
+    }
+}
@@ -1,4 +1,11 @@
-use crate::device::manager::{ManagerActorHandler, Request, UuidWrapper};
+use crate::device::manager::{
+    device_lease::{LeaseClient, LeaseRequestStruct},
+    ManagerActorHandler, MavlinkOutputRequestStruct, NmeaOutputRequestStruct,
+    PlaybackRequestStruct, Request, SonarImageRequestStruct, UuidWrapper,
+};
+use crate::device::output::{mavlink::MavlinkOutputConfig, nmea::NmeaOutputConfig};
+use crate::device::playback::PlaybackCommand;
+use crate::device::sonar_image::SonarImageConfig;
 use crate::server::protocols::v1::errors::Error;
 use actix_web::{HttpRequest, Responder};
 use mime_guess::from_path;
@@ -11,7 +18,11 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
 
+pub mod events;
+pub mod export;
 pub mod recording;
+pub mod vehicle;
+pub mod websocket;
 
 #[cfg(not(feature = "embed-frontend"))]
 #[derive(rust_embed::RustEmbed)]
@@ -62,7 +73,19 @@ async fn server_metadata() -> Result<Json<ServerMetadata>, Error> {
 pub fn register_services(cfg: &mut web::ServiceConfig) {
     cfg.service(index)
         .service(post_request)
+        // Before device_manager/{selection}, which would take the events path
+        .service(events::device_manager_events)
         .service(device_manager_get)
+        .service(device_manager_playback_request)
+        .service(device_manager_sonar_image_png)
+        .service(device_manager_sonar_image_request)
+        .service(device_manager_nmea_post)
+        .service(device_manager_nmea_request)
+        .service(device_manager_mavlink_post)
+        .service(device_manager_mavlink_request)
+        .service(device_manager_lease_get)
+        .service(device_manager_lease_release)
+        .service(device_manager_lease_request)
         .service(device_manager_post)
         .service(recording::recording_manager_get)
         .service(recording::recording_manager_post)
@@ -77,13 +100,36 @@ pub fn register_services(cfg: &mut web::ServiceConfig) {
         .service(recording::list_mcap_recordings)
         .service(recording::download_mcap_file)
         .service(recording::delete_mcap_file)
+        .service(vehicle::vehicle_status)
+        .service(websocket::websocket_clients)
+        .service(export::post_export)
+        .service(export::get_exports)
+        .service(export::download_export)
+        .service(export::download_export_world_file)
+        .service(export::get_export)
+        .service(export::delete_export_job)
         .service(index_files);
 }
 
+// Lease token of the device changed by the request, from the X-Lease-Token header
+pub fn lease_client(req: &HttpRequest) -> LeaseClient {
+    LeaseClient {
+        token: req
+            .headers()
+            .get("X-Lease-Token")
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string),
+    }
+}
+
 async fn send_request_and_broadcast(
     manager_handler: &web::Data<ManagerActorHandler>,
+    req: &HttpRequest,
     request: Request,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
+    crate::server::auth::authorize_request(req, &request)?;
+
     let request_has_id = match &request {
         Request::ModifyDevice(modify) => Some(modify.uuid),
         Request::Ping(device_request) => Some(device_request.uuid),
@@ -91,23 +137,38 @@ async fn send_request_and_broadcast(
         Request::Info(uuid_wrapper) => Some(uuid_wrapper.uuid),
         Request::EnableContinuousMode(uuid_wrapper) => Some(uuid_wrapper.uuid),
         Request::DisableContinuousMode(uuid_wrapper) => Some(uuid_wrapper.uuid),
+        Request::Playback(playback_request) => Some(playback_request.uuid),
+        Request::SetNmeaOutput(nmea_request) => Some(nmea_request.uuid),
+        Request::GetNmeaOutput(uuid_wrapper) => Some(uuid_wrapper.uuid),
+        Request::SetMavlinkOutput(mavlink_request) => Some(mavlink_request.uuid),
+        Request::GetMavlinkOutput(uuid_wrapper) => Some(uuid_wrapper.uuid),
+        Request::SetSonarImage(sonar_image_request) => Some(sonar_image_request.uuid),
+        Request::AcquireLease(lease_request) => Some(lease_request.uuid),
+        Request::ReleaseLease(uuid_wrapper) => Some(uuid_wrapper.uuid),
+        Request::GetLease(uuid_wrapper) => Some(uuid_wrapper.uuid),
         _ => None,
     };
 
-    let answer = manager_handler.send(request).await?;
-    crate::server::protocols::v1::websocket::send_to_websockets(json!(answer), request_has_id);
+    let answer = manager_handler
+        .send_as(request, Some(lease_client(req)))
+        .await?;
+    crate::server::protocols::v1::websocket::send_answer_to_websockets(
+        &answer.shared(),
+        request_has_id,
+    );
     Ok(Json(answer))
 }
 
 #[api_v2_operation(tags("Device Manager"))]
 #[post("device_manager/request")]
 async fn post_request(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     json: web::Json<crate::device::manager::Request>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
     let request = json.into_inner();
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
@@ -128,6 +189,7 @@ pub enum DeviceManagerPostOptionsV1 {
 #[api_v2_operation(tags("Device Manager"))]
 #[get("device_manager/{selection}")]
 async fn device_manager_get(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     selection: web::Path<DeviceManagerGetOptionsV1>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
@@ -137,12 +199,13 @@ async fn device_manager_get(
         DeviceManagerGetOptionsV1::Search => crate::device::manager::Request::Search,
     };
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[api_v2_operation(tags("Device Manager"))]
 #[post("device_manager/create")]
 async fn post_create(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     info: web::Json<crate::device::manager::CreateStruct>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
@@ -150,12 +213,13 @@ async fn post_create(
 
     let request = crate::device::manager::Request::Create(create_struct);
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[api_v2_operation(tags("Device Manager : Device"))]
 #[post("device_manager/{device}/{selection}")]
 async fn device_manager_post(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     info: web::Path<(Uuid, DeviceManagerPostOptionsV1)>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
@@ -178,12 +242,210 @@ async fn device_manager_post(
         }
     };
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+/// Playback controls: play, pause, seek, speed changes and status
+#[api_v2_operation(tags("Device Manager : Playback"))]
+#[post("device_manager/{device}/playback")]
+async fn device_manager_playback_request(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+    json: web::Json<PlaybackCommand>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let request = Request::Playback(PlaybackRequestStruct {
+        uuid: device.into_inner(),
+        command: json.into_inner(),
+    });
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+/// Configure the sonar image of a Ping360 device, the image restarts empty
+#[api_v2_operation(tags("Device Manager : Sonar Image"))]
+#[post("device_manager/{device}/sonar_image")]
+async fn device_manager_sonar_image_request(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+    json: web::Json<SonarImageConfig>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let request = Request::SetSonarImage(SonarImageRequestStruct {
+        uuid: device.into_inner(),
+        config: json.into_inner(),
+    });
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+/// Latest Ping360 scan as a PNG, the sonar is in the center and the head angle 0 points up
+#[api_v2_operation(tags("Device Manager : Sonar Image"))]
+#[get("device_manager/{device}/sonar_image.png")]
+async fn device_manager_sonar_image_png(
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+    let image = manager_handler
+        .get_sonar_image(device.into_inner())
+        .await
+        .map_err(|err| Error::BadRequest(json!(err).to_string()))?;
+    let info = image.info();
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .append_header(("X-Sonar-Range-M", info.range_m.to_string()))
+        .append_header(("X-Sonar-Revision", info.revision.to_string()))
+        .append_header(("Cache-Control", "no-cache, no-store, must-revalidate"))
+        .body(image.encode_png()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub enum NmeaPostOptionsV1 {
+    Disable,
+    GetStatus,
+}
+
+#[api_v2_operation(tags("Device Manager : NMEA Output"))]
+#[post("device_manager/{device}/nmea/{selection}")]
+async fn device_manager_nmea_post(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    info: web::Path<(Uuid, NmeaPostOptionsV1)>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let (uuid, selection) = info.into_inner();
+
+    let request = match selection {
+        NmeaPostOptionsV1::Disable => {
+            Request::SetNmeaOutput(NmeaOutputRequestStruct { uuid, config: None })
+        }
+        NmeaPostOptionsV1::GetStatus => Request::GetNmeaOutput(UuidWrapper { uuid }),
+    };
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+/// Enable or replace the NMEA depth output of a Ping1D device
+#[api_v2_operation(tags("Device Manager : NMEA Output"))]
+#[post("device_manager/{device}/nmea")]
+async fn device_manager_nmea_request(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+    json: web::Json<NmeaOutputConfig>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let request = Request::SetNmeaOutput(NmeaOutputRequestStruct {
+        uuid: device.into_inner(),
+        config: Some(json.into_inner()),
+    });
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema)]
+pub enum MavlinkPostOptionsV1 {
+    Disable,
+    GetStatus,
+}
+
+#[api_v2_operation(tags("Device Manager : MAVLink Output"))]
+#[post("device_manager/{device}/mavlink/{selection}")]
+async fn device_manager_mavlink_post(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    info: web::Path<(Uuid, MavlinkPostOptionsV1)>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let (uuid, selection) = info.into_inner();
+
+    let request = match selection {
+        MavlinkPostOptionsV1::Disable => {
+            Request::SetMavlinkOutput(MavlinkOutputRequestStruct { uuid, config: None })
+        }
+        MavlinkPostOptionsV1::GetStatus => Request::GetMavlinkOutput(UuidWrapper { uuid }),
+    };
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+/// Enable or replace the MAVLink DISTANCE_SENSOR output of a Ping1D device
+#[api_v2_operation(tags("Device Manager : MAVLink Output"))]
+#[post("device_manager/{device}/mavlink")]
+async fn device_manager_mavlink_request(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+    json: web::Json<MavlinkOutputConfig>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let request = Request::SetMavlinkOutput(MavlinkOutputRequestStruct {
+        uuid: device.into_inner(),
+        config: Some(json.into_inner()),
+    });
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct LeaseOptionsV1 {
+    /// Lease duration in seconds, defaults to 30 and is limited to one hour.
+    #[serde(default)]
+    pub duration_s: Option<u64>,
+    /// Take over the lease of another client.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Acquire or renew the control lease of a device, other clients can't change it until the lease expires.
+/// The returned token is required as the X-Lease-Token header to change the device and renew or release the lease
+#[api_v2_operation(tags("Device Manager : Lease"))]
+#[post("device_manager/{device}/lease")]
+async fn device_manager_lease_request(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+    json: web::Json<LeaseOptionsV1>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let options = json.into_inner();
+    let request = Request::AcquireLease(LeaseRequestStruct {
+        uuid: device.into_inner(),
+        duration_s: options.duration_s,
+        force: options.force,
+    });
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+#[api_v2_operation(tags("Device Manager : Lease"))]
+#[post("device_manager/{device}/lease/release")]
+async fn device_manager_lease_release(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let request = Request::ReleaseLease(UuidWrapper {
+        uuid: device.into_inner(),
+    });
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
+}
+
+#[api_v2_operation(tags("Device Manager : Lease"))]
+#[get("device_manager/{device}/lease")]
+async fn device_manager_lease_get(
+    req: HttpRequest,
+    manager_handler: web::Data<ManagerActorHandler>,
+    device: web::Path<Uuid>,
+) -> Result<Json<crate::device::manager::Answer>, Error> {
+    let request = Request::GetLease(UuidWrapper {
+        uuid: device.into_inner(),
+    });
+
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[api_v2_operation(tags("Device Manager : Device"))]
 #[get("device_manager/{device}/{request}")]
 async fn device_manager_device_get(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     info: web::Path<(Uuid, crate::device::devices::PingRequest)>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
@@ -197,12 +459,13 @@ async fn device_manager_device_get(
             device_request: request,
         });
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[api_v2_operation(tags("Device Manager : Device"))]
 #[get("device_manager/{device}/ping1d/{request}")]
 async fn device_manager_device_ping1d_get(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     info: web::Path<(Uuid, crate::device::devices::Ping1DRequest)>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
@@ -218,12 +481,13 @@ async fn device_manager_device_ping1d_get(
             device_request: request,
         });
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[api_v2_operation(tags("Device Manager : Device"))]
 #[get("device_manager/{device}/ping360/{request}")]
 async fn device_manager_device_ping360_get(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     info: web::Path<(Uuid, crate::device::devices::Ping360Request)>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
@@ -239,12 +503,13 @@ async fn device_manager_device_ping360_get(
             device_request: request,
         });
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[api_v2_operation(tags("Device Manager : Device"))]
 #[get("device_manager/{device}/common/{request}")]
 async fn device_manager_device_common_get(
+    req: HttpRequest,
     manager_handler: web::Data<ManagerActorHandler>,
     info: web::Path<(Uuid, crate::device::devices::PingCommonRequest)>,
 ) -> Result<Json<crate::device::manager::Answer>, Error> {
@@ -260,7 +525,7 @@ async fn device_manager_device_common_get(
             device_request: request,
         });
 
-    send_request_and_broadcast(&manager_handler, request).await
+    send_request_and_broadcast(&manager_handler, &req, request).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
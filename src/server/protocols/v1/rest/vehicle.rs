@@ -0,0 +1,10 @@
+use crate::server::protocols::v1::errors::Error;
+use crate::vehicle::status::{bridge_status, VehicleBridgeStatus};
+use paperclip::actix::{api_v2_operation, get, web::Json};
+
+/// Vehicle bridge connection state and age of the last received telemetry
+#[api_v2_operation(tags("Vehicle"))]
+#[get("vehicle/status")]
+async fn vehicle_status() -> Result<Json<VehicleBridgeStatus>, Error> {
+    Ok(Json(bridge_status()))
+}
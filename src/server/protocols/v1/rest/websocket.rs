@@ -0,0 +1,14 @@
+use crate::server::protocols::v1::{
+    client_queue::WebsocketClientStats, errors::Error, websocket::MANAGER,
+};
+use paperclip::actix::{api_v2_operation, get, web::Json};
+
+/// Connected websocket clients with their queued, sent, dropped and decimated messages
+#[api_v2_operation(tags("Websocket Manager"))]
+#[get("websocket_manager/clients")]
+async fn websocket_clients() -> Result<Json<Vec<WebsocketClientStats>>, Error> {
+    let manager = MANAGER
+        .lock()
+        .map_err(|err| Error::Internal(err.to_string()))?;
+    Ok(Json(manager.stats()))
+}
@@ -0,0 +1,353 @@
+use bluerobotics_ping::message::PingMessage;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::device::{
+    devices::PingAnswer,
+    manager::{Answer, DeviceAnswer},
+};
+
+// Device types of the Ping protocol messages, as named in their JSON
+static DEVICE_TYPES: [&str; 5] = ["Ping360", "Ping1D", "Common", "Bluebps", "Omniscan450"];
+
+/// Messages a websocket client can subscribe to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MessageKind {
+    /// Ping protocol messages, optionally only of a device type like Ping360 and a message like AutoDeviceData.
+    DeviceMessage {
+        #[serde(default)]
+        device_type: Option<String>,
+        #[serde(default)]
+        message: Option<String>,
+    },
+    /// Device information with its status, sent when devices are listed, created or changed.
+    DeviceStatus,
+    /// Other device manager answers: configurations, leases, outputs and playback.
+    DeviceAnswer,
+    /// Recording sessions started or stopped.
+    RecordingStatus,
+    Error,
+}
+
+impl MessageKind {
+    fn matches(&self, topic: &Topic) -> bool {
+        match (self, topic) {
+            (
+                Self::DeviceMessage {
+                    device_type,
+                    message,
+                },
+                Topic::DeviceMessage {
+                    device_type: topic_device_type,
+                    message: topic_message,
+                },
+            ) => {
+                device_type
+                    .as_ref()
+                    .is_none_or(|device_type| device_type == topic_device_type)
+                    && message
+                        .as_ref()
+                        .is_none_or(|message| same_message_name(message, topic_message))
+            }
+            (Self::DeviceStatus, Topic::DeviceStatus)
+            | (Self::DeviceAnswer, Topic::DeviceAnswer)
+            | (Self::RecordingStatus, Topic::RecordingStatus)
+            | (Self::Error, Topic::Error) => true,
+            _ => false,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::DeviceMessage {
+                device_type: Some(device_type),
+                ..
+            } if !DEVICE_TYPES.contains(&device_type.as_str()) => Err(format!(
+                "Unknown device type {device_type:?}, expected one of {DEVICE_TYPES:?}"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The JSON names the messages in pascal case (AutoDeviceData), the protocol in snake case (auto_device_data)
+fn same_message_name(name: &str, other: &str) -> bool {
+    fn normalize(name: &str) -> impl Iterator<Item = char> + '_ {
+        name.chars()
+            .filter(|character| *character != '_')
+            .map(|character| character.to_ascii_lowercase())
+    }
+    normalize(name).eq(normalize(other))
+}
+
+/// What a message sent to the websocket clients is about, matched against their subscriptions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Topic {
+    DeviceMessage {
+        device_type: &'static str,
+        message: &'static str,
+    },
+    DeviceStatus,
+    DeviceAnswer,
+    RecordingStatus,
+    Error,
+}
+
+impl Topic {
+    pub fn of(answer: &Answer) -> Self {
+        match answer {
+            Answer::DeviceMessage(DeviceAnswer {
+                answer: PingAnswer::PingMessage(message),
+                ..
+            }) => {
+                let (device_type, message) = match message {
+                    bluerobotics_ping::Messages::Ping360(message) => {
+                        ("Ping360", message.message_name())
+                    }
+                    bluerobotics_ping::Messages::Ping1D(message) => {
+                        ("Ping1D", message.message_name())
+                    }
+                    bluerobotics_ping::Messages::Common(message) => {
+                        ("Common", message.message_name())
+                    }
+                    bluerobotics_ping::Messages::Bluebps(message) => {
+                        ("Bluebps", message.message_name())
+                    }
+                    bluerobotics_ping::Messages::Omniscan450(message) => {
+                        ("Omniscan450", message.message_name())
+                    }
+                };
+                Self::DeviceMessage {
+                    device_type,
+                    message,
+                }
+            }
+            Answer::DeviceInfo(_) => Self::DeviceStatus,
+            _ => Self::DeviceAnswer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubscriptionRequest {
+    /// Devices to receive messages from, all of them when empty.
+    #[serde(default)]
+    pub devices: Vec<Uuid>,
+    /// Messages to receive, all of them when empty.
+    #[serde(default)]
+    pub kinds: Vec<MessageKind>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: u32,
+    pub devices: Vec<Uuid>,
+    pub kinds: Vec<MessageKind>,
+}
+
+impl Subscription {
+    // Messages without a device only match subscriptions to all the devices
+    fn matches(&self, topic: &Topic, device: Option<Uuid>) -> bool {
+        (self.devices.is_empty() || device.is_some_and(|device| self.devices.contains(&device)))
+            && (self.kinds.is_empty() || self.kinds.iter().any(|kind| kind.matches(topic)))
+    }
+}
+
+/// Commands sent by a websocket client to change its subscriptions, each one is acknowledged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SubscriptionCommand {
+    Subscribe(SubscriptionRequest),
+    /// Remove the subscriptions with the ids, or all of them when no ids are given.
+    Unsubscribe {
+        #[serde(default)]
+        ids: Option<Vec<u32>>,
+    },
+    Subscriptions,
+}
+
+impl SubscriptionCommand {
+    // Malformed commands are reported as such instead of as invalid device manager requests
+    pub fn is_named(text: &str) -> bool {
+        static NAMES: [&str; 3] = ["Subscribe", "Unsubscribe", "Subscriptions"];
+        match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(serde_json::Value::String(name)) => NAMES.contains(&name.as_str()),
+            Ok(serde_json::Value::Object(object)) => {
+                object.keys().any(|key| NAMES.contains(&key.as_str()))
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum SubscriptionAck {
+    Subscribed(Subscription),
+    Unsubscribed { ids: Vec<u32> },
+    Subscriptions(Vec<Subscription>),
+}
+
+// Subscriptions of a websocket client, clients that never subscribed keep receiving
+// everything selected by their connection filter
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    next_id: u32,
+    active: Option<Vec<Subscription>>,
+}
+
+impl Subscriptions {
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    // None while the client uses its connection filter
+    pub fn matches(&self, topic: &Topic, device: Option<Uuid>) -> Option<bool> {
+        self.active.as_ref().map(|subscriptions| {
+            subscriptions
+                .iter()
+                .any(|subscription| subscription.matches(topic, device))
+        })
+    }
+
+    pub fn handle(&mut self, command: SubscriptionCommand) -> Result<SubscriptionAck, String> {
+        match command {
+            SubscriptionCommand::Subscribe(request) => {
+                request.kinds.iter().try_for_each(MessageKind::validate)?;
+                self.next_id += 1;
+                let subscription = Subscription {
+                    id: self.next_id,
+                    devices: request.devices,
+                    kinds: request.kinds,
+                };
+                self.active
+                    .get_or_insert_with(Vec::new)
+                    .push(subscription.clone());
+                Ok(SubscriptionAck::Subscribed(subscription))
+            }
+            SubscriptionCommand::Unsubscribe { ids } => {
+                let subscriptions = self.active.get_or_insert_with(Vec::new);
+                if let Some(unknown) = ids.iter().flatten().find(|id| {
+                    !subscriptions
+                        .iter()
+                        .any(|subscription| subscription.id == **id)
+                }) {
+                    return Err(format!("Unknown subscription {unknown}"));
+                }
+
+                let mut removed = Vec::new();
+                subscriptions.retain(|subscription| {
+                    let remove = ids
+                        .as_ref()
+                        .is_none_or(|ids| ids.contains(&subscription.id));
+                    if remove {
+                        removed.push(subscription.id);
+                    }
+                    !remove
+                });
+                Ok(SubscriptionAck::Unsubscribed { ids: removed })
+            }
+            SubscriptionCommand::Subscriptions => Ok(SubscriptionAck::Subscriptions(
+                self.active.clone().unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscribe(value: serde_json::Value) -> SubscriptionCommand {
+        serde_json::from_value(serde_json::json!({ "Subscribe": value })).unwrap()
+    }
+
+    #[test]
+    fn subscriptions_select_devices_and_kinds() {
+        let (sonar, altimeter) = (Uuid::new_v4(), Uuid::new_v4());
+        let auto_device_data = Topic::DeviceMessage {
+            device_type: "Ping360",
+            message: "auto_device_data",
+        };
+        let distance = Topic::DeviceMessage {
+            device_type: "Ping1D",
+            message: "distance_simple",
+        };
+
+        let mut subscriptions = Subscriptions::default();
+        assert_eq!(subscriptions.matches(&Topic::Error, None), None);
+        assert!(subscriptions
+            .handle(subscribe(serde_json::json!({
+                "kinds": [{ "DeviceMessage": { "device_type": "Ping720" } }]
+            })))
+            .is_err());
+        assert!(!subscriptions.is_active());
+        assert!(SubscriptionCommand::is_named(
+            r#"{"Subscribe":{"devices":[""]}}"#
+        ));
+        assert!(!SubscriptionCommand::is_named(
+            r#"{"module":"DeviceManager"}"#
+        ));
+
+        let Ok(SubscriptionAck::Subscribed(scans)) = subscriptions.handle(subscribe(
+            serde_json::json!({
+                "devices": [sonar],
+                "kinds": [{ "DeviceMessage": { "device_type": "Ping360", "message": "AutoDeviceData" } }]
+            }),
+        )) else {
+            panic!("Failed to subscribe to the Ping360 scans");
+        };
+        assert_eq!(
+            subscriptions.matches(&auto_device_data, Some(sonar)),
+            Some(true)
+        );
+        assert_eq!(
+            subscriptions.matches(&auto_device_data, Some(altimeter)),
+            Some(false)
+        );
+        assert_eq!(subscriptions.matches(&distance, Some(sonar)), Some(false));
+
+        subscriptions
+            .handle(subscribe(serde_json::json!({
+                "kinds": ["DeviceStatus", "RecordingStatus"]
+            })))
+            .unwrap();
+        assert_eq!(
+            subscriptions.matches(&Topic::DeviceStatus, None),
+            Some(true)
+        );
+        assert_eq!(
+            subscriptions.matches(&Topic::Error, Some(sonar)),
+            Some(false)
+        );
+
+        assert_eq!(
+            subscriptions.handle(SubscriptionCommand::Unsubscribe {
+                ids: Some(vec![scans.id])
+            }),
+            Ok(SubscriptionAck::Unsubscribed {
+                ids: vec![scans.id]
+            })
+        );
+        assert_eq!(
+            subscriptions.matches(&auto_device_data, Some(sonar)),
+            Some(false)
+        );
+        assert!(subscriptions
+            .handle(SubscriptionCommand::Unsubscribe {
+                ids: Some(vec![scans.id])
+            })
+            .is_err());
+
+        // Without subscriptions nothing is sent anymore
+        subscriptions
+            .handle(serde_json::from_str(r#"{"Unsubscribe":{}}"#).unwrap())
+            .unwrap();
+        assert_eq!(
+            subscriptions.matches(&Topic::RecordingStatus, None),
+            Some(false)
+        );
+        assert_eq!(
+            subscriptions.handle(serde_json::from_str(r#""Subscriptions""#).unwrap()),
+            Ok(SubscriptionAck::Subscriptions(Vec::new()))
+        );
+    }
+}
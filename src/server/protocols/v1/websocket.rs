@@ -2,8 +2,9 @@ use actix::{
     dev::ContextFutureSpawner, fut, Actor, ActorFutureExt, Addr, AsyncContext, Handler, Message,
     StreamHandler, WrapFuture,
 };
-use actix_web::HttpRequest;
+use actix_web::{web::Bytes, HttpRequest};
 use actix_web_actors::ws;
+use bluerobotics_ping::message::ProtocolMessage;
 use lazy_static::lazy_static;
 use paperclip::actix::{
     api_v2_operation, get,
@@ -13,14 +14,31 @@ use paperclip::actix::{
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::sync::{Arc, Mutex};
-use tokio::sync::broadcast;
-use tracing::info;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::broadcast, task::JoinHandle};
+use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::device::{
-    manager::{ManagerActorHandler, Request},
-    recording::{RecordingManagerCommand, RecordingsManagerHandler},
+use super::{
+    client_queue::{
+        ClientQueue, MaxRate, OutgoingMessage, WebsocketClientStats, CLIENT_QUEUE_SIZE,
+    },
+    subscription::{SubscriptionCommand, Subscriptions, Topic},
+};
+use crate::{
+    device::{
+        devices::PingAnswer,
+        manager::{
+            device_events::DeviceEvent, device_lease::LeaseClient, Answer, DeviceAnswer,
+            ManagerActorHandler, Request,
+        },
+        recording::{RecordingManagerCommand, RecordingsManagerHandler},
+    },
+    server::auth::{self, TokenScope},
 };
 
 pub struct StringMessage(String);
@@ -29,6 +47,24 @@ impl Message for StringMessage {
     type Result = ();
 }
 
+// Ask a websocket client to write its queued messages
+pub struct FlushQueue;
+
+impl Message for FlushQueue {
+    type Result = ();
+}
+
+/// Encoding of the device messages sent to a websocket client.
+/// Binary frames carry the 16 bytes of the device id followed by the Ping protocol message,
+/// other answers and errors are still sent as JSON text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Apiv2Schema)]
+#[serde(rename_all = "lowercase")]
+pub enum WebsocketFormat {
+    #[default]
+    Json,
+    Binary,
+}
+
 #[derive(Serialize, Debug)]
 pub struct WebsocketError {
     pub error: String,
@@ -39,6 +75,33 @@ pub struct WebsocketActorContent {
     pub actor: Addr<WebsocketActor>,
     pub re: Option<Regex>,
     pub device_number: Option<Uuid>,
+    pub format: WebsocketFormat,
+    pub queue: Arc<ClientQueue>,
+    pub subscriptions: Subscriptions,
+}
+
+impl WebsocketActorContent {
+    fn deliver(&self, message: OutgoingMessage) {
+        if self.queue.push(message) {
+            self.actor.do_send(FlushQueue);
+        }
+    }
+
+    // Subscribed clients only receive their subscriptions, the others receive the messages of their
+    // device, or of all of them, except for the recording status that was never part of this channel
+    fn wants(&self, topic: &Topic, device_number: Option<Uuid>) -> bool {
+        self.subscriptions
+            .matches(topic, device_number)
+            .unwrap_or_else(|| {
+                *topic != Topic::RecordingStatus
+                    && (self.device_number.is_none() || self.device_number == device_number)
+            })
+    }
+
+    // The regex filter is only applied until the client subscribes
+    fn passes_filter(&self, text: &str) -> bool {
+        self.subscriptions.is_active() || self.re.as_ref().is_some_and(|regx| regx.is_match(text))
+    }
 }
 
 #[derive(Debug, Default)]
@@ -47,22 +110,101 @@ pub struct WebsocketManager {
 }
 
 impl WebsocketManager {
-    pub fn send(&self, value: &serde_json::Value, name: &str, device_number: Option<Uuid>) {
+    pub fn send(
+        &self,
+        value: &serde_json::Value,
+        name: &str,
+        topic: Topic,
+        device_number: Option<Uuid>,
+    ) {
         if self.clients.is_empty() {
             return;
         }
 
         let string = serde_json::to_string(value).unwrap();
-        for client in &self.clients {
-            // check client list was subscribed or subscribed to all
-            if client.device_number.is_none() || client.device_number == device_number {
-                let is_match = client.re.as_ref().is_some_and(|regx| regx.is_match(name));
-                if is_match {
-                    client.actor.do_send(StringMessage(string.clone()));
+        for client in self.subscribed(&topic, device_number) {
+            if client.passes_filter(name) {
+                client.deliver(OutgoingMessage::Text(string.clone()));
+            }
+        }
+    }
+
+    // Device messages go as binary frames to binary clients, ignoring their filter,
+    // the JSON text is only serialized when a JSON client is subscribed.
+    // Streamed device messages are decimated to the max_rate of each client.
+    pub fn send_answer(&self, answer: &Answer, device_number: Option<Uuid>, streamed: bool) {
+        let now = Instant::now();
+        let topic = Topic::of(answer);
+        let mut text = None;
+        let mut frame = None;
+        for client in self.subscribed(&topic, device_number) {
+            if let (true, Some(device_id)) = (streamed, device_number) {
+                if !client.queue.accept_device_message(device_id, now) {
+                    continue;
+                }
+            }
+
+            if client.format == WebsocketFormat::Binary {
+                if let Some(frame) = frame.get_or_insert_with(|| binary_frame(answer)) {
+                    client.deliver(OutgoingMessage::Binary(frame.clone()));
+                    continue;
                 }
             }
+
+            let text = text.get_or_insert_with(|| serde_json::to_string(answer).unwrap());
+            if client.passes_filter(text) {
+                client.deliver(OutgoingMessage::Text(text.clone()));
+            }
         }
     }
+
+    pub fn stats(&self) -> Vec<WebsocketClientStats> {
+        self.clients
+            .iter()
+            .map(|client| client.queue.stats(client.device_number))
+            .collect()
+    }
+
+    // Clients subscribed to the topic from the device
+    fn subscribed<'a>(
+        &'a self,
+        topic: &'a Topic,
+        device_number: Option<Uuid>,
+    ) -> impl Iterator<Item = &'a WebsocketActorContent> {
+        self.clients
+            .iter()
+            .filter(move |client| client.wants(topic, device_number))
+    }
+
+    fn client_mut(&mut self, actor: &Addr<WebsocketActor>) -> Option<&mut WebsocketActorContent> {
+        self.clients
+            .iter_mut()
+            .find(|client| client.actor == *actor)
+    }
+}
+
+// Device id followed by the Ping protocol message, for device messages only
+pub fn binary_frame(answer: &Answer) -> Option<Bytes> {
+    let Answer::DeviceMessage(DeviceAnswer {
+        answer: PingAnswer::PingMessage(message),
+        device_id,
+    }) = answer
+    else {
+        return None;
+    };
+
+    let mut protocol_message = ProtocolMessage::new();
+    match message {
+        bluerobotics_ping::Messages::Ping360(message) => protocol_message.set_message(message),
+        bluerobotics_ping::Messages::Ping1D(message) => protocol_message.set_message(message),
+        bluerobotics_ping::Messages::Common(message) => protocol_message.set_message(message),
+        bluerobotics_ping::Messages::Bluebps(message) => protocol_message.set_message(message),
+        bluerobotics_ping::Messages::Omniscan450(message) => protocol_message.set_message(message),
+    }
+
+    let mut frame = device_id.as_bytes().to_vec();
+    frame.extend(protocol_message.serialized());
+    Some(Bytes::from(frame))
 }
 
 lazy_static! {
@@ -70,35 +212,84 @@ lazy_static! {
         Arc::new(Mutex::new(WebsocketManager::default()));
 }
 
-pub fn send_to_websockets(message: Value, device: Option<Uuid>) {
+pub fn send_to_websockets(message: Value, topic: Topic, device: Option<Uuid>) {
+    MANAGER
+        .lock()
+        .unwrap()
+        .send(&message, &message.to_string(), topic, device);
+}
+
+pub fn send_answer_to_websockets(answer: &Answer, device: Option<Uuid>) {
+    MANAGER.lock().unwrap().send_answer(answer, device, false);
+}
+
+// Continuous mode messages, which clients may receive at a lower rate
+pub fn stream_to_websockets(answer: &Answer, device_id: Uuid) {
     MANAGER
         .lock()
         .unwrap()
-        .send(&message, &message.to_string(), device);
+        .send_answer(answer, Some(device_id), true);
 }
 
 pub struct WebsocketActor {
     server: Arc<Mutex<WebsocketManager>>,
     pub filter: String,
     pub device_number: Option<Uuid>,
+    pub format: WebsocketFormat,
     pub manager_handler: web::Data<ManagerActorHandler>,
+    // Lease tokens granted to this connection, or given on the query for the other devices
+    pub lease_tokens: HashMap<Uuid, String>,
+    pub lease_token: Option<String>,
+    // Access of the API token used to connect
+    pub scope: TokenScope,
+    pub queue: Arc<ClientQueue>,
 }
 
 impl WebsocketActor {
     pub fn new(
         message_filter: String,
         device_number: Option<Uuid>,
+        format: WebsocketFormat,
         manager_handler: web::Data<ManagerActorHandler>,
+        lease_token: Option<String>,
+        scope: TokenScope,
+        max_rate: Option<MaxRate>,
     ) -> Self {
         Self {
             server: MANAGER.clone(),
             filter: message_filter,
             device_number,
+            format,
             manager_handler,
+            lease_tokens: HashMap::new(),
+            lease_token,
+            scope,
+            queue: Arc::new(ClientQueue::new(CLIENT_QUEUE_SIZE, max_rate)),
         }
     }
 }
 
+impl WebsocketActor {
+    // Changes are applied in order with the messages being broadcast, the acknowledgement is
+    // queued after the last message sent with the previous subscriptions
+    fn subscription_command(
+        &self,
+        command: SubscriptionCommand,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let mut server = self.server.lock().unwrap();
+        let Some(client) = server.client_mut(&ctx.address()) else {
+            return;
+        };
+
+        let reply = match client.subscriptions.handle(command) {
+            Ok(ack) => json!(ack),
+            Err(error) => json!(WebsocketError { error }),
+        };
+        client.deliver(OutgoingMessage::Text(reply.to_string()));
+    }
+}
+
 impl Handler<StringMessage> for WebsocketActor {
     type Result = ();
 
@@ -107,6 +298,19 @@ impl Handler<StringMessage> for WebsocketActor {
     }
 }
 
+impl Handler<FlushQueue> for WebsocketActor {
+    type Result = ();
+
+    fn handle(&mut self, _: FlushQueue, context: &mut Self::Context) {
+        for message in self.queue.take() {
+            match message {
+                OutgoingMessage::Text(text) => context.text(text),
+                OutgoingMessage::Binary(frame) => context.binary(frame),
+            }
+        }
+    }
+}
+
 impl Actor for WebsocketActor {
     type Context = ws::WebsocketContext<Self>;
 }
@@ -122,6 +326,9 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
                 actor: ctx.address(),
                 re: Regex::new(&self.filter).ok(),
                 device_number: (self.device_number),
+                format: self.format,
+                queue: self.queue.clone(),
+                subscriptions: Subscriptions::default(),
             });
     }
 
@@ -138,6 +345,23 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
             Ok(ws::Message::Text(text)) => {
+                match serde_json::from_str::<SubscriptionCommand>(&text) {
+                    Ok(command) => {
+                        self.subscription_command(command, ctx);
+                        return;
+                    }
+                    Err(err) if SubscriptionCommand::is_named(&text) => {
+                        ctx.text(
+                            json!(WebsocketError {
+                                error: format!("Invalid subscription command: {err}"),
+                            })
+                            .to_string(),
+                        );
+                        return;
+                    }
+                    Err(_) => (),
+                }
+
                 let manager_requests: Vec<crate::ModuleType> = match serde_json::from_str(&text) {
                     Ok(requests) => requests,
                     Err(err) => match serde_json::from_str(&text) {
@@ -153,6 +377,19 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
                 for request in manager_requests {
                     match request {
                         crate::ModuleType::DeviceManager(request) => {
+                            let required = auth::request_scope(&request);
+                            if self.scope < required {
+                                ctx.text(
+                                    json!(WebsocketError {
+                                        error: format!(
+                                            "The API token can't be used for {required:?} requests"
+                                        ),
+                                    })
+                                    .to_string(),
+                                );
+                                continue;
+                            }
+
                             let manager_handler = self.manager_handler.clone();
 
                             let request_has_id = match &request {
@@ -166,22 +403,47 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
                                 Request::DisableContinuousMode(uuid_wrapper) => {
                                     Some(uuid_wrapper.uuid)
                                 }
+                                Request::AcquireLease(lease_request) => Some(lease_request.uuid),
+                                Request::ReleaseLease(uuid_wrapper) => Some(uuid_wrapper.uuid),
                                 _ => None,
                             };
+                            let client = LeaseClient {
+                                token: request_has_id
+                                    .and_then(|device| self.lease_tokens.get(&device))
+                                    .or(self.lease_token.as_ref())
+                                    .cloned(),
+                            };
 
                             let future =
-                                async move { manager_handler.send(request).await }.into_actor(self);
+                                async move { manager_handler.send_as(request, Some(client)).await }
+                                    .into_actor(self);
 
                             future
                                 .then(move |res, actor, ctx| {
                                     match &res {
                                         Ok(result) => {
-                                            let device_number = match request_has_id{
+                                            match result {
+                                                // The token is only sent to this client
+                                                Answer::LeaseGranted(grant) => {
+                                                    actor.lease_tokens.insert(
+                                                        grant.lease.device_id,
+                                                        grant.token.clone(),
+                                                    );
+                                                    ctx.text(json!(result).to_string());
+                                                }
+                                                Answer::Lease(None) => {
+                                                    if let Some(device) = request_has_id {
+                                                        actor.lease_tokens.remove(&device);
+                                                    }
+                                                }
+                                                _ => (),
+                                            }
+                                            let device_number = match request_has_id {
                                                 Some(device_number) => Some(device_number),
                                                 None => actor.device_number,
                                             };
-                                            crate::server::protocols::v1::websocket::send_to_websockets(
-                                                json!(result),
+                                            send_answer_to_websockets(
+                                                &result.shared(),
                                                 device_number,
                                             );
                                         }
@@ -218,6 +480,18 @@ pub async fn websocket(
     };
     let device_number = query_inner.device_number;
 
+    let max_rate = match query_inner.max_rate {
+        Some(rate) => match MaxRate::new(rate) {
+            Some(max_rate) => Some(max_rate),
+            None => {
+                return Ok(HttpResponse::BadRequest().json(json!({
+                    "error": format!("Invalid max_rate: {rate}")
+                })));
+            }
+        },
+        None => None,
+    };
+
     if let Some(device_number) = device_number {
         let request = crate::device::manager::Request::Info(crate::device::manager::UuidWrapper {
             uuid: device_number,
@@ -235,12 +509,48 @@ pub async fn websocket(
     }
 
     ws::start(
-        WebsocketActor::new(filter, device_number, manager_handler.clone()),
+        WebsocketActor::new(
+            filter,
+            device_number,
+            query_inner.format.unwrap_or_default(),
+            manager_handler.clone(),
+            query_inner.lease_token,
+            auth::granted_scope(&req),
+            max_rate,
+        ),
         &req,
         stream,
     )
 }
 
+// Recording sessions for the clients subscribed to the recording status
+pub async fn forward_recording_status(recordings_handler: RecordingsManagerHandler) {
+    let mut subscriber = match recordings_handler
+        .send(RecordingManagerCommand::GetSubscriber)
+        .await
+    {
+        Ok(crate::device::recording::Answer::RecordingManager(subscriber)) => subscriber,
+        _ => {
+            warn!("ServerManager: Failed to get Recordings Manager, recording status is not forwarded");
+            return;
+        }
+    };
+
+    loop {
+        match subscriber.recv().await {
+            Ok(session) => send_to_websockets(
+                json!({ "RecordingStatus": session }),
+                Topic::RecordingStatus,
+                Some(session.device_id),
+            ),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("ServerManager: {skipped} recording status updates not forwarded");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
 pub struct RecordingStatusActor {
     recording_subscriber: broadcast::Receiver<crate::device::recording::RecordingSession>,
 }
@@ -313,9 +623,271 @@ pub async fn recording_websocket(
     ws::start(RecordingStatusActor::new(subscriber), &req, stream)
 }
 
+pub struct DeviceEventsActor {
+    events: Option<broadcast::Receiver<DeviceEvent>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Actor for DeviceEventsActor {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Handler<StringMessage> for DeviceEventsActor {
+    type Result = ();
+
+    fn handle(&mut self, message: StringMessage, ctx: &mut Self::Context) {
+        ctx.text(message.0);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for DeviceEventsActor {
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("DeviceEventsActor: Starting websocket client");
+
+        let addr = ctx.address();
+        let Some(mut events) = self.events.take() else {
+            return;
+        };
+
+        self.task = Some(tokio::spawn(async move {
+            loop {
+                let message = match events.recv().await {
+                    Ok(event) => json!(event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "DeviceEventsActor: {skipped} device events skipped for a slow client"
+                        );
+                        json!({ "EventsSkipped": skipped })
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                addr.do_send(StringMessage(message.to_string()));
+            }
+        }));
+    }
+
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(msg)) => ctx.close(msg),
+            _ => (),
+        }
+    }
+}
+
+/// Stream the device lifecycle events, each one as a JSON text message.
+/// A slow client is told how many events it missed with `{"EventsSkipped": N}`.
+#[api_v2_operation(skip)]
+#[get("ws/events")]
+pub async fn device_events_websocket(
+    req: HttpRequest,
+    stream: web::Payload,
+    manager_handler: web::Data<ManagerActorHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    ws::start(
+        DeviceEventsActor {
+            events: Some(manager_handler.subscribe_events()),
+            task: None,
+        },
+        &req,
+        stream,
+    )
+}
+
+pub struct SonarImageFrame {
+    info: String,
+    png: Vec<u8>,
+}
+
+impl Message for SonarImageFrame {
+    type Result = ();
+}
+
+// Wait before asking again for the sonar image of a device that isn't streaming
+static SONAR_IMAGE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub struct SonarImageActor {
+    device_number: Uuid,
+    period: Duration,
+    manager_handler: web::Data<ManagerActorHandler>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Actor for SonarImageActor {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Handler<StringMessage> for SonarImageActor {
+    type Result = ();
+
+    fn handle(&mut self, message: StringMessage, ctx: &mut Self::Context) {
+        ctx.text(message.0);
+    }
+}
+
+impl Handler<SonarImageFrame> for SonarImageActor {
+    type Result = ();
+
+    fn handle(&mut self, frame: SonarImageFrame, ctx: &mut Self::Context) {
+        ctx.text(frame.info);
+        ctx.binary(frame.png);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SonarImageActor {
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!(
+            "SonarImageActor: Starting websocket client, device: {}",
+            self.device_number
+        );
+
+        let addr = ctx.address();
+        let (device_number, period) = (self.device_number, self.period);
+        let manager_handler = self.manager_handler.clone();
+
+        self.task = Some(tokio::spawn(async move {
+            // The image restarts with the device, keep asking for it while the client is connected
+            while addr.connected() {
+                let image = match manager_handler.get_sonar_image(device_number).await {
+                    Ok(image) => image,
+                    Err(err) => {
+                        warn!("SonarImageActor: Sonar image not available: {err:?}");
+                        addr.do_send(StringMessage(json!(err).to_string()));
+                        tokio::time::sleep(SONAR_IMAGE_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+
+                let mut revision = image.subscribe();
+                while revision.changed().await.is_ok() {
+                    addr.do_send(SonarImageFrame {
+                        info: json!({ "SonarImage": image.info() }).to_string(),
+                        png: image.encode_png(),
+                    });
+                    // Pings drawn while waiting are sent with the next image
+                    tokio::time::sleep(period).await;
+                }
+
+                tokio::time::sleep(SONAR_IMAGE_RETRY_DELAY).await;
+            }
+        }));
+    }
+
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(msg)) => ctx.close(msg),
+            _ => (),
+        }
+    }
+}
+
+/// Stream the sonar image of a Ping360 device, each update is a JSON text message with the
+/// image information followed by a binary message with the PNG.
+#[api_v2_operation(skip)]
+#[get("ws/sonar_image")]
+pub async fn sonar_image_websocket(
+    req: HttpRequest,
+    query: web::Query<SonarImageQuery>,
+    stream: web::Payload,
+    manager_handler: web::Data<ManagerActorHandler>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let query = query.into_inner();
+    let max_rate = query.max_rate.unwrap_or(10.0);
+    // Tiny rates give periods that don't fit a Duration
+    let period = match Duration::try_from_secs_f32(1.0 / max_rate) {
+        Ok(period) if max_rate.is_finite() && max_rate > 0.0 => period,
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid max_rate: {max_rate}")
+            })));
+        }
+    };
+
+    ws::start(
+        SonarImageActor {
+            device_number: query.device_number,
+            period,
+            manager_handler: manager_handler.clone(),
+            task: None,
+        },
+        &req,
+        stream,
+    )
+}
+
+#[derive(Deserialize, Apiv2Schema, Clone)]
+pub struct SonarImageQuery {
+    device_number: Uuid,
+    /// Maximum images per second, defaults to 10
+    max_rate: Option<f32>,
+}
+
 #[derive(Deserialize, Apiv2Schema, Clone)]
 pub struct WebsocketQuery {
-    /// Regex filter to select the desired incoming messages
+    /// Regex filter to select the desired incoming messages, replaced by the subscriptions once the client subscribes
     filter: Option<String>,
     device_number: Option<Uuid>,
+    /// Token of a device lease acquired through the REST API, leases acquired by the connection are used automatically
+    lease_token: Option<String>,
+    /// Device messages encoding, json by default or binary Ping protocol frames prefixed by the device id
+    format: Option<WebsocketFormat>,
+    /// Maximum continuous mode messages per second for each device, all of them are sent by default
+    max_rate: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bluerobotics_ping::ping360::{AutoDeviceDataStruct, Messages as Ping360Messages};
+
+    #[test]
+    fn binary_frames_carry_device_and_ping_message() {
+        let device_id = Uuid::new_v4();
+        let data = AutoDeviceDataStruct {
+            mode: 1,
+            gain_setting: 0,
+            angle: 42,
+            transmit_duration: 10,
+            sample_period: 80,
+            transmit_frequency: 750,
+            start_angle: 0,
+            stop_angle: 399,
+            num_steps: 1,
+            delay: 0,
+            number_of_samples: 1200,
+            data_length: 1200,
+            data: (0..1200).map(|sample| (sample % 256) as u8).collect(),
+        };
+        let answer = Answer::DeviceMessage(DeviceAnswer {
+            answer: PingAnswer::PingMessage(bluerobotics_ping::Messages::Ping360(
+                Ping360Messages::AutoDeviceData(data.clone()),
+            )),
+            device_id,
+        });
+
+        let frame = binary_frame(&answer).unwrap();
+        assert_eq!(&frame[..16], device_id.as_bytes());
+        let Ok(bluerobotics_ping::Messages::Ping360(Ping360Messages::AutoDeviceData(decoded))) =
+            bluerobotics_ping::Messages::try_from(&frame[16..].to_vec())
+        else {
+            panic!("Failed to decode the Ping protocol message");
+        };
+        assert_eq!((decoded.angle, decoded.data), (42, data.data));
+        assert!(frame.len() * 2 < serde_json::to_string(&answer).unwrap().len());
+
+        assert!(binary_frame(&Answer::DeviceInfo(Vec::new())).is_none());
+    }
 }
@@ -0,0 +1,223 @@
+use chrono::{Datelike, Duration as ChronoDuration, Utc};
+use rcgen::{date_time_ymd, CertificateParams, DistinguishedName, DnType, KeyPair};
+use rustls::{crypto::ring::default_provider, ServerConfig};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::info;
+
+static CERTIFICATE_FILE_NAME: &str = "cert.pem";
+static KEY_FILE_NAME: &str = "key.pem";
+static SELF_SIGNED_COMMON_NAME: &str = "ping-viewer-next";
+static SELF_SIGNED_VALIDITY_DAYS: i64 = 3650;
+
+/// Certificate and private key used to serve HTTPS and secure websockets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsSettings {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// Generate a self-signed certificate when both files are missing.
+    pub self_signed: bool,
+}
+
+impl TlsSettings {
+    // Self-signed certificate stored in the folder, generated on first run
+    pub fn self_signed(folder: &Path) -> Self {
+        Self {
+            cert_path: folder.join(CERTIFICATE_FILE_NAME),
+            key_path: folder.join(KEY_FILE_NAME),
+            self_signed: true,
+        }
+    }
+}
+
+fn crypto_error(err: impl fmt::Display) -> io::Error {
+    io::Error::other(format!("Failed to generate certificate: {err}"))
+}
+
+// Self-signed ECDSA P-256 certificate for the host names and addresses, as PEM certificate and key
+pub fn self_signed_certificate(names: &[String]) -> io::Result<(String, String)> {
+    let mut params = CertificateParams::new(names.to_vec()).map_err(crypto_error)?;
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, SELF_SIGNED_COMMON_NAME);
+    let date = |time: chrono::DateTime<Utc>| {
+        date_time_ymd(time.year(), time.month() as u8, time.day() as u8)
+    };
+    let now = Utc::now();
+    params.not_before = date(now - ChronoDuration::days(1));
+    params.not_after = date(now + ChronoDuration::days(SELF_SIGNED_VALIDITY_DAYS));
+
+    let key_pair = KeyPair::generate().map_err(crypto_error)?;
+    let certificate = params.self_signed(&key_pair).map_err(crypto_error)?;
+    Ok((certificate.pem(), key_pair.serialize_pem()))
+}
+
+// Names of the server certificate, the local ones and the address the server is bound to,
+// or the addresses of all interfaces when it is bound to every one of them
+pub fn certificate_names(server_address: &str) -> Vec<String> {
+    let mut addresses: Vec<IpAddr> =
+        vec![[127, 0, 0, 1].into(), std::net::Ipv6Addr::LOCALHOST.into()];
+    match server_address.parse::<SocketAddr>() {
+        Ok(address) if address.ip().is_unspecified() => addresses.extend(
+            pnet_datalink::interfaces()
+                .iter()
+                .flat_map(|interface| interface.ips.iter().map(|network| network.ip())),
+        ),
+        Ok(address) => addresses.push(address.ip()),
+        Err(_) => (),
+    }
+
+    let mut names = vec!["localhost".to_string()];
+    for address in addresses.iter().map(IpAddr::to_string) {
+        if !names.contains(&address) {
+            names.push(address);
+        }
+    }
+    names
+}
+
+fn write_self_signed(settings: &TlsSettings, names: &[String]) -> io::Result<()> {
+    let (certificate, key) = self_signed_certificate(names)?;
+    for path in [&settings.cert_path, &settings.key_path] {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    // The key is only readable by its owner from the moment it is created
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&settings.key_path)?
+        .write_all(key.as_bytes())?;
+    std::fs::write(&settings.cert_path, certificate)?;
+
+    info!(
+        "TLS: Self-signed certificate for {names:?} generated: {:?}",
+        settings.cert_path
+    );
+    Ok(())
+}
+
+// Load the certificate chain and key, generating the self-signed certificate on first run
+pub fn server_config(settings: &TlsSettings, names: &[String]) -> io::Result<ServerConfig> {
+    if settings.self_signed && !settings.cert_path.exists() && !settings.key_path.exists() {
+        write_self_signed(settings, names)?;
+    }
+
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|err| io::Error::new(err.kind(), format!("{path:?}: {err}")))
+    };
+    let certificates =
+        rustls_pemfile::certs(&mut open(&settings.cert_path)?).collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No certificate found in {:?}", settings.cert_path),
+        ));
+    }
+    let key = rustls_pemfile::private_key(&mut open(&settings.key_path)?)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No private key found in {:?}", settings.key_path),
+        )
+    })?;
+
+    ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpServer};
+    use rustls::{
+        pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+    };
+    use std::{io::Read, net::TcpStream};
+
+    #[actix_web::test]
+    async fn https_with_self_signed_certificate() {
+        let folder = std::env::temp_dir().join(format!("tls-{}", uuid::Uuid::new_v4()));
+        let settings = TlsSettings::self_signed(&folder);
+        let names = certificate_names("0.0.0.0:4936");
+        assert!(names.contains(&"127.0.0.1".to_string()));
+        let config = server_config(&settings, &names).unwrap();
+
+        // The certificate is generated once and kept, with a private key
+        let certificate = std::fs::read(&settings.cert_path).unwrap();
+        server_config(&settings, &names).unwrap();
+        assert_eq!(std::fs::read(&settings.cert_path).unwrap(), certificate);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(&settings.key_path).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = HttpServer::new(|| {
+            App::new().route(
+                "/",
+                web::get().to(|req: HttpRequest| async move {
+                    req.connection_info().scheme().to_string()
+                }),
+            )
+        })
+        .listen_rustls_0_23(listener, config)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        // Clients trusting the generated certificate
+        let mut roots = RootCertStore::empty();
+        for certificate in rustls_pemfile::certs(&mut certificate.as_slice()) {
+            roots.add(certificate.unwrap()).unwrap();
+        }
+        let client = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let response = tokio::task::spawn_blocking(move || {
+            let connection =
+                ClientConnection::new(Arc::new(client), ServerName::try_from("localhost").unwrap())
+                    .unwrap();
+            let mut stream = StreamOwned::new(connection, TcpStream::connect(address).unwrap());
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            // The server may close without a TLS close_notify
+            let _ = stream.read_to_string(&mut response);
+            response
+        })
+        .await
+        .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("https"), "{response}");
+
+        handle.stop(false).await;
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    cli,
+    device::{
+        manager::{
+            ping360_scan::Ping360ScanSchedule, DeviceSelection, Ping1DConfig,
+            Ping1DContinuousConfig, Ping360Config, SourceSelection,
+        },
+        output::{mavlink::MavlinkOutputConfig, nmea::NmeaOutputConfig},
+    },
+    logger,
+    server::auth::ApiToken,
+};
+
+static SETTINGS_FILE_NAME: &str = "settings.json";
+static SETTINGS_VERSION: u32 = 1;
+static SETTINGS_BACKUP_EXTENSION: &str = "json.bak";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct HeaderSettingsFile {
+    pub name: String,
+    pub version: u32,
+}
+
+/// Everything required to recreate a device created through DeviceManager.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DeviceSettings {
+    pub id: Uuid,
+    pub source: SourceSelection,
+    pub device_type: DeviceSelection,
+    pub continuous_mode: bool,
+    #[serde(default)]
+    pub ping360_config: Option<Ping360Config>,
+    #[serde(default)]
+    pub ping360_scan_schedule: Option<Ping360ScanSchedule>,
+    #[serde(default)]
+    pub ping1d_continuous_config: Option<Ping1DContinuousConfig>,
+    #[serde(default)]
+    pub ping1d_config: Option<Ping1DConfig>,
+    #[serde(default)]
+    pub nmea_output: Option<NmeaOutputConfig>,
+    #[serde(default)]
+    pub mavlink_output: Option<MavlinkOutputConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SettingsStruct {
+    pub header: HeaderSettingsFile,
+    pub devices: Vec<DeviceSettings>,
+    /// Tokens accepted by the REST and websocket server, along the ones from the command line.
+    #[serde(default)]
+    pub api_tokens: Vec<ApiToken>,
+}
+
+impl Default for SettingsStruct {
+    fn default() -> Self {
+        Self {
+            header: HeaderSettingsFile {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: SETTINGS_VERSION,
+            },
+            devices: Vec::new(),
+            api_tokens: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Manager {
+    file_path: PathBuf,
+    content: SettingsStruct,
+}
+
+lazy_static! {
+    static ref MANAGER: Arc<Mutex<Option<Manager>>> = Arc::new(Mutex::new(None));
+}
+
+impl Manager {
+    fn new(file_path: PathBuf) -> Self {
+        if cli::manager::is_reset() && file_path.exists() {
+            match std::fs::remove_file(&file_path) {
+                Ok(()) => info!("Settings file removed: {file_path:?}"),
+                Err(err) => error!("Failed to remove settings file {file_path:?}: {err}"),
+            }
+        }
+
+        let content = load_or_backup_settings(&file_path);
+
+        Self { file_path, content }
+    }
+}
+
+// Construct our manager, should be done inside main
+pub fn init() {
+    let file_path = settings_file_path();
+    let manager = Manager::new(file_path);
+
+    // Existing files are only rewritten when something changes, a default one is created otherwise
+    if !manager.file_path.exists() {
+        if let Err(err) = save_settings_to_file(&manager.file_path, &manager.content) {
+            error!(
+                "Failed to save settings file {:?}: {err}",
+                manager.file_path
+            );
+        }
+    }
+
+    info!("Using settings file: {:?}", manager.file_path);
+    *MANAGER.lock().unwrap() = Some(manager);
+}
+
+pub fn settings_file_path() -> PathBuf {
+    logger::manager::get_app_home_dir().join(SETTINGS_FILE_NAME)
+}
+
+// Load the settings file, an unreadable one is kept aside as a backup before using the defaults
+fn load_or_backup_settings(file_path: &Path) -> SettingsStruct {
+    let err = match load_settings_from_file(file_path) {
+        Ok(content) => return content,
+        Err(err) => err,
+    };
+
+    let backup_path = file_path.with_extension(SETTINGS_BACKUP_EXTENSION);
+    match std::fs::rename(file_path, &backup_path) {
+        Ok(()) => warn!(
+            "Using default settings, failed to load {file_path:?}: {err}. Previous file kept as {backup_path:?}"
+        ),
+        Err(rename_err) => error!(
+            "Using default settings, failed to load {file_path:?}: {err}. Unable to keep it as {backup_path:?}: {rename_err}"
+        ),
+    }
+
+    SettingsStruct::default()
+}
+
+fn load_settings_from_file(file_path: &Path) -> Result<SettingsStruct, String> {
+    if !file_path.exists() {
+        debug!("Settings file {file_path:?} does not exist yet");
+        return Ok(SettingsStruct::default());
+    }
+
+    let content = std::fs::read_to_string(file_path).map_err(|err| err.to_string())?;
+    let settings: SettingsStruct = serde_json::from_str(&content).map_err(|err| err.to_string())?;
+    let settings = migrate_settings(settings)?;
+
+    Ok(settings)
+}
+
+// Bring a file saved by a previous version up to date, fields added since then take their defaults
+fn migrate_settings(mut settings: SettingsStruct) -> Result<SettingsStruct, String> {
+    let version = settings.header.version;
+    if version > SETTINGS_VERSION {
+        return Err(format!(
+            "Unsupported settings version {version}, newer than {SETTINGS_VERSION}"
+        ));
+    }
+
+    if version < SETTINGS_VERSION {
+        info!("Migrating settings from version {version} to {SETTINGS_VERSION}");
+        settings.header.version = SETTINGS_VERSION;
+    }
+
+    Ok(settings)
+}
+
+fn save_settings_to_file(file_path: &Path, content: &SettingsStruct) -> Result<(), String> {
+    if let Some(parent) = file_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+
+    let json = serde_json::to_string_pretty(content).map_err(|err| err.to_string())?;
+    std::fs::write(file_path, json).map_err(|err| err.to_string())
+}
+
+// Return the devices stored in the settings file
+pub fn devices() -> Vec<DeviceSettings> {
+    match MANAGER.lock().unwrap().as_ref() {
+        Some(manager) => manager.content.devices.clone(),
+        None => {
+            warn!("Settings manager is not initialized, no devices available");
+            Vec::new()
+        }
+    }
+}
+
+// Return the API tokens stored in the settings file
+pub fn api_tokens() -> Vec<ApiToken> {
+    match MANAGER.lock().unwrap().as_ref() {
+        Some(manager) => manager.content.api_tokens.clone(),
+        None => Vec::new(),
+    }
+}
+
+// Replace the devices stored in the settings file and save it
+pub fn set_devices(devices: Vec<DeviceSettings>) {
+    let mut guard = MANAGER.lock().unwrap();
+    let Some(manager) = guard.as_mut() else {
+        debug!("Settings manager is not initialized, devices will not be saved");
+        return;
+    };
+
+    if manager.content.devices == devices {
+        return;
+    }
+
+    manager.content.devices = devices;
+    match save_settings_to_file(&manager.file_path, &manager.content) {
+        Ok(()) => debug!("Settings file saved: {:?}", manager.file_path),
+        Err(err) => error!(
+            "Failed to save settings file {:?}: {err}",
+            manager.file_path
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::manager::SourceUdpStruct;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn settings_file_round_trip() {
+        let file_path = std::env::temp_dir()
+            .join(format!("ping-viewer-next-{}", Uuid::new_v4()))
+            .join(SETTINGS_FILE_NAME);
+
+        let mut settings = SettingsStruct::default();
+        settings.devices.push(DeviceSettings {
+            id: Uuid::new_v4(),
+            source: SourceSelection::UdpStream(SourceUdpStruct {
+                ip: Ipv4Addr::new(192, 168, 2, 2),
+                port: 12345,
+            }),
+            device_type: DeviceSelection::Ping360,
+            continuous_mode: true,
+            ping360_config: None,
+            ping360_scan_schedule: None,
+            ping1d_continuous_config: None,
+            ping1d_config: None,
+            nmea_output: None,
+            mavlink_output: None,
+        });
+
+        save_settings_to_file(&file_path, &settings).unwrap();
+        assert_eq!(load_settings_from_file(&file_path), Ok(settings));
+
+        std::fs::remove_dir_all(file_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn corrupt_settings_file_is_kept() {
+        let dir = std::env::temp_dir().join(format!("ping-viewer-next-{}", Uuid::new_v4()));
+        let file_path = dir.join(SETTINGS_FILE_NAME);
+        std::fs::create_dir_all(&dir).unwrap();
+        let corrupt = r#"{"header": {"name": "ping-viewer-next", "version": 1}, "devices": ["#;
+        std::fs::write(&file_path, corrupt).unwrap();
+
+        assert_eq!(
+            load_or_backup_settings(&file_path),
+            SettingsStruct::default()
+        );
+        assert!(!file_path.exists());
+        let backup = std::fs::read_to_string(dir.join("settings.json.bak")).unwrap();
+        assert_eq!(backup, corrupt);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn older_settings_are_migrated() {
+        let file_path = std::env::temp_dir()
+            .join(format!("ping-viewer-next-{}", Uuid::new_v4()))
+            .join(SETTINGS_FILE_NAME);
+
+        let mut settings = SettingsStruct::default();
+        settings.header.version = 0;
+        save_settings_to_file(&file_path, &settings).unwrap();
+
+        assert_eq!(
+            load_settings_from_file(&file_path),
+            Ok(SettingsStruct::default())
+        );
+
+        settings.header.version = SETTINGS_VERSION + 1;
+        save_settings_to_file(&file_path, &settings).unwrap();
+        assert!(load_settings_from_file(&file_path).is_err());
+
+        std::fs::remove_dir_all(file_path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use mavlink::{
+    ardupilotmega::MavMessage, async_peek_reader::AsyncPeekReader, error::MessageReadError,
+    AsyncMavConnection, MavHeader,
+};
+use tokio::sync::RwLock;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tracing::{error, info, trace};
+
+use super::{status, PoseHistory, VehicleBridgeSettings, VehicleDataState};
+
+// Component id used by the autopilot, same one subscribed on the zenoh topics
+static AUTOPILOT_COMPONENT_ID: u8 = 1;
+
+enum MavlinkReader {
+    Connection(Box<dyn AsyncMavConnection<MavMessage> + Sync + Send>),
+    Serial(Box<AsyncPeekReader<SerialStream>>),
+}
+
+impl MavlinkReader {
+    async fn connect(address: &str) -> std::io::Result<Self> {
+        // The mavlink crate serial support is not enabled, serial ports are handled with tokio-serial
+        if let Some(serial) = address.strip_prefix("serial:") {
+            let (path, baudrate) = serial.rsplit_once(':').ok_or_else(|| {
+                std::io::Error::other(format!(
+                    "Invalid serial address {address:?}, expected serial:<PATH>:<BAUDRATE>"
+                ))
+            })?;
+            let baudrate = baudrate.parse::<u32>().map_err(std::io::Error::other)?;
+            let stream = tokio_serial::new(path, baudrate).open_native_async()?;
+            return Ok(Self::Serial(Box::new(AsyncPeekReader::new(stream))));
+        }
+
+        Ok(Self::Connection(
+            mavlink::connect_async::<MavMessage>(address).await?,
+        ))
+    }
+
+    async fn recv(&mut self) -> Result<(MavHeader, MavMessage), MessageReadError> {
+        match self {
+            Self::Connection(connection) => connection.recv().await,
+            Self::Serial(reader) => mavlink::read_any_msg_async(reader.as_mut()).await,
+        }
+    }
+}
+
+pub async fn mavlink_client_bridge(
+    address: String,
+    settings: VehicleBridgeSettings,
+    latest_pose: Arc<RwLock<PoseHistory>>,
+) {
+    use tokio::time::{sleep, Duration};
+
+    let reconnect_delay_secs = 5;
+    let reconnect_delay = Duration::from_secs(reconnect_delay_secs);
+
+    loop {
+        let mut reader = match MavlinkReader::connect(&address).await {
+            Ok(reader) => reader,
+            Err(e) => {
+                error!("MAVLink connection error for {address}: {e}, retrying in {reconnect_delay_secs}s");
+                status::set_disconnected(format!("MAVLink connection error: {e}"));
+                sleep(reconnect_delay).await;
+                continue;
+            }
+        };
+        info!("MAVLink vehicle source connected to {address}");
+        status::set_connected();
+
+        let mut state = VehicleDataState::new(settings.depth);
+
+        loop {
+            match reader.recv().await {
+                Ok((header, message)) => {
+                    if header.component_id != AUTOPILOT_COMPONENT_ID
+                        || !settings.accepts_system(header.system_id)
+                    {
+                        continue;
+                    }
+                    if let Some(data) = state.update(&message) {
+                        status::message_received();
+                        latest_pose.write().await.push(data.clone());
+                    }
+                }
+                Err(MessageReadError::Io(e)) => {
+                    error!("MAVLink recv error: {e}, reconnecting in {reconnect_delay_secs}s");
+                    status::set_disconnected(format!("MAVLink recv error: {e}"));
+                    break;
+                }
+                Err(e) => {
+                    trace!("MAVLink invalid message ignored: {e}");
+                    continue;
+                }
+            }
+        }
+
+        sleep(reconnect_delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavlink::ardupilotmega::{ATTITUDE_DATA, GLOBAL_POSITION_INT_DATA};
+    use std::net::UdpSocket;
+    use tokio::time::{sleep, timeout, Duration};
+
+    fn frame(system_id: u8, component_id: u8, message: &MavMessage) -> Vec<u8> {
+        let header = MavHeader {
+            system_id,
+            component_id,
+            sequence: 0,
+        };
+        let mut frame = Vec::new();
+        mavlink::write_v2_msg(&mut frame, header, message).unwrap();
+        frame
+    }
+
+    #[tokio::test]
+    async fn vehicle_data_from_udp() {
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let latest_pose = Arc::new(RwLock::new(PoseHistory::default()));
+        let settings = VehicleBridgeSettings {
+            system_id: Some(2),
+            ..Default::default()
+        };
+        let bridge = tokio::spawn(mavlink_client_bridge(
+            format!("udpin:127.0.0.1:{port}"),
+            settings,
+            latest_pose.clone(),
+        ));
+
+        let attitude = MavMessage::ATTITUDE(ATTITUDE_DATA {
+            roll: 0.1,
+            pitch: -0.2,
+            yaw: 1.5,
+            ..Default::default()
+        });
+        let position = MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            lat: -275_000_000,
+            lon: -485_000_000,
+            alt: -12_500,
+            ..Default::default()
+        });
+        // Messages from other components, like a camera, or other vehicles are ignored
+        let foreign_attitude = MavMessage::ATTITUDE(ATTITUDE_DATA::default());
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let pose = timeout(Duration::from_secs(5), async {
+            loop {
+                for frame in [
+                    frame(2, 1, &attitude),
+                    frame(2, 1, &position),
+                    frame(2, 100, &foreign_attitude),
+                    frame(1, 1, &foreign_attitude),
+                ] {
+                    sender.send_to(&frame, ("127.0.0.1", port)).unwrap();
+                }
+                if let Some(pose) = latest_pose
+                    .read()
+                    .await
+                    .latest()
+                    .cloned()
+                    .filter(|pose| pose.roll.is_some() && pose.lat.is_some())
+                {
+                    break pose;
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("No vehicle data received");
+        bridge.abort();
+
+        assert_eq!(
+            (pose.roll, pose.pitch, pose.yaw),
+            (Some(0.1), Some(-0.2), Some(1.5))
+        );
+        assert_eq!(pose.alt, Some(-12.5));
+        assert_eq!((pose.lat, pose.lon), (Some(-27.5), Some(-48.5)));
+        assert_eq!(pose.heading, None);
+    }
+}
@@ -1,141 +1,601 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
-use mavlink::ardupilotmega::ATTITUDE_DATA;
-use mavlink::ardupilotmega::GLOBAL_POSITION_INT_DATA;
+use mavlink::ardupilotmega::MavMessage;
 
 use serde::Deserialize;
 use serde::Serialize;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::info;
 
-#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+/// The `mavlink_bridge` module reads vehicle telemetry from a direct MAVLink UDP, TCP
+/// or serial connection, for systems without a Zenoh router.
+pub mod mavlink_bridge;
+
+/// The `pose_history` module keeps recent vehicle poses, interpolating the pose at the time
+/// each sonar sample is received.
+pub mod pose_history;
+
+/// The `status` module keeps the vehicle bridge connection state, reported by the REST API.
+pub mod status;
+
+/// The `zenoh_bridge` module reads vehicle telemetry from the MAVLink topics published
+/// on Zenoh, like BlueOS does.
+pub mod zenoh_bridge;
+
+pub use mavlink_bridge::mavlink_client_bridge;
+pub use pose_history::PoseHistory;
+pub use zenoh_bridge::{make_default_config, zenoh_client_bridge};
+
+// MAVLink connection prefixes accepted as vehicle source, serial uses serial:<PATH>:<BAUDRATE>
+static MAVLINK_ADDRESS_PREFIXES: [&str; 6] = [
+    "udpin:",
+    "udpout:",
+    "udpbcast:",
+    "tcpin:",
+    "tcpout:",
+    "serial:",
+];
+
+// Messages used to build VehicleData, requested from the zenoh topics
+pub static VEHICLE_MESSAGES: [&str; 6] = [
+    "ATTITUDE",
+    "GLOBAL_POSITION_INT",
+    "VFR_HUD",
+    "SCALED_PRESSURE2",
+    "LOCAL_POSITION_NED",
+    "GPS_RAW_INT",
+];
+
+static GRAVITY_M_S2: f32 = 9.80665;
+// Messages are not received in order, only a larger jump back in the time since boot is a reboot
+static VEHICLE_REBOOT_THRESHOLD_MS: u32 = 5000;
+
+/// Values used to convert the absolute pressure to depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthSettings {
+    /// Absolute pressure at the water surface, in hectopascal.
+    pub surface_pressure_hpa: f32,
+    /// Water density, in kilograms per cubic meter.
+    pub water_density_kg_m3: f32,
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        Self {
+            surface_pressure_hpa: 1013.25,
+            water_density_kg_m3: 1025.0,
+        }
+    }
+}
+
+/// Fields of VehicleData updated together, each one by a single MAVLink message.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, schemars::JsonSchema, PartialEq, Eq)]
+pub enum VehicleDataGroup {
+    /// Roll, pitch and yaw, from ATTITUDE.
+    Attitude,
+    /// Latitude, longitude and altitude, from GLOBAL_POSITION_INT.
+    Position,
+    /// From VFR_HUD.
+    Heading,
+    /// Pressure and depth, from SCALED_PRESSURE2.
+    Depth,
+    /// From LOCAL_POSITION_NED.
+    Velocity,
+    /// Fix type and visible satellites, from GPS_RAW_INT.
+    Gps,
+}
+
+impl VehicleDataGroup {
+    pub const ALL: [Self; 6] = [
+        Self::Attitude,
+        Self::Position,
+        Self::Heading,
+        Self::Depth,
+        Self::Velocity,
+        Self::Gps,
+    ];
+}
+
+/// Unix time in milliseconds of the last measurement of each group of fields.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, schemars::JsonSchema, PartialEq)]
+pub struct VehicleDataTimestamps {
+    pub attitude: Option<u64>,
+    pub position: Option<u64>,
+    pub heading: Option<u64>,
+    pub depth: Option<u64>,
+    pub velocity: Option<u64>,
+    pub gps: Option<u64>,
+}
+
+impl VehicleDataTimestamps {
+    pub fn get(&self, group: VehicleDataGroup) -> Option<u64> {
+        match group {
+            VehicleDataGroup::Attitude => self.attitude,
+            VehicleDataGroup::Position => self.position,
+            VehicleDataGroup::Heading => self.heading,
+            VehicleDataGroup::Depth => self.depth,
+            VehicleDataGroup::Velocity => self.velocity,
+            VehicleDataGroup::Gps => self.gps,
+        }
+    }
+
+    fn set(&mut self, group: VehicleDataGroup, time_ms: u64) {
+        *self.get_mut(group) = Some(time_ms);
+    }
+
+    pub fn clear(&mut self, group: VehicleDataGroup) {
+        *self.get_mut(group) = None;
+    }
+
+    fn get_mut(&mut self, group: VehicleDataGroup) -> &mut Option<u64> {
+        match group {
+            VehicleDataGroup::Attitude => &mut self.attitude,
+            VehicleDataGroup::Position => &mut self.position,
+            VehicleDataGroup::Heading => &mut self.heading,
+            VehicleDataGroup::Depth => &mut self.depth,
+            VehicleDataGroup::Velocity => &mut self.velocity,
+            VehicleDataGroup::Gps => &mut self.gps,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, schemars::JsonSchema, PartialEq)]
 pub struct VehicleData {
     #[schemars(description = "Roll angle in radians")]
-    pub roll: f32,
+    pub roll: Option<f32>,
     #[schemars(description = "Pitch angle in radians")]
-    pub pitch: f32,
+    pub pitch: Option<f32>,
     #[schemars(description = "Yaw angle in radians")]
-    pub yaw: f32,
+    pub yaw: Option<f32>,
     #[schemars(description = "Altitude in meters above sea level")]
-    pub alt: f64,
+    pub alt: Option<f64>,
     #[schemars(description = "Latitude in decimal degrees")]
-    pub lat: f64,
+    pub lat: Option<f64>,
     #[schemars(description = "Longitude in decimal degrees")]
-    pub lon: f64,
+    pub lon: Option<f64>,
+    #[schemars(description = "Heading in degrees, from VFR_HUD")]
+    pub heading: Option<f32>,
+    #[schemars(
+        description = "Absolute pressure in hectopascal, from the external sensor in SCALED_PRESSURE2"
+    )]
+    pub pressure: Option<f32>,
+    #[schemars(description = "Depth in meters below the surface, computed from pressure")]
+    pub depth: Option<f32>,
+    #[schemars(description = "North velocity in meters per second, from LOCAL_POSITION_NED")]
+    pub vx: Option<f32>,
+    #[schemars(description = "East velocity in meters per second, from LOCAL_POSITION_NED")]
+    pub vy: Option<f32>,
+    #[schemars(description = "Down velocity in meters per second, from LOCAL_POSITION_NED")]
+    pub vz: Option<f32>,
+    #[schemars(
+        description = "GPS fix type, 0 no GPS, 1 no fix, 2 2D fix, 3 3D fix, 4 DGPS, 5 RTK float, 6 RTK fixed"
+    )]
+    pub gps_fix_type: Option<u8>,
+    #[schemars(description = "Number of visible GPS satellites")]
+    pub satellites_visible: Option<u8>,
+    #[schemars(
+        description = "Time since vehicle boot in milliseconds, from the last message with a timestamp"
+    )]
+    pub time_boot_ms: Option<u32>,
+    #[schemars(description = "Unix time in milliseconds when the last message was received")]
+    pub timestamp_ms: Option<u64>,
+    #[schemars(
+        description = "Unix time in milliseconds of each group of fields, from the message time since boot when available"
+    )]
+    #[serde(default)]
+    pub timestamps: VehicleDataTimestamps,
+    #[schemars(
+        description = "True when the closest measurement of any group is older than the allowed age"
+    )]
+    #[serde(default)]
+    pub stale: bool,
+    #[schemars(
+        description = "Groups of fields whose closest measurement is older than the allowed age"
+    )]
+    #[serde(default)]
+    pub stale_groups: Vec<VehicleDataGroup>,
 }
 
-#[derive(Deserialize)]
-struct Envelope<T> {
-    message: T,
-}
+impl VehicleData {
+    // Check if a group of fields was not measured for longer than max_age, or never was
+    pub fn is_stale(&self, group: VehicleDataGroup, max_age: std::time::Duration) -> bool {
+        let Some(timestamp_ms) = self.timestamps.get(group) else {
+            return true;
+        };
+        unix_time_ms().saturating_sub(timestamp_ms) > max_age.as_millis() as u64
+    }
 
-fn make_default_config(node_name: &str) -> zenoh::Config {
-    let mut config = zenoh::Config::default();
+    // Mark the data and all its groups with values as measured at the same time
+    pub fn measured_at(mut self, time_ms: u64) -> Self {
+        for group in VehicleDataGroup::ALL {
+            if self.has_group(group) {
+                self.timestamps.set(group, time_ms);
+            }
+        }
+        self.timestamp_ms = Some(time_ms);
+        self
+    }
 
-    // Set client mode (common to both)
-    config
-        .insert_json5("mode", r#""client""#)
-        .expect("Failed to insert client mode");
-    config
-        .insert_json5("metadata", &format!(r#"{{"name": "{}"}}"#, node_name))
-        .expect("Failed to insert metadata");
-    config
-        .insert_json5("adminspace/enabled", r#"true"#)
-        .expect("Failed to insert adminspace/enabled");
-    config
-        .insert_json5("connect/endpoints", r#"["tcp/127.0.0.1:7447"]"#)
-        .expect("Failed to insert endpoints");
-    info!("Generated zenoh config with default settings");
-    config
+    fn has_group(&self, group: VehicleDataGroup) -> bool {
+        match group {
+            VehicleDataGroup::Attitude => self.roll.is_some() || self.yaw.is_some(),
+            VehicleDataGroup::Position => self.lat.is_some() || self.alt.is_some(),
+            VehicleDataGroup::Heading => self.heading.is_some(),
+            VehicleDataGroup::Depth => self.pressure.is_some() || self.depth.is_some(),
+            VehicleDataGroup::Velocity => self.vx.is_some(),
+            VehicleDataGroup::Gps => self.gps_fix_type.is_some(),
+        }
+    }
 }
 
-pub async fn zenoh_client_bridge(latest_pose: Arc<RwLock<Option<VehicleData>>>) {
-    use tokio::time::{sleep, Duration};
-    let node_name = env!("CARGO_PKG_NAME");
+pub fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
 
-    let reconnect_delay_secs = 5;
-    let reconnect_delay = Duration::from_secs(reconnect_delay_secs);
+// Accumulate the latest value of each VehicleData field from the received messages
+#[derive(Debug, Default)]
+pub struct VehicleDataState {
+    data: VehicleData,
+    depth: DepthSettings,
+    // Unix time of the vehicle boot, from the messages received with the lowest latency
+    boot_time_ms: Option<u64>,
+}
 
-    loop {
-        let config = make_default_config(node_name);
+impl VehicleDataState {
+    pub fn new(depth: DepthSettings) -> Self {
+        Self {
+            data: VehicleData::default(),
+            depth,
+            boot_time_ms: None,
+        }
+    }
 
-        sleep(reconnect_delay).await;
+    // Apply a message, returning the updated data when it was used
+    pub fn update(&mut self, message: &MavMessage) -> Option<&VehicleData> {
+        self.update_at(message, unix_time_ms())
+    }
 
-        let session = match zenoh::open(config).await {
-            Ok(s) => s,
-            Err(e) => {
-                error!("Zenoh session error: {e}, retrying in {reconnect_delay_secs}s");
-                continue;
+    fn update_at(&mut self, message: &MavMessage, receive_time_ms: u64) -> Option<&VehicleData> {
+        let data = &mut self.data;
+        let (group, time_boot_ms) = match message {
+            MavMessage::ATTITUDE(attitude) => {
+                data.roll = Some(attitude.roll);
+                data.pitch = Some(attitude.pitch);
+                data.yaw = Some(attitude.yaw);
+                (VehicleDataGroup::Attitude, Some(attitude.time_boot_ms))
             }
-        };
-        let attitude_sub = match session.declare_subscriber("mavlink/**/1/ATTITUDE").await {
-            Ok(s) => s,
-            Err(e) => {
-                error!(
-                    "Zenoh subscribe error for ATTITUDE: {e}, retrying in {reconnect_delay_secs}s"
-                );
-                continue;
+            MavMessage::GLOBAL_POSITION_INT(position) => {
+                data.alt = Some(position.alt as f64 / 1000.0);
+                data.lat = Some(position.lat as f64 / 1e7);
+                data.lon = Some(position.lon as f64 / 1e7);
+                (VehicleDataGroup::Position, Some(position.time_boot_ms))
             }
-        };
-        let position_sub = match session
-            .declare_subscriber("mavlink/**/1/GLOBAL_POSITION_INT")
-            .await
-        {
-            Ok(s) => s,
-            Err(e) => {
-                error!("Zenoh subscribe error for GLOBAL_POSITION_INT: {e}, retrying in {reconnect_delay_secs}s");
-                continue;
+            MavMessage::VFR_HUD(vfr_hud) => {
+                data.heading = Some(vfr_hud.heading as f32);
+                (VehicleDataGroup::Heading, None)
             }
+            MavMessage::SCALED_PRESSURE2(pressure) => {
+                data.pressure = Some(pressure.press_abs);
+                data.depth = Some(self.depth.depth(pressure.press_abs));
+                (VehicleDataGroup::Depth, Some(pressure.time_boot_ms))
+            }
+            MavMessage::LOCAL_POSITION_NED(position) => {
+                data.vx = Some(position.vx);
+                data.vy = Some(position.vy);
+                data.vz = Some(position.vz);
+                (VehicleDataGroup::Velocity, Some(position.time_boot_ms))
+            }
+            MavMessage::GPS_RAW_INT(gps) => {
+                data.gps_fix_type = Some(gps.fix_type as u8);
+                data.satellites_visible = Some(gps.satellites_visible);
+                (VehicleDataGroup::Gps, None)
+            }
+            _ => return None,
         };
-        info!("Subscribed to mavlink/**/1/ATTITUDE and mavlink/**/1/GLOBAL_POSITION_INT");
-
-        let mut latest_attitude: Option<ATTITUDE_DATA> = None;
-        let mut latest_position: Option<GLOBAL_POSITION_INT_DATA> = None;
-
-        loop {
-            tokio::select! {
-                res = attitude_sub.recv_async() => {
-                    match res {
-                        Ok(sample) => {
-                            if let Ok(env) = serde_json5::from_slice::<Envelope<ATTITUDE_DATA>>(&sample.payload().to_bytes()) {
-                                latest_attitude = Some(env.message);
-                            }
-                        },
-                        Err(e) => {
-                            error!("Zenoh ATTITUDE recv error: {e}, reconnecting in {reconnect_delay_secs}s");
-                            break;
-                        }
-                    }
-                }
-                res = position_sub.recv_async() => {
-                    match res {
-                        Ok(sample) => {
-                            if let Ok(env) = serde_json5::from_slice::<Envelope<GLOBAL_POSITION_INT_DATA>>(&sample.payload().to_bytes()) {
-                                latest_position = Some(env.message);
-                            }
-                        },
-                        Err(e) => {
-                            error!("Zenoh POSITION recv error: {e}, reconnecting in {reconnect_delay_secs}s");
-                            break;
-                        }
-                    }
+
+        let measurement_time_ms = match time_boot_ms {
+            Some(time_boot_ms) => {
+                // The vehicle rebooted, its previous boot time is no longer valid
+                if data
+                    .time_boot_ms
+                    .is_some_and(|previous| previous > time_boot_ms + VEHICLE_REBOOT_THRESHOLD_MS)
+                {
+                    self.boot_time_ms = None;
                 }
+                data.time_boot_ms = Some(time_boot_ms);
+
+                // The lowest latency is kept, rising slowly to follow the drift between the clocks
+                let boot_time_ms = receive_time_ms.saturating_sub(time_boot_ms as u64);
+                let boot_time_ms = self
+                    .boot_time_ms
+                    .map_or(boot_time_ms, |previous| boot_time_ms.min(previous + 1));
+                self.boot_time_ms = Some(boot_time_ms);
+                boot_time_ms + time_boot_ms as u64
             }
+            None => receive_time_ms,
+        };
+
+        data.timestamps.set(group, measurement_time_ms);
+        data.timestamp_ms = Some(receive_time_ms);
+
+        Some(&self.data)
+    }
+}
+
+impl DepthSettings {
+    // Depth in meters below the surface for an absolute pressure in hectopascal
+    pub fn depth(&self, press_abs: f32) -> f32 {
+        (press_abs - self.surface_pressure_hpa) * 100.0 / (self.water_density_kg_m3 * GRAVITY_M_S2)
+    }
+}
+
+// Replaced in topic key expressions by the vehicle system id, or ** to accept any vehicle
+static SYSTEM_ID_PLACEHOLDER: &str = "{system_id}";
+static MESSAGE_PLACEHOLDER: &str = "{message}";
 
-            if let (Some(att), Some(pos)) = (&latest_attitude, &latest_position) {
-                let pose = VehicleData {
-                    roll: att.roll,
-                    pitch: att.pitch,
-                    yaw: att.yaw,
-                    alt: pos.alt as f64 / 1000.0,
-                    lat: pos.lat as f64 / 1e7,
-                    lon: pos.lon as f64 / 1e7,
-                };
-                let mut pose_guard = latest_pose.write().await;
-                *pose_guard = Some(pose);
+#[derive(Debug, Clone, PartialEq)]
+pub enum VehicleSource {
+    Zenoh,
+    /// MAVLink connection address, like udpin:0.0.0.0:14550 or serial:/dev/ttyACM0:115200.
+    Mavlink(String),
+    Disabled,
+}
+
+impl FromStr for VehicleSource {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "zenoh" => Ok(Self::Zenoh),
+            "none" => Ok(Self::Disabled),
+            address
+                if MAVLINK_ADDRESS_PREFIXES
+                    .iter()
+                    .any(|prefix| address.starts_with(prefix)) =>
+            {
+                Ok(Self::Mavlink(address.to_string()))
             }
+            unknown => Err(format!(
+                "Invalid vehicle source {unknown:?}, expected zenoh, none or a MAVLink address starting with one of {MAVLINK_ADDRESS_PREFIXES:?}"
+            )),
+        }
+    }
+}
+
+impl VehicleSource {
+    pub fn name(&self) -> String {
+        match self {
+            Self::Zenoh => "zenoh".to_string(),
+            Self::Mavlink(address) => address.clone(),
+            Self::Disabled => "none".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZenohMode {
+    Client,
+    Peer,
+    Router,
+}
+
+impl ZenohMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Peer => "peer",
+            Self::Router => "router",
         }
+    }
+}
+
+impl FromStr for ZenohMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "client" => Ok(Self::Client),
+            "peer" => Ok(Self::Peer),
+            "router" => Ok(Self::Router),
+            unknown => Err(format!(
+                "Invalid zenoh mode {unknown:?}, expected client, peer or router"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZenohSettings {
+    pub mode: ZenohMode,
+    /// Endpoints to connect to, like tcp/127.0.0.1:7447.
+    pub endpoints: Vec<String>,
+}
+
+impl Default for ZenohSettings {
+    fn default() -> Self {
+        Self {
+            mode: ZenohMode::Client,
+            endpoints: vec!["tcp/127.0.0.1:7447".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleBridgeSettings {
+    pub source: VehicleSource,
+    pub zenoh: ZenohSettings,
+    /// MAVLink system id of the vehicle, any vehicle is accepted when not set.
+    pub system_id: Option<u8>,
+    /// Zenoh key expression for ATTITUDE, `{system_id}` is replaced by the vehicle system id.
+    pub attitude_topic: String,
+    /// Zenoh key expression for GLOBAL_POSITION_INT, `{system_id}` is replaced by the vehicle system id.
+    pub position_topic: String,
+    /// Zenoh key expression for the other messages, `{system_id}` is replaced by the vehicle
+    /// system id and `{message}` by the message name.
+    pub topic: String,
+    /// Used to convert the absolute pressure to depth.
+    pub depth: DepthSettings,
+}
+
+impl Default for VehicleBridgeSettings {
+    fn default() -> Self {
+        Self {
+            source: VehicleSource::Zenoh,
+            zenoh: ZenohSettings::default(),
+            system_id: None,
+            attitude_topic: "mavlink/{system_id}/1/ATTITUDE".to_string(),
+            position_topic: "mavlink/{system_id}/1/GLOBAL_POSITION_INT".to_string(),
+            topic: "mavlink/{system_id}/1/{message}".to_string(),
+            depth: DepthSettings::default(),
+        }
+    }
+}
+
+impl VehicleBridgeSettings {
+    pub fn key_expression(&self, message: &str) -> String {
+        let system_id = self
+            .system_id
+            .map(|system_id| system_id.to_string())
+            .unwrap_or_else(|| "**".to_string());
+        let topic = match message {
+            "ATTITUDE" => &self.attitude_topic,
+            "GLOBAL_POSITION_INT" => &self.position_topic,
+            _ => &self.topic,
+        };
+        topic
+            .replace(SYSTEM_ID_PLACEHOLDER, &system_id)
+            .replace(MESSAGE_PLACEHOLDER, message)
+    }
+
+    pub fn accepts_system(&self, system_id: u8) -> bool {
+        self.system_id.is_none_or(|expected| expected == system_id)
+    }
+}
+
+// Keep the latest vehicle pose updated from the selected source
+pub async fn vehicle_data_bridge(
+    settings: VehicleBridgeSettings,
+    latest_pose: Arc<RwLock<PoseHistory>>,
+) {
+    status::set_source(settings.source.name());
+
+    match settings.source.clone() {
+        VehicleSource::Zenoh => zenoh_client_bridge(settings, latest_pose).await,
+        VehicleSource::Mavlink(address) => {
+            mavlink_client_bridge(address, settings, latest_pose).await
+        }
+        VehicleSource::Disabled => info!("Vehicle data source disabled"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vehicle_source_from_str() {
+        assert_eq!("zenoh".parse(), Ok(VehicleSource::Zenoh));
+        assert_eq!("none".parse(), Ok(VehicleSource::Disabled));
+        assert_eq!(
+            "udpin:0.0.0.0:14550".parse(),
+            Ok(VehicleSource::Mavlink("udpin:0.0.0.0:14550".to_string()))
+        );
+        assert!("0.0.0.0:14550".parse::<VehicleSource>().is_err());
+    }
+
+    #[test]
+    fn topic_key_expressions() {
+        let mut settings = VehicleBridgeSettings::default();
+        assert_eq!(settings.key_expression("ATTITUDE"), "mavlink/**/1/ATTITUDE");
+        assert!(settings.accepts_system(7));
+
+        settings.system_id = Some(2);
+        assert_eq!(
+            settings.key_expression("GLOBAL_POSITION_INT"),
+            "mavlink/2/1/GLOBAL_POSITION_INT"
+        );
+        assert_eq!(settings.key_expression("VFR_HUD"), "mavlink/2/1/VFR_HUD");
+
+        settings.attitude_topic = "vehicle/{system_id}/attitude".to_string();
+        assert_eq!(settings.key_expression("ATTITUDE"), "vehicle/2/attitude");
+        assert!(settings.accepts_system(2));
+        assert!(!settings.accepts_system(1));
+    }
+
+    #[test]
+    fn vehicle_data_fields_are_independent() {
+        use mavlink::ardupilotmega::{SCALED_PRESSURE2_DATA, VFR_HUD_DATA};
+
+        let depth = DepthSettings {
+            surface_pressure_hpa: 1000.0,
+            water_density_kg_m3: 1000.0,
+        };
+        let mut state = VehicleDataState::new(depth);
+        let data = state
+            .update(&MavMessage::VFR_HUD(VFR_HUD_DATA {
+                heading: 270,
+                ..Default::default()
+            }))
+            .unwrap()
+            .clone();
+        assert_eq!(data.heading, Some(270.0));
+        assert_eq!(data.roll, None);
+        assert_eq!(data.time_boot_ms, None);
+        let max_age = std::time::Duration::from_secs(1);
+        assert!(!data.is_stale(VehicleDataGroup::Heading, max_age));
+        assert!(data.is_stale(VehicleDataGroup::Attitude, max_age));
+
+        let data = state
+            .update(&MavMessage::SCALED_PRESSURE2(SCALED_PRESSURE2_DATA {
+                time_boot_ms: 1000,
+                press_abs: 1098.0665,
+                ..Default::default()
+            }))
+            .unwrap()
+            .clone();
+        assert!((data.depth.unwrap() - 1.0).abs() < 0.001);
+        assert_eq!(data.time_boot_ms, Some(1000));
+        assert!(!data.is_stale(VehicleDataGroup::Depth, max_age));
+        assert!(VehicleData::default().is_stale(VehicleDataGroup::Depth, max_age));
+    }
+
+    #[test]
+    fn group_timestamps_follow_the_vehicle_clock() {
+        use mavlink::ardupilotmega::{ATTITUDE_DATA, GLOBAL_POSITION_INT_DATA};
+
+        let attitude = |time_boot_ms| {
+            MavMessage::ATTITUDE(ATTITUDE_DATA {
+                time_boot_ms,
+                ..Default::default()
+            })
+        };
+        let position = |time_boot_ms| {
+            MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+                time_boot_ms,
+                ..Default::default()
+            })
+        };
+
+        let mut state = VehicleDataState::new(DepthSettings::default());
+        state.update_at(&attitude(1000), 10_050);
+        // Received with less latency, the boot time is corrected
+        let data = state.update_at(&attitude(1100), 10_120).unwrap();
+        assert_eq!(data.timestamps.attitude, Some(10_120));
+
+        // Positions keep their own time, even when attitudes keep arriving
+        state.update_at(&position(1050), 10_200);
+        let data = state.update_at(&attitude(1200), 10_230).unwrap().clone();
+        assert_eq!(data.timestamps.position, Some(10_071));
+        assert_eq!(data.timestamps.attitude, Some(10_222));
+        assert_eq!(data.timestamp_ms, Some(10_230));
 
-        error!("Zenoh client bridge disconnected, retrying in {reconnect_delay_secs}s");
+        // After a reboot the boot time is measured again
+        state.update_at(&attitude(9000), 18_000);
+        let data = state.update_at(&attitude(500), 20_000).unwrap();
+        assert_eq!(data.timestamps.attitude, Some(20_000));
+        assert_eq!(data.time_boot_ms, Some(500));
     }
 }
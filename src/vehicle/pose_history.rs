@@ -0,0 +1,380 @@
+use std::{collections::VecDeque, time::Duration};
+
+use super::{VehicleData, VehicleDataGroup};
+
+static DEFAULT_HISTORY: Duration = Duration::from_secs(10);
+static DEFAULT_MAX_AGE: Duration = Duration::from_secs(1);
+
+// Measurements of a group of fields, with their time, ordered by time
+type Series = VecDeque<(u64, VehicleData)>;
+
+// Keep the recent vehicle poses to find the pose at the time each sonar sample was received,
+// each group of fields is interpolated from its own measurements
+#[derive(Debug, Clone)]
+pub struct PoseHistory {
+    series: [Series; VehicleDataGroup::ALL.len()],
+    latest: Option<VehicleData>,
+    history: Duration,
+    /// Measurements further than this from the requested time are flagged as stale.
+    max_age: Duration,
+}
+
+impl Default for PoseHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY, DEFAULT_MAX_AGE)
+    }
+}
+
+impl PoseHistory {
+    pub fn new(history: Duration, max_age: Duration) -> Self {
+        Self {
+            series: Default::default(),
+            latest: None,
+            history,
+            max_age,
+        }
+    }
+
+    // Add the data with the groups measured since the last one, data without receive time is ignored
+    pub fn push(&mut self, data: VehicleData) {
+        let Some(timestamp_ms) = data.timestamp_ms else {
+            return;
+        };
+
+        // The system clock went backwards, older samples can't be used anymore
+        if self
+            .latest
+            .as_ref()
+            .and_then(|latest| latest.timestamp_ms)
+            .is_some_and(|last| last > timestamp_ms)
+        {
+            self.series.iter_mut().for_each(Series::clear);
+        }
+
+        let oldest_allowed = timestamp_ms.saturating_sub(self.history.as_millis() as u64);
+        for group in VehicleDataGroup::ALL {
+            let series = &mut self.series[group as usize];
+            if let Some(time_ms) = data.timestamps.get(group) {
+                // Times come from the vehicle clock, a correction of its offset may reorder them
+                let index = series.partition_point(|(sample_ms, _)| *sample_ms < time_ms);
+                if series
+                    .get(index)
+                    .is_none_or(|(sample_ms, _)| *sample_ms != time_ms)
+                {
+                    series.insert(index, (time_ms, data.clone()));
+                }
+            }
+
+            // The last measurement is kept, so a lost source is reported as stale
+            while series.len() > 1
+                && series
+                    .front()
+                    .is_some_and(|(sample_ms, _)| *sample_ms < oldest_allowed)
+            {
+                series.pop_front();
+            }
+        }
+
+        self.latest = Some(data);
+    }
+
+    pub fn latest(&self) -> Option<&VehicleData> {
+        self.latest.as_ref()
+    }
+
+    // The pose at a time won't change anymore once every group was measured after it,
+    // or when none came within max_age
+    pub fn is_settled(&self, time_ms: u64, now_ms: u64) -> bool {
+        let measured = self.latest.is_some()
+            && self
+                .series
+                .iter()
+                .filter_map(|series| series.back())
+                .all(|(sample_ms, _)| *sample_ms >= time_ms);
+
+        measured || now_ms.saturating_sub(time_ms) > self.max_age.as_millis() as u64
+    }
+
+    // Pose at a unix time in milliseconds, each group is interpolated between its own measurements
+    // and flagged as stale when the closest one is older than max_age
+    pub fn pose_at(&self, time_ms: u64) -> Option<VehicleData> {
+        self.latest.as_ref()?;
+
+        let mut pose = VehicleData {
+            timestamp_ms: Some(time_ms),
+            ..Default::default()
+        };
+        for group in VehicleDataGroup::ALL {
+            let Some((data, value_ms, distance_ms)) = self.group_at(group, time_ms) else {
+                continue;
+            };
+            copy_group(group, &data, &mut pose);
+            pose.timestamps.set(group, value_ms);
+            if distance_ms > self.max_age.as_millis() as u64 {
+                pose.stale_groups.push(group);
+            }
+        }
+        pose.stale = !pose.stale_groups.is_empty();
+
+        Some(pose)
+    }
+
+    // Group at a time, with the time of its value and the distance to the closest measurement,
+    // interpolated values are valid at the requested time, held ones at their measurement time
+    fn group_at(&self, group: VehicleDataGroup, time_ms: u64) -> Option<(VehicleData, u64, u64)> {
+        let series = &self.series[group as usize];
+        let after_index = series.partition_point(|(sample_ms, _)| *sample_ms < time_ms);
+
+        match (
+            after_index
+                .checked_sub(1)
+                .and_then(|index| series.get(index)),
+            series.get(after_index),
+        ) {
+            (None, None) => None,
+            // Measurements are held outside of the history, never extrapolated
+            (Some((before_ms, before)), None) => {
+                Some((before.clone(), *before_ms, time_ms - before_ms))
+            }
+            (None, Some((after_ms, after))) => Some((after.clone(), *after_ms, after_ms - time_ms)),
+            (Some((before_ms, before)), Some((after_ms, after))) => {
+                let ratio = (time_ms - before_ms) as f64 / (after_ms - before_ms).max(1) as f64;
+                Some((
+                    interpolate(before, after, ratio),
+                    time_ms,
+                    (time_ms - before_ms).min(after_ms - time_ms),
+                ))
+            }
+        }
+    }
+}
+
+// Copy the fields of a group, the time since boot follows the attitude, the most frequent message
+fn copy_group(group: VehicleDataGroup, from: &VehicleData, to: &mut VehicleData) {
+    match group {
+        VehicleDataGroup::Attitude => {
+            (to.roll, to.pitch, to.yaw) = (from.roll, from.pitch, from.yaw);
+            to.time_boot_ms = from.time_boot_ms;
+        }
+        VehicleDataGroup::Position => (to.lat, to.lon, to.alt) = (from.lat, from.lon, from.alt),
+        VehicleDataGroup::Heading => to.heading = from.heading,
+        VehicleDataGroup::Depth => (to.pressure, to.depth) = (from.pressure, from.depth),
+        VehicleDataGroup::Velocity => (to.vx, to.vy, to.vz) = (from.vx, from.vy, from.vz),
+        VehicleDataGroup::Gps => {
+            to.gps_fix_type = from.gps_fix_type;
+            to.satellites_visible = from.satellites_visible;
+        }
+    }
+}
+
+// Slerp for attitude, shortest arc for heading and linear for everything else,
+// discrete values come from the closest pose
+fn interpolate(before: &VehicleData, after: &VehicleData, ratio: f64) -> VehicleData {
+    let closest = if ratio < 0.5 { before } else { after };
+
+    let attitude = match (
+        (before.roll, before.pitch, before.yaw),
+        (after.roll, after.pitch, after.yaw),
+    ) {
+        ((Some(r0), Some(p0), Some(y0)), (Some(r1), Some(p1), Some(y1))) => {
+            let from = quaternion_from_euler(r0 as f64, p0 as f64, y0 as f64);
+            let to = quaternion_from_euler(r1 as f64, p1 as f64, y1 as f64);
+            let (roll, pitch, yaw) = euler_from_quaternion(slerp(from, to, ratio));
+            (Some(roll as f32), Some(pitch as f32), Some(yaw as f32))
+        }
+        _ => (closest.roll, closest.pitch, closest.yaw),
+    };
+
+    let linear = |a: Option<f64>, b: Option<f64>| match (a, b) {
+        (Some(a), Some(b)) => Some(a + (b - a) * ratio),
+        (a, b) => {
+            if ratio < 0.5 {
+                a.or(b)
+            } else {
+                b.or(a)
+            }
+        }
+    };
+    let linear_f32 = |a: Option<f32>, b: Option<f32>| {
+        linear(a.map(f64::from), b.map(f64::from)).map(|value| value as f32)
+    };
+
+    let heading = match (before.heading, after.heading) {
+        (Some(a), Some(b)) => {
+            let difference = (b - a + 540.0).rem_euclid(360.0) - 180.0;
+            Some((a + difference * ratio as f32).rem_euclid(360.0))
+        }
+        _ => closest.heading,
+    };
+
+    VehicleData {
+        roll: attitude.0,
+        pitch: attitude.1,
+        yaw: attitude.2,
+        alt: linear(before.alt, after.alt),
+        lat: linear(before.lat, after.lat),
+        lon: linear(before.lon, after.lon),
+        heading,
+        pressure: linear_f32(before.pressure, after.pressure),
+        depth: linear_f32(before.depth, after.depth),
+        vx: linear_f32(before.vx, after.vx),
+        vy: linear_f32(before.vy, after.vy),
+        vz: linear_f32(before.vz, after.vz),
+        gps_fix_type: closest.gps_fix_type,
+        satellites_visible: closest.satellites_visible,
+        time_boot_ms: linear(
+            before.time_boot_ms.map(f64::from),
+            after.time_boot_ms.map(f64::from),
+        )
+        .map(|time| time.round() as u32),
+        ..Default::default()
+    }
+}
+
+// Quaternions are stored as [w, x, y, z], euler angles follow the MAVLink ZYX convention
+fn quaternion_from_euler(roll: f64, pitch: f64, yaw: f64) -> [f64; 4] {
+    let (sr, cr) = (roll / 2.0).sin_cos();
+    let (sp, cp) = (pitch / 2.0).sin_cos();
+    let (sy, cy) = (yaw / 2.0).sin_cos();
+    [
+        cr * cp * cy + sr * sp * sy,
+        sr * cp * cy - cr * sp * sy,
+        cr * sp * cy + sr * cp * sy,
+        cr * cp * sy - sr * sp * cy,
+    ]
+}
+
+fn euler_from_quaternion([w, x, y, z]: [f64; 4]) -> (f64, f64, f64) {
+    let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+    let pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+    let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+    (roll, pitch, yaw)
+}
+
+fn slerp(from: [f64; 4], mut to: [f64; 4], ratio: f64) -> [f64; 4] {
+    let mut dot: f64 = from.iter().zip(&to).map(|(a, b)| a * b).sum();
+    // Take the shortest path
+    if dot < 0.0 {
+        to = to.map(|value| -value);
+        dot = -dot;
+    }
+
+    let (from_weight, to_weight) = if dot > 0.9995 {
+        (1.0 - ratio, ratio)
+    } else {
+        let angle = dot.acos();
+        let sin_angle = angle.sin();
+        (
+            ((1.0 - ratio) * angle).sin() / sin_angle,
+            (ratio * angle).sin() / sin_angle,
+        )
+    };
+
+    let result: [f64; 4] = std::array::from_fn(|i| from[i] * from_weight + to[i] * to_weight);
+    let norm = result.iter().map(|value| value * value).sum::<f64>().sqrt();
+    result.map(|value| value / norm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn pose(timestamp_ms: u64, yaw: f32, lat: f64, heading: f32) -> VehicleData {
+        VehicleData {
+            roll: Some(0.0),
+            pitch: Some(0.0),
+            yaw: Some(yaw),
+            lat: Some(lat),
+            heading: Some(heading),
+            ..Default::default()
+        }
+        .measured_at(timestamp_ms)
+    }
+
+    #[test]
+    fn interpolates_between_poses() {
+        let mut history = PoseHistory::default();
+        history.push(pose(1000, 3.0, -27.0, 350.0));
+        history.push(pose(1200, -3.0, -28.0, 10.0));
+
+        let middle = history.pose_at(1100).unwrap();
+        assert_eq!(middle.timestamp_ms, Some(1100));
+        assert!((middle.lat.unwrap() + 27.5).abs() < 1e-9);
+        // Attitude and heading take the shortest way around
+        assert!((middle.yaw.unwrap().abs() - PI).abs() < 1e-3);
+        assert!(
+            middle.heading.unwrap().abs() < 1e-3 || (middle.heading.unwrap() - 360.0).abs() < 1e-3
+        );
+        assert!(!middle.stale);
+    }
+
+    #[test]
+    fn flags_stale_poses() {
+        let mut history = PoseHistory::new(Duration::from_secs(10), Duration::from_millis(500));
+        assert!(history.pose_at(1000).is_none());
+
+        history.push(pose(1000, 0.0, 0.0, 0.0));
+        assert!(!history.pose_at(1400).unwrap().stale);
+        assert!(history.pose_at(1600).unwrap().stale);
+        assert!(history.pose_at(400).unwrap().stale);
+
+        history.push(pose(3000, 1.0, 1.0, 90.0));
+        assert!(history.pose_at(2000).unwrap().stale);
+        assert!(!history.pose_at(2600).unwrap().stale);
+    }
+
+    #[test]
+    fn settles_once_a_later_pose_arrives() {
+        let mut history = PoseHistory::new(Duration::from_secs(10), Duration::from_millis(500));
+        history.push(pose(1000, 0.0, 0.0, 0.0));
+        assert!(!history.is_settled(1100, 1100));
+        assert!(history.is_settled(1100, 1700));
+
+        history.push(pose(1200, 0.0, 1.0, 0.0));
+        assert!(history.is_settled(1100, 1200));
+        assert!((history.pose_at(1100).unwrap().lat.unwrap() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn drops_old_poses() {
+        let mut history = PoseHistory::new(Duration::from_secs(1), Duration::from_secs(1));
+        history.push(pose(1000, 0.0, 0.0, 0.0));
+        history.push(pose(2500, 0.0, 1.0, 0.0));
+        assert!(history.series.iter().all(|series| series.len() <= 1));
+        assert_eq!(history.latest().unwrap().lat, Some(1.0));
+    }
+
+    #[test]
+    fn interpolates_held_position_from_its_own_measurements() {
+        let mut history = PoseHistory::new(Duration::from_secs(10), Duration::from_millis(500));
+        history.push(pose(1000, 0.0, 0.0, 0.0));
+
+        // Attitude updates keep the last position, measured at 1000
+        let mut held = pose(1000, 0.0, 0.0, 0.0);
+        for time_ms in [1200, 1400, 1600, 1800] {
+            held.yaw = Some(time_ms as f32 / 1000.0);
+            held.timestamp_ms = Some(time_ms);
+            held.timestamps.attitude = Some(time_ms);
+            history.push(held.clone());
+        }
+        history.push(pose(2000, 2.0, 1.0, 0.0));
+
+        let middle = history.pose_at(1500).unwrap();
+        assert!((middle.lat.unwrap() - 0.5).abs() < 1e-9);
+        assert!((middle.yaw.unwrap() - 1.5).abs() < 1e-3);
+        assert_eq!(middle.timestamps.position, Some(1500));
+        assert!(!middle.stale);
+
+        // Only the position is flagged once it stops being measured
+        let mut lost = pose(2000, 0.0, 1.0, 0.0);
+        lost.timestamp_ms = Some(3000);
+        lost.timestamps.attitude = Some(3000);
+        lost.timestamps.heading = Some(3000);
+        history.push(lost);
+        let late = history.pose_at(2800).unwrap();
+        assert_eq!(late.stale_groups, vec![VehicleDataGroup::Position]);
+        assert_eq!(late.timestamps.position, Some(2000));
+        assert!(late.stale);
+    }
+}
@@ -0,0 +1,62 @@
+use lazy_static::lazy_static;
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use std::{sync::RwLock, time::Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct VehicleBridgeStatus {
+    /// Selected source, zenoh, none or the MAVLink connection address.
+    pub source: String,
+    pub connected: bool,
+    /// Milliseconds since the last vehicle telemetry message was received.
+    pub last_message_age_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct BridgeState {
+    source: String,
+    connected: bool,
+    last_message: Option<Instant>,
+    last_error: Option<String>,
+}
+
+lazy_static! {
+    static ref STATE: RwLock<BridgeState> = RwLock::new(BridgeState::default());
+}
+
+// Return the current bridge state, shared with the REST API
+pub fn bridge_status() -> VehicleBridgeStatus {
+    let state = STATE.read().unwrap();
+    VehicleBridgeStatus {
+        source: state.source.clone(),
+        connected: state.connected,
+        last_message_age_ms: state
+            .last_message
+            .map(|instant| instant.elapsed().as_millis() as u64),
+        last_error: state.last_error.clone(),
+    }
+}
+
+pub(crate) fn set_source(source: String) {
+    *STATE.write().unwrap() = BridgeState {
+        source,
+        ..Default::default()
+    };
+}
+
+pub(crate) fn set_connected() {
+    let mut state = STATE.write().unwrap();
+    state.connected = true;
+    state.last_error = None;
+}
+
+pub(crate) fn set_disconnected(error: String) {
+    let mut state = STATE.write().unwrap();
+    state.connected = false;
+    state.last_error = Some(error);
+}
+
+pub(crate) fn message_received() {
+    STATE.write().unwrap().last_message = Some(Instant::now());
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use mavlink::ardupilotmega::MavMessage;
+
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::{
+    sync::{mpsc, RwLock},
+    task::JoinSet,
+};
+use tracing::{error, info, trace};
+
+use super::{
+    status, PoseHistory, VehicleBridgeSettings, VehicleDataState, ZenohSettings, VEHICLE_MESSAGES,
+};
+
+#[derive(Deserialize)]
+struct Envelope<T> {
+    message: T,
+}
+
+pub fn make_default_config(node_name: &str, settings: &ZenohSettings) -> zenoh::Config {
+    let mut config = zenoh::Config::default();
+
+    config
+        .insert_json5("mode", &format!(r#""{}""#, settings.mode.as_str()))
+        .expect("Failed to insert mode");
+    config
+        .insert_json5("metadata", &format!(r#"{{"name": "{}"}}"#, node_name))
+        .expect("Failed to insert metadata");
+    config
+        .insert_json5("adminspace/enabled", r#"true"#)
+        .expect("Failed to insert adminspace/enabled");
+    config
+        .insert_json5(
+            "connect/endpoints",
+            &serde_json::to_string(&settings.endpoints).expect("Endpoints should be serializable"),
+        )
+        .expect("Failed to insert endpoints");
+    info!("Generated zenoh config with settings: {settings:?}");
+    config
+}
+
+pub async fn zenoh_client_bridge(
+    settings: VehicleBridgeSettings,
+    latest_pose: Arc<RwLock<PoseHistory>>,
+) {
+    use tokio::time::{sleep, Duration};
+    let node_name = env!("CARGO_PKG_NAME");
+
+    let reconnect_delay_secs = 5;
+    let reconnect_delay = Duration::from_secs(reconnect_delay_secs);
+
+    loop {
+        let config = make_default_config(node_name, &settings.zenoh);
+
+        sleep(reconnect_delay).await;
+
+        let session = match zenoh::open(config).await {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Zenoh session error: {e}, retrying in {reconnect_delay_secs}s");
+                status::set_disconnected(format!("Zenoh session error: {e}"));
+                continue;
+            }
+        };
+
+        // Each subscriber forwards its samples, dropping the set stops all of them on reconnection
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut subscribers = JoinSet::new();
+        let mut subscribe_error = None;
+        for message_name in VEHICLE_MESSAGES {
+            let topic = settings.key_expression(message_name);
+            let subscriber = match session.declare_subscriber(&topic).await {
+                Ok(s) => s,
+                Err(e) => {
+                    subscribe_error = Some(format!("Zenoh subscribe error for {topic}: {e}"));
+                    break;
+                }
+            };
+            let sender = sender.clone();
+            subscribers.spawn(async move {
+                loop {
+                    let sample = subscriber
+                        .recv_async()
+                        .await
+                        .map(|sample| (message_name, sample.payload().to_bytes().to_vec()))
+                        .map_err(|e| format!("Zenoh {message_name} recv error: {e}"));
+                    let failed = sample.is_err();
+                    if sender.send(sample).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+        }
+        if let Some(e) = subscribe_error {
+            error!("{e}, retrying in {reconnect_delay_secs}s");
+            status::set_disconnected(e);
+            continue;
+        }
+        info!(
+            "Subscribed to {:?}",
+            VEHICLE_MESSAGES.map(|message_name| settings.key_expression(message_name))
+        );
+        status::set_connected();
+
+        let mut state = VehicleDataState::new(settings.depth);
+
+        while let Some(sample) = receiver.recv().await {
+            let (message_name, payload) = match sample {
+                Ok(sample) => sample,
+                Err(e) => {
+                    error!("{e}, reconnecting in {reconnect_delay_secs}s");
+                    status::set_disconnected(e);
+                    break;
+                }
+            };
+
+            let Some(message) = parse_message(message_name, &payload) else {
+                trace!("Zenoh invalid {message_name} payload ignored");
+                continue;
+            };
+
+            if let Some(data) = state.update(&message) {
+                status::message_received();
+                latest_pose.write().await.push(data.clone());
+            }
+        }
+
+        error!("Zenoh client bridge disconnected, retrying in {reconnect_delay_secs}s");
+    }
+}
+
+// Parse the JSON envelope published by mavlink-server for each message topic
+fn parse_message(message_name: &str, payload: &[u8]) -> Option<MavMessage> {
+    fn parse<T: DeserializeOwned>(payload: &[u8]) -> Option<T> {
+        serde_json5::from_slice::<Envelope<T>>(payload)
+            .ok()
+            .map(|envelope| envelope.message)
+    }
+
+    match message_name {
+        "ATTITUDE" => parse(payload).map(MavMessage::ATTITUDE),
+        "GLOBAL_POSITION_INT" => parse(payload).map(MavMessage::GLOBAL_POSITION_INT),
+        "VFR_HUD" => parse(payload).map(MavMessage::VFR_HUD),
+        "SCALED_PRESSURE2" => parse(payload).map(MavMessage::SCALED_PRESSURE2),
+        "LOCAL_POSITION_NED" => parse(payload).map(MavMessage::LOCAL_POSITION_NED),
+        "GPS_RAW_INT" => parse(payload).map(MavMessage::GPS_RAW_INT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_vfr_hud_envelope() {
+        let payload = br#"{
+            "header": {"system_id": 1, "component_id": 1, "sequence": 0},
+            "message": {"type": "VFR_HUD", "airspeed": 0.0, "groundspeed": 0.5, "alt": -3.2,
+                        "climb": 0.0, "heading": 123, "throttle": 0}
+        }"#;
+
+        let Some(MavMessage::VFR_HUD(vfr_hud)) = parse_message("VFR_HUD", payload) else {
+            panic!("VFR_HUD expected");
+        };
+        assert_eq!(vfr_hud.heading, 123);
+        assert!(parse_message("ATTITUDE", payload).is_none());
+    }
+}
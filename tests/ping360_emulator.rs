@@ -0,0 +1,338 @@
+use std::{
+    net::{Ipv4Addr, SocketAddrV4, UdpSocket},
+    process::{Child, Command},
+    time::Duration,
+};
+
+use bluerobotics_ping::{
+    message::ProtocolMessage,
+    ping360::{self, AutoDeviceDataStruct},
+};
+use ping_viewer_next::device::{
+    devices::{PingAnswer, PingRequest},
+    manager::{
+        ping360_scan::{Ping360ScanSchedule, Ping360ScanStep},
+        Answer, CreateStruct, DeviceInfo, DeviceManager, DeviceSelection, DeviceStatus,
+        ManagerActorHandler, ModifyDevice, ModifyDeviceCommand, ModifyDeviceResult, Request,
+        SourceSelection, SourceUdpStruct, UuidWrapper,
+    },
+};
+use tokio::sync::broadcast::Receiver;
+
+struct Emulator {
+    process: Child,
+    discovery_port: u16,
+}
+
+// Each emulator answers discovery on its own port, so the tests don't reach each other or real devices
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+impl Emulator {
+    fn start(ip: Ipv4Addr, data_port: u16) -> Self {
+        let discovery_port = free_udp_port();
+        let process = Command::new(env!("CARGO_BIN_EXE_ping360-emulator"))
+            .args([
+                "--ip",
+                &ip.to_string(),
+                "--discovery-port",
+                &discovery_port.to_string(),
+                "--data-port",
+                &data_port.to_string(),
+            ])
+            .spawn()
+            .expect("Failed to start the Ping360 emulator");
+
+        let emulator = Self {
+            process,
+            discovery_port,
+        };
+
+        for _ in 0..50 {
+            if emulator.discovery().is_some() {
+                return emulator;
+            }
+        }
+        panic!("Ping360 emulator is not answering discovery requests");
+    }
+
+    fn discovery(&self) -> Option<String> {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        socket
+            .send_to(b"Discovery", ("127.0.0.1", self.discovery_port))
+            .ok()?;
+
+        let mut buffer = [0u8; 1024];
+        let (size, _) = socket.recv_from(&mut buffer).ok()?;
+        Some(String::from_utf8_lossy(&buffer[..size]).to_string())
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn start_manager(emulator: &Emulator) -> ManagerActorHandler {
+    let (mut manager, handler) = DeviceManager::new(10);
+    manager.set_network_discovery_address(SocketAddrV4::new(
+        Ipv4Addr::LOCALHOST,
+        emulator.discovery_port,
+    ));
+    tokio::spawn(async move { manager.run().await });
+    handler
+}
+
+fn udp_source(ip: Ipv4Addr, port: u16) -> SourceSelection {
+    SourceSelection::UdpStream(SourceUdpStruct { ip, port })
+}
+
+async fn list(handler: &ManagerActorHandler) -> Vec<DeviceInfo> {
+    match handler.send(Request::List).await {
+        Ok(Answer::DeviceInfo(devices)) => devices,
+        answer => panic!("Unexpected list answer: {answer:?}"),
+    }
+}
+
+async fn create(handler: &ManagerActorHandler, source: SourceSelection) -> DeviceInfo {
+    let answer = handler
+        .send(Request::Create(CreateStruct {
+            source,
+            device_selection: DeviceSelection::Auto,
+        }))
+        .await;
+    match answer {
+        Ok(Answer::DeviceInfo(mut devices)) => devices.remove(0),
+        answer => panic!("Unexpected create answer: {answer:?}"),
+    }
+}
+
+async fn subscribe(
+    handler: &ManagerActorHandler,
+    device: &DeviceInfo,
+) -> Receiver<ProtocolMessage> {
+    let Ok(Answer::InnerDeviceHandler(device_handler)) = handler
+        .send(Request::GetDeviceHandler(UuidWrapper { uuid: device.id }))
+        .await
+    else {
+        panic!("Failed to get device handler");
+    };
+    match device_handler.send(PingRequest::GetSubscriber).await {
+        Ok(PingAnswer::Subscriber(subscriber)) => subscriber,
+        answer => panic!("Unexpected subscriber answer: {answer:?}"),
+    }
+}
+
+async fn next_auto_device_data(subscriber: &mut Receiver<ProtocolMessage>) -> AutoDeviceDataStruct {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let message = subscriber.recv().await.unwrap();
+            if let Ok(bluerobotics_ping::Messages::Ping360(ping360::Messages::AutoDeviceData(
+                data,
+            ))) = bluerobotics_ping::Messages::try_from(&message)
+            {
+                return data;
+            }
+        }
+    })
+    .await
+    .expect("No auto device data received")
+}
+
+#[tokio::test]
+async fn auto_create() {
+    let emulator = Emulator::start(Ipv4Addr::LOCALHOST, 12345);
+    let handler = start_manager(&emulator);
+    let source = udp_source(Ipv4Addr::LOCALHOST, 12345);
+
+    // Wait for the discovery service to find the emulator
+    tokio::time::timeout(Duration::from_secs(20), async {
+        loop {
+            let devices = list(&handler).await;
+            if devices
+                .iter()
+                .any(|device| device.source == source && device.status == DeviceStatus::Available)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("Emulator was not discovered");
+
+    let Ok(Answer::DeviceInfo(devices)) = handler.send(Request::AutoCreate).await else {
+        panic!("Auto create failed");
+    };
+    let device = devices
+        .into_iter()
+        .find(|device| device.source == source)
+        .expect("Emulator was not created");
+    assert_eq!(device.device_type, DeviceSelection::Ping360);
+    assert_eq!(device.status, DeviceStatus::ContinuousMode);
+
+    let mut subscriber = subscribe(&handler, &device).await;
+    next_auto_device_data(&mut subscriber).await;
+}
+
+#[tokio::test]
+async fn modify_device_ip() {
+    // An unconfigured board, listening on every address, moved to the loopback address
+    let emulator = Emulator::start(Ipv4Addr::UNSPECIFIED, 12346);
+    let handler = start_manager(&emulator);
+
+    let device = create(&handler, udp_source(Ipv4Addr::LOCALHOST, 12346)).await;
+    assert_eq!(device.device_type, DeviceSelection::Ping360);
+
+    let new_ip = Ipv4Addr::LOCALHOST;
+    let answer = handler
+        .send(Request::ModifyDevice(ModifyDevice {
+            uuid: device.id,
+            modify: ModifyDeviceCommand::SetIp(new_ip),
+        }))
+        .await;
+    assert!(matches!(
+        answer,
+        Ok(Answer::DeviceConfig(ModifyDeviceResult::ConfigAcknowledge(
+            _
+        )))
+    ));
+    assert!(list(&handler)
+        .await
+        .iter()
+        .all(|existing| existing.id != device.id));
+
+    // The emulator reports the new address once the command is processed
+    let mut response = None;
+    for _ in 0..50 {
+        response = emulator.discovery();
+        if response
+            .as_ref()
+            .is_some_and(|response| response.contains("IP Address:- 127.000.000.001"))
+        {
+            break;
+        }
+    }
+    assert!(
+        response.is_some_and(|response| response.contains("127.000.000.001")),
+        "Emulator did not change its IP address"
+    );
+
+    let device = create(&handler, udp_source(new_ip, 12346)).await;
+    assert_eq!(device.device_type, DeviceSelection::Ping360);
+}
+
+#[tokio::test]
+async fn firmware_continuous_mode() {
+    let emulator = Emulator::start(Ipv4Addr::LOCALHOST, 12347);
+    let handler = start_manager(&emulator);
+
+    let device = create(&handler, udp_source(Ipv4Addr::LOCALHOST, 12347)).await;
+    assert_eq!(device.device_type, DeviceSelection::Ping360);
+    assert_eq!(device.status, DeviceStatus::ContinuousMode);
+
+    let mut subscriber = subscribe(&handler, &device).await;
+    let first = next_auto_device_data(&mut subscriber).await;
+    let second = next_auto_device_data(&mut subscriber).await;
+    assert_ne!(first.angle, second.angle);
+
+    let Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping360Config(mut config))) = handler
+        .send(Request::ModifyDevice(ModifyDevice {
+            uuid: device.id,
+            modify: ModifyDeviceCommand::GetPing360Config,
+        }))
+        .await
+    else {
+        panic!("Failed to get Ping360 config");
+    };
+
+    // Changing the configuration restarts the firmware auto transmit with the new settings
+    config.number_of_samples = 600;
+    handler
+        .send(Request::ModifyDevice(ModifyDevice {
+            uuid: device.id,
+            modify: ModifyDeviceCommand::SetPing360Config(config),
+        }))
+        .await
+        .expect("Failed to set Ping360 config");
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let data = next_auto_device_data(&mut subscriber).await;
+            if data.number_of_samples == 600 {
+                assert_eq!(data.data.len(), 600);
+                break;
+            }
+        }
+    })
+    .await
+    .expect("Auto transmit did not restart with the new configuration");
+}
+
+#[tokio::test]
+async fn firmware_scan_schedule() {
+    let emulator = Emulator::start(Ipv4Addr::LOCALHOST, 12348);
+    let handler = start_manager(&emulator);
+
+    let device = create(&handler, udp_source(Ipv4Addr::LOCALHOST, 12348)).await;
+    let mut subscriber = subscribe(&handler, &device).await;
+
+    let schedule = Ping360ScanSchedule {
+        pattern: vec![
+            Ping360ScanStep {
+                profile: "narrow_track".to_string(),
+                sweeps: 3,
+            },
+            Ping360ScanStep {
+                profile: "full_circle".to_string(),
+                sweeps: 1,
+            },
+        ],
+        ..Default::default()
+    };
+    handler
+        .send(Request::ModifyDevice(ModifyDevice {
+            uuid: device.id,
+            modify: ModifyDeviceCommand::SetPing360ScanSchedule(schedule.clone()),
+        }))
+        .await
+        .expect("Failed to set Ping360 scan schedule");
+
+    let Ok(Answer::DeviceConfig(ModifyDeviceResult::Ping360ScanSchedule(stored))) = handler
+        .send(Request::ModifyDevice(ModifyDevice {
+            uuid: device.id,
+            modify: ModifyDeviceCommand::GetPing360ScanSchedule,
+        }))
+        .await
+    else {
+        panic!("Failed to get Ping360 scan schedule");
+    };
+    assert_eq!(stored, schedule);
+
+    // The full circle is scanned after the narrow track, which is scanned again afterwards
+    let in_narrow_track = |angle: u16| !(21..380).contains(&angle);
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while in_narrow_track(next_auto_device_data(&mut subscriber).await.angle) {}
+
+        let mut consecutive = 0;
+        while consecutive < 80 {
+            if in_narrow_track(next_auto_device_data(&mut subscriber).await.angle) {
+                consecutive += 1;
+            } else {
+                consecutive = 0;
+            }
+        }
+    })
+    .await
+    .expect("Scan schedule did not alternate between profiles");
+}